@@ -0,0 +1,148 @@
+use clap::{Arg, ArgMatches, Command};
+use std::error::Error;
+use std::io::{IsTerminal, Write};
+use std::sync::Arc;
+
+use crate::core::{
+    llm_manager::LLMManager, sentence_player::play_sentences, voice_manager::VoiceManager,
+};
+use crate::paths::schema_config;
+use crate::utils::audio_player::AudioPlayer;
+use crate::utils::{markdown, schema_handler::SchemaHandler, text};
+use crate::SETTINGS;
+
+use super::{init_voice, voice_args, VoiceOptions};
+
+pub fn command() -> Command {
+    Command::new("chat")
+        .about("Chat with the language model set up in the GUI and hear its replies")
+        .long_about(
+            "Sends a single prompt, or every line read from stdin, to the active LLM provider \
+                    and prints the replies while reading them aloud.",
+        )
+        .arg(
+            Arg::new("prompt")
+                .help("Prompt to send, starts a conversation on stdin when missing")
+                .value_name("PROMPT"),
+        )
+        .args(voice_args())
+        .arg(
+            Arg::new("language")
+                .short('l')
+                .long("language")
+                .help("Language to reply in (defaults to the language of the voice)")
+                .value_name("LANGUAGE"),
+        )
+        .arg(
+            Arg::new("mute")
+                .long("mute")
+                .help("Only print the replies")
+                .action(clap::ArgAction::SetTrue),
+        )
+}
+
+pub async fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    // Providers and their keys are kept in the GUI settings
+    if !SchemaHandler::schema_exists(
+        &schema_config::get_schema_url(),
+        &SchemaHandler::get_schemas_dir(),
+    ) {
+        SchemaHandler::install_from_url().await?;
+    }
+
+    let voice = if matches.get_flag("mute") {
+        None
+    } else {
+        Some(init_voice(matches).await?)
+    };
+    let language = match matches.get_one::<String>("language") {
+        Some(language) => language.clone(),
+        None => voice_language(matches.get_one::<String>("voice").unwrap()),
+    };
+    let chat = Chat {
+        llm_manager: LLMManager::default(),
+        language,
+        player: Arc::new(AudioPlayer::default()),
+        voice,
+    };
+    if let Some(voice) = &chat.voice {
+        chat.player.set_volume(voice.volume);
+        chat.player.set_pitch(voice.effects.pitch);
+        chat.player.set_normalize(voice.effects.normalize);
+    }
+
+    if let Some(prompt) = matches.get_one::<String>("prompt") {
+        return chat.reply(prompt).await;
+    }
+
+    let interactive = std::io::stdin().is_terminal();
+    if interactive {
+        println!(
+            "Chatting with {}, an empty line or Ctrl+D ends the conversation",
+            SETTINGS.get_active_provider()
+        );
+    }
+
+    loop {
+        if interactive {
+            print!("> ");
+            std::io::stdout().flush()?;
+        }
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let prompt = line.trim();
+        if prompt.is_empty() {
+            if interactive {
+                break;
+            }
+            continue;
+        }
+        chat.reply(prompt).await?;
+    }
+    Ok(())
+}
+
+// The language part of the voice's locale, like the GUI chat picks it
+fn voice_language(voice_style: &str) -> String {
+    VoiceManager::get_voice_rows()
+        .into_iter()
+        .find(|voice| voice.key == voice_style)
+        .and_then(|voice| voice.language.code.split('-').next().map(str::to_string))
+        .unwrap_or_else(|| "en".to_string())
+}
+
+struct Chat {
+    llm_manager: LLMManager,
+    language: String,
+    player: Arc<AudioPlayer>,
+    voice: Option<VoiceOptions>,
+}
+
+impl Chat {
+    async fn reply(&self, prompt: &str) -> Result<(), Box<dyn Error>> {
+        let response = self
+            .llm_manager
+            .send_to_llm(prompt, &self.language)
+            .await
+            .map_err(|e| format!("Error: LLM response failed: {}", e))?;
+        println!("{}", response);
+
+        let Some(voice) = &self.voice else {
+            return Ok(());
+        };
+        let sentences =
+            text::split_text_into_sentences(&markdown::strip_markdown_for_tts(&response));
+        play_sentences(
+            tokio_stream::iter(sentences),
+            &voice.voice_style,
+            voice.speed,
+            self.player.clone(),
+            || false,
+        )
+        .await
+        .map_err(|e| format!("Error: Failed to play audio: {}", e).into())
+    }
+}
@@ -0,0 +1,81 @@
+use clap::{Arg, ArgMatches, Command};
+use std::error::Error;
+use std::path::PathBuf;
+
+use crate::core::daemon::{self, DaemonRequest, DaemonResponse};
+use crate::paths::daemon_config;
+use crate::utils::audio_player::AudioOutput;
+
+use super::init_engines;
+
+pub fn command() -> Command {
+    Command::new("daemon")
+        .about("Keep the speech engine loaded in the background and control it")
+        .long_about(
+            "Starts a daemon that keeps the voices loaded and reads text sent over a Unix socket, \
+                    so speak calls start reading right away. The other subcommands control a running daemon.",
+        )
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("start")
+                .about("Run the daemon in the foreground until shutdown, Ctrl+C or SIGTERM")
+                .arg(
+                    Arg::new("audio-output")
+                        .long("audio-output")
+                        .help("Where to play audio: device, null, null-instant or wav:<path>")
+                        .value_name("AUDIO_OUTPUT")
+                        .default_value("device")
+                        .value_parser(|s: &str| s.parse::<AudioOutput>()),
+                ),
+        )
+        .subcommand(Command::new("status").about("Show whether the daemon is reading"))
+        .subcommand(Command::new("stop").about("Stop reading and drop the queued texts"))
+        .subcommand(Command::new("pause").about("Pause reading"))
+        .subcommand(Command::new("resume").about("Resume paused reading"))
+        .subcommand(Command::new("shutdown").about("Stop the daemon"))
+}
+
+pub fn socket_path() -> PathBuf {
+    PathBuf::from(daemon_config::get_socket_path())
+}
+
+pub async fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let request = match matches.subcommand() {
+        Some(("start", matches)) => {
+            let output = matches.get_one::<AudioOutput>("audio-output").unwrap();
+            init_engines().await?;
+            println!("Listening on {}", socket_path().display());
+            return daemon::serve(&socket_path(), output.clone())
+                .await
+                .map_err(|e| format!("Error: Daemon failed: {}", e).into());
+        }
+        Some(("status", _)) => DaemonRequest::Status,
+        Some(("stop", _)) => DaemonRequest::Stop,
+        Some(("pause", _)) => DaemonRequest::Pause,
+        Some(("resume", _)) => DaemonRequest::Resume,
+        Some(("shutdown", _)) => DaemonRequest::Shutdown,
+        _ => return Err("Error: Unknown daemon subcommand".into()),
+    };
+
+    if !daemon::is_running(&socket_path()).await {
+        return Err("Error: No daemon is running, start one with `fox-reader daemon start`".into());
+    }
+    let response = daemon::send_request(&socket_path(), &request)
+        .await
+        .map_err(|e| format!("Error: {}", e))?;
+    if request == DaemonRequest::Status {
+        print_status(&response);
+    }
+    Ok(())
+}
+
+fn print_status(response: &DaemonResponse) {
+    let state = if response.paused {
+        "paused"
+    } else if response.playing {
+        "reading"
+    } else {
+        "idle"
+    };
+    println!("{} ({} texts pending)", state, response.pending);
+}
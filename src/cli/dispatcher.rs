@@ -0,0 +1,158 @@
+use clap::{Arg, ArgMatches, Command};
+use std::error::Error;
+
+use tokio::io::BufReader;
+
+use crate::core::{
+    dispatcher_module::{self, ModuleConfig},
+    speech_dispatcher::SpeechDispatcher,
+    voice_manager::{VoiceFilter, VoiceManager},
+};
+use crate::paths::dispatcher_config;
+use crate::utils::{audio_player::AudioOutput, espeak_handler::EspeakHandler};
+
+pub fn command() -> Command {
+    Command::new("dispatcher")
+        .about("Set up Fox Reader as a Speech Dispatcher module")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("install")
+                .about("Add the module to the Speech Dispatcher config, keeping the rest of it")
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Rewrite the module files, like after moving the fox-reader binary")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("uninstall")
+                .about("Take Fox Reader out of the Speech Dispatcher config and remove its module"),
+        )
+        .subcommand(
+            Command::new("set-voice")
+                .about("Set the voice Speech Dispatcher uses by default, or for a language")
+                .arg(
+                    Arg::new("voice")
+                        .help("Voice style (see the voices subcommand)")
+                        .value_name("VOICE_STYLE")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("language")
+                        .short('l')
+                        .long("language")
+                        .help("Only read this language with the voice, like fr or en-GB")
+                        .value_name("LANGUAGE"),
+                ),
+        )
+        .subcommand(
+            Command::new("module")
+                .about("Run as the Speech Dispatcher output module, talking its protocol on stdin and stdout")
+                .hide(true)
+                .arg(
+                    Arg::new("config")
+                        .help("Module config Speech Dispatcher passes")
+                        .value_name("CONFIG_PATH"),
+                ),
+        )
+}
+
+pub async fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("install", matches)) => {
+            let installed = if matches.get_flag("force") {
+                SpeechDispatcher::reinstall()
+            } else {
+                SpeechDispatcher::init()
+            };
+            installed
+                .map_err(|e| format!("Error: Failed to install Speech Dispatcher files: {}", e))?;
+            println!(
+                "Speech Dispatcher module installed to: {}",
+                dispatcher_config::get_module_config_path()
+            );
+            Ok(())
+        }
+        Some(("uninstall", _)) => {
+            SpeechDispatcher::uninstall().map_err(|e| {
+                format!("Error: Failed to uninstall Speech Dispatcher files: {}", e)
+            })?;
+            println!("Speech Dispatcher module removed");
+            Ok(())
+        }
+        Some(("set-voice", matches)) => {
+            let voice_style = matches.get_one::<String>("voice").unwrap();
+            let voices = VoiceManager::get_voice_rows();
+            let Some(voice) = voices.iter().find(|voice| voice.key == *voice_style) else {
+                let err_msg = format!(
+                    "Error: Invalid voice style '{}'. Use the voices subcommand to see available options.",
+                    voice_style
+                );
+                return Err(err_msg.into());
+            };
+            let language = matches.get_one::<String>("language");
+            // Still set, a voice can read another language, just with its own accent
+            if let Some(language) = language {
+                let filter = VoiceFilter {
+                    language: Some(language.clone()),
+                    ..Default::default()
+                };
+                if !filter.matches(voice) {
+                    eprintln!(
+                        "Warning: {} speaks {}, not {}",
+                        voice_style, voice.language.code, language
+                    );
+                }
+            }
+            // The voice is set in the module config, which may not be written yet
+            SpeechDispatcher::init()
+                .and_then(|_| match language {
+                    Some(language) => SpeechDispatcher::set_language_voice(language, voice_style),
+                    None => SpeechDispatcher::set_default_voice(voice_style),
+                })
+                .map_err(|e| format!("Error: Failed to set default voice: {}", e))?;
+            match language {
+                Some(language) => {
+                    println!(
+                        "Speech Dispatcher now reads {} with: {}",
+                        language, voice_style
+                    )
+                }
+                None => println!("Speech Dispatcher now reads with: {}", voice_style),
+            }
+            Ok(())
+        }
+        Some(("module", matches)) => run_module(matches).await,
+        _ => Err("Error: Unknown dispatcher subcommand".into()),
+    }
+}
+
+// Stdout belongs to the protocol, everything else goes to stderr, which Speech Dispatcher logs
+async fn run_module(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let config_path = matches
+        .get_one::<String>("config")
+        .cloned()
+        .unwrap_or_else(dispatcher_config::get_module_config_path);
+    let config = ModuleConfig {
+        default_voice: SpeechDispatcher::default_voice(&config_path)
+            .unwrap_or_else(|| "af_heart".to_string()),
+        voices: SpeechDispatcher::configured_voices(&config_path),
+        language_voices: SpeechDispatcher::language_voices(&config_path),
+    };
+
+    // Nothing is downloaded here, INIT reports missing voices back to Speech Dispatcher
+    EspeakHandler::set_espeak_environment();
+    if let Err(e) = VoiceManager::init_kokoros().await {
+        eprintln!("Failed to initialize Kokoros TTS: {}", e);
+    }
+
+    dispatcher_module::run_module(
+        BufReader::new(tokio::io::stdin()),
+        tokio::io::stdout(),
+        AudioOutput::Device,
+        config,
+    )
+    .await
+    .map_err(|e| format!("Error: Speech Dispatcher module failed: {}", e).into())
+}
@@ -0,0 +1,289 @@
+use clap::{Arg, ArgMatches, Command};
+use clap_complete::Shell;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use crate::core::voice_manager::VoiceManager;
+use crate::paths::DISPATCHER_MODULE_BINARY;
+use crate::utils::audio_effects::{AudioEffects, MAX_PITCH_SHIFT, MAX_VOLUME, MIN_VOLUME};
+use crate::utils::espeak_handler::EspeakHandler;
+
+mod chat;
+mod daemon;
+mod dispatcher;
+mod models;
+mod presets;
+mod serve;
+mod speak;
+mod transcribe;
+
+const BIN_NAME: &str = "fox-reader";
+
+// Without a subcommand the GUI is started
+pub fn command() -> Command {
+    Command::new(BIN_NAME)
+        .about("A text-to-speech application with GUI and CLI modes")
+        .long_about(
+            "Fox Reader can synthesize speech from text using various voice styles. \
+                    Run without a subcommand for GUI mode.",
+        )
+        .arg(
+            Arg::new("keybindings")
+                .short('k')
+                .long("keybindings")
+                .help("Show the keyboard shortcuts of the GUI")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .subcommand(speak::speak_command())
+        .subcommand(speak::export_command())
+        .subcommand(models::voices_command())
+        .subcommand(models::models_command())
+        .subcommand(presets::command())
+        .subcommand(chat::command())
+        .subcommand(transcribe::command())
+        .subcommand(serve::command())
+        .subcommand(daemon::command())
+        .subcommand(dispatcher::command())
+        .subcommand(
+            Command::new("completions")
+                .about("Print a shell completion script")
+                .arg(
+                    Arg::new("shell")
+                        .help("Shell to complete for")
+                        .value_name("SHELL")
+                        .required(true)
+                        .value_parser(clap::value_parser!(Shell)),
+                ),
+        )
+}
+
+pub async fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("speak", matches)) => speak::run_speak(matches).await,
+        Some(("export", matches)) => speak::run_export(matches).await,
+        Some(("voices", matches)) => models::run_voices(matches),
+        Some(("models", matches)) => models::run_models(matches),
+        Some(("preset", matches)) => presets::run(matches),
+        Some(("chat", matches)) => chat::run(matches).await,
+        Some(("transcribe", matches)) => transcribe::run(matches).await,
+        Some(("serve", matches)) => serve::run(matches).await,
+        Some(("daemon", matches)) => daemon::run(matches).await,
+        Some(("dispatcher", matches)) => dispatcher::run(matches).await,
+        Some(("completions", matches)) => {
+            let shell = *matches.get_one::<Shell>("shell").unwrap();
+            clap_complete::generate(shell, &mut command(), BIN_NAME, &mut std::io::stdout());
+            Ok(())
+        }
+        _ => Err("Error: Unknown subcommand".into()),
+    }
+}
+
+// Translates the flat `--cli` flags from before subcommands, which speech dispatcher
+// scripts installed by older versions still pass
+pub fn legacy_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut args = args.into_iter().collect::<Vec<_>>();
+    // Speech Dispatcher starts the module through a link named after it
+    if args.first().is_some_and(|arg0| {
+        Path::new(arg0).file_name() == Some(OsStr::new(DISPATCHER_MODULE_BINARY))
+    }) {
+        let module_args = [BIN_NAME, "dispatcher", "module"].map(str::to_string);
+        return module_args
+            .into_iter()
+            .chain(args.into_iter().skip(1))
+            .collect();
+    }
+    let Some(cli_flag) = args.iter().position(|arg| arg == "--cli") else {
+        return args;
+    };
+    args.remove(cli_flag);
+
+    if args.iter().any(|arg| arg == "--list-voices") {
+        return vec![args.swap_remove(0), "voices".to_string()];
+    }
+
+    let subcommand = match args.iter().position(|arg| arg == "--export") {
+        Some(export_flag) => {
+            args[export_flag] = "--output".to_string();
+            "export"
+        }
+        None => "speak",
+    };
+    args.insert(1.min(args.len()), subcommand.to_string());
+    args
+}
+
+// Voice flags of every subcommand that speaks
+fn voice_args() -> [Arg; 5] {
+    [
+        Arg::new("voice")
+            .short('v')
+            .long("voice")
+            .help("Voice style to use for speech synthesis (see the voices subcommand)")
+            .value_name("VOICE_STYLE")
+            .default_value("af_heart"),
+        Arg::new("speed")
+            .short('s')
+            .long("speed")
+            .help("Speech speed (0.5-2.0)")
+            .value_name("SPEED")
+            .default_value("1.0")
+            .value_parser(clap::value_parser!(f32)),
+        Arg::new("volume")
+            .long("volume")
+            .help("Playback volume (0.0-2.0)")
+            .value_name("VOLUME")
+            .default_value("1.0")
+            .value_parser(clap::value_parser!(f32)),
+        Arg::new("pitch")
+            .long("pitch")
+            .help("Pitch shift in semitones (-12 to 12)")
+            .value_name("SEMITONES")
+            .default_value("0.0")
+            .allow_hyphen_values(true)
+            .value_parser(clap::value_parser!(f32)),
+        Arg::new("normalize")
+            .long("normalize")
+            .help("Normalize the loudness of the generated speech")
+            .action(clap::ArgAction::SetTrue),
+    ]
+}
+
+struct VoiceOptions {
+    voice_style: String,
+    speed: f32,
+    volume: f32,
+    effects: AudioEffects,
+}
+
+// Checks the voice flags and gets the speech engine ready to use them
+async fn init_voice(matches: &ArgMatches) -> Result<VoiceOptions, Box<dyn Error>> {
+    let voice_style = matches.get_one::<String>("voice").unwrap().clone();
+    let speed = *matches.get_one::<f32>("speed").unwrap();
+    let volume = *matches.get_one::<f32>("volume").unwrap();
+    let effects = AudioEffects {
+        pitch: *matches.get_one::<f32>("pitch").unwrap(),
+        normalize: matches.get_flag("normalize"),
+    };
+
+    if !(0.5..=2.0).contains(&speed) {
+        let err_msg = "Error: Speed must be between 0.5 and 2.0";
+        return Err(err_msg.into());
+    }
+
+    if !(MIN_VOLUME..=MAX_VOLUME).contains(&volume) {
+        let err_msg = format!(
+            "Error: Volume must be between {} and {}",
+            MIN_VOLUME, MAX_VOLUME
+        );
+        return Err(err_msg.into());
+    }
+
+    if effects.pitch.abs() > MAX_PITCH_SHIFT {
+        let err_msg = format!(
+            "Error: Pitch must be between -{} and {}",
+            MAX_PITCH_SHIFT, MAX_PITCH_SHIFT
+        );
+        return Err(err_msg.into());
+    }
+
+    init_engines().await?;
+
+    if !VoiceManager::get_voices().contains(&voice_style) {
+        let err_msg = format!(
+            "Error: Invalid voice style '{}'. Use the voices subcommand to see available options.",
+            voice_style
+        );
+        return Err(err_msg.into());
+    }
+
+    Ok(VoiceOptions {
+        voice_style,
+        speed,
+        volume,
+        effects,
+    })
+}
+
+async fn init_engines() -> Result<(), Box<dyn Error>> {
+    if !EspeakHandler::is_espeak_installed() {
+        EspeakHandler::download_with_progress_cli().await?;
+    }
+
+    EspeakHandler::set_espeak_environment();
+
+    VoiceManager::init_kokoros()
+        .await
+        .map_err(|e| format!("Failed to initialize Kokoros TTS: {}", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_command_is_valid() {
+        command().debug_assert();
+    }
+
+    #[test]
+    fn test_legacy_cli_flags() {
+        assert_eq!(
+            legacy_args(args(&[
+                "fox-reader",
+                "--cli",
+                "--voice",
+                "af_sky",
+                "-t",
+                "Hi"
+            ])),
+            args(&["fox-reader", "speak", "--voice", "af_sky", "-t", "Hi"])
+        );
+        assert_eq!(
+            legacy_args(args(&[
+                "fox-reader",
+                "-i",
+                "book.pdf",
+                "--cli",
+                "--export",
+                "b.wav"
+            ])),
+            args(&[
+                "fox-reader",
+                "export",
+                "-i",
+                "book.pdf",
+                "--output",
+                "b.wav"
+            ])
+        );
+        assert_eq!(
+            legacy_args(args(&["fox-reader", "--cli", "--list-voices"])),
+            args(&["fox-reader", "voices"])
+        );
+        assert_eq!(
+            legacy_args(args(&["fox-reader", "speak", "-t", "Hi"])),
+            args(&["fox-reader", "speak", "-t", "Hi"])
+        );
+
+        assert_eq!(
+            legacy_args(args(&[
+                "/home/fox/.local/share/fox-reader/sd_fox-reader",
+                "fox-reader.conf"
+            ])),
+            args(&["fox-reader", "dispatcher", "module", "fox-reader.conf"])
+        );
+
+        let matches = command()
+            .try_get_matches_from(legacy_args(args(&["fox-reader", "--cli", "-t", "Hi"])))
+            .unwrap();
+        let (name, speak) = matches.subcommand().unwrap();
+        assert_eq!(name, "speak");
+        assert_eq!(speak.get_one::<String>("text").unwrap(), "Hi");
+    }
+}
@@ -0,0 +1,140 @@
+use clap::{Arg, ArgMatches, Command};
+use serde::Serialize;
+use std::error::Error;
+
+use crate::core::voice_manager::{grade_rank, VoiceFilter, VoiceManager};
+use crate::paths::{voice_config, whisper_config};
+use crate::utils::kokoros_downloader::KokorosDownloader;
+use crate::utils::whisper_downloader;
+
+#[derive(Debug, Serialize)]
+struct ModelInfo {
+    name: String,
+    // What the model is used for, speech synthesis or transcription
+    kind: &'static str,
+    downloaded: bool,
+    path: String,
+}
+
+pub fn voices_command() -> Command {
+    Command::new("voices")
+        .about("List the available voice styles, with their language, quality grade and traits")
+        .arg(
+            Arg::new("language")
+                .short('l')
+                .long("language")
+                .help("Only list voices of a locale like en-GB, or of every locale of a language like en")
+                .value_name("LANGUAGE"),
+        )
+        .arg(
+            Arg::new("gender")
+                .long("gender")
+                .help("Only list female (f) or male (m) voices")
+                .value_name("GENDER")
+                .value_parser(["f", "m"]),
+        )
+        .arg(
+            Arg::new("min-grade")
+                .long("min-grade")
+                .help("Only list voices graded this well or better, like B- or C")
+                .value_name("GRADE")
+                .value_parser(|s: &str| match grade_rank(s) {
+                    Some(_) => Ok(s.to_uppercase()),
+                    None => Err(format!("Invalid grade '{}', expected A to F with an optional + or -", s)),
+                }),
+        )
+        .arg(json_arg())
+}
+
+pub fn models_command() -> Command {
+    Command::new("models")
+        .about("List the speech and transcription models and whether they are downloaded")
+        .arg(json_arg())
+}
+
+fn json_arg() -> Arg {
+    Arg::new("json")
+        .long("json")
+        .help("Print as JSON")
+        .action(clap::ArgAction::SetTrue)
+}
+
+pub fn run_voices(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let filter = VoiceFilter {
+        language: matches.get_one::<String>("language").cloned(),
+        gender: matches
+            .get_one::<String>("gender")
+            .and_then(|gender| gender.chars().next()),
+        min_grade: matches.get_one::<String>("min-grade").cloned(),
+    };
+    let voices = VoiceManager::get_voice_rows()
+        .into_iter()
+        .filter(|voice| filter.matches(voice))
+        .collect::<Vec<_>>();
+
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&voices)?);
+        return Ok(());
+    }
+
+    if voices.is_empty() {
+        return Err("Error: No voice matches the given filters".into());
+    }
+    println!(
+        "{:<14} {:<12} {:<7} {:<15} {:<6} TRAITS",
+        "KEY", "NAME", "LOCALE", "REGION", "GRADE"
+    );
+    for voice in voices {
+        println!(
+            "{:<14} {:<12} {:<7} {:<15} {:<6} {}",
+            voice.key,
+            voice.name,
+            voice.language.code,
+            voice.language.region,
+            voice.quality,
+            voice.traits
+        );
+    }
+    Ok(())
+}
+
+pub fn run_models(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let models = list_models();
+    if matches.get_flag("json") {
+        println!("{}", serde_json::to_string_pretty(&models)?);
+        return Ok(());
+    }
+
+    for (kind, title) in [
+        ("speech", "Speech models:"),
+        ("transcription", "\nWhisper models:"),
+    ] {
+        println!("{}", title);
+        for model in models.iter().filter(|m| m.kind == kind) {
+            let state = if model.downloaded { "downloaded" } else { "" };
+            println!("  {:<22} {}", model.name, state);
+        }
+    }
+    Ok(())
+}
+
+fn list_models() -> Vec<ModelInfo> {
+    let kokoros = ModelInfo {
+        name: "kokoro".to_string(),
+        kind: "speech",
+        downloaded: KokorosDownloader::are_files_available(),
+        path: voice_config::get_kokoros_model_path(),
+    };
+
+    let downloaded = whisper_downloader::get_downloaded_models();
+    let whisper = whisper_config::get_whisper_models_names()
+        .into_iter()
+        .map(|name| ModelInfo {
+            name: name.to_string(),
+            kind: "transcription",
+            downloaded: downloaded.iter().any(|d| d == name),
+            path: whisper_config::get_model_path(name),
+        });
+
+    std::iter::once(kokoros).chain(whisper).collect()
+}
@@ -0,0 +1,119 @@
+use clap::{Arg, ArgMatches, Command};
+use std::error::Error;
+
+use crate::core::voice_presets::{PresetStyle, VoicePreset, VoicePresets};
+
+pub fn command() -> Command {
+    Command::new("preset")
+        .about("Manage voice presets, which blend voice styles into a new voice")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("add")
+                .about("Save a preset, replacing the one with the same name")
+                .arg(
+                    Arg::new("name")
+                        .help("Name of the preset, its key is used like a voice style")
+                        .value_name("NAME")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("styles")
+                        .help(
+                            "Voice styles to blend with their weights, like af_heart:60 bf_emma:40",
+                        )
+                        .value_name("STYLE:WEIGHT")
+                        .required(true)
+                        .num_args(1..)
+                        .value_parser(parse_style),
+                )
+                .arg(
+                    Arg::new("speed")
+                        .short('s')
+                        .long("speed")
+                        .help("Speed of the preset relative to the speed asked for (0.5 - 2.0)")
+                        .value_name("SPEED")
+                        .default_value("1.0")
+                        .value_parser(clap::value_parser!(f32)),
+                ),
+        )
+        .subcommand(Command::new("list").about("List the presets with their styles"))
+        .subcommand(
+            Command::new("remove").about("Remove a preset").arg(
+                Arg::new("preset")
+                    .help("Key or name of the preset")
+                    .value_name("PRESET")
+                    .required(true),
+            ),
+        )
+}
+
+// A style alone counts with a weight of 1
+fn parse_style(s: &str) -> Result<PresetStyle, String> {
+    let (voice, weight) = match s.split_once(':') {
+        Some((voice, weight)) => {
+            let weight = weight
+                .parse::<f32>()
+                .map_err(|_| format!("Invalid weight '{}' of '{}'", weight, voice))?;
+            (voice, weight)
+        }
+        None => (s, 1.0),
+    };
+    Ok(PresetStyle {
+        voice: voice.to_string(),
+        weight,
+    })
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    match matches.subcommand() {
+        Some(("add", matches)) => {
+            let preset = VoicePreset {
+                name: matches.get_one::<String>("name").unwrap().clone(),
+                styles: matches
+                    .get_many::<PresetStyle>("styles")
+                    .unwrap()
+                    .cloned()
+                    .collect(),
+                speed: *matches.get_one::<f32>("speed").unwrap(),
+            };
+            let key = preset.key();
+            VoicePresets::save(preset).map_err(|e| format!("Error: {}", e))?;
+            println!("Preset saved, read with it using the voice style: {}", key);
+            Ok(())
+        }
+        Some(("list", _)) => {
+            let presets = VoicePresets::load();
+            if presets.is_empty() {
+                println!("No presets yet, add one with `fox-reader preset add`");
+            }
+            for preset in presets {
+                println!(
+                    "{:<24} {:<5} {}",
+                    preset.key(),
+                    preset.speed,
+                    preset.kokoros_style()
+                );
+            }
+            Ok(())
+        }
+        Some(("remove", matches)) => {
+            let preset = matches.get_one::<String>("preset").unwrap();
+            // Names are turned into keys the same way presets are saved
+            let key = match VoicePresets::find(preset) {
+                Some(_) => preset.clone(),
+                None => VoicePreset {
+                    name: preset.clone(),
+                    styles: Vec::new(),
+                    speed: 1.0,
+                }
+                .key(),
+            };
+            if !VoicePresets::remove(&key).map_err(|e| format!("Error: {}", e))? {
+                return Err(format!("Error: No preset named '{}'", preset).into());
+            }
+            println!("Preset removed: {}", key);
+            Ok(())
+        }
+        _ => Err("Error: Unknown preset subcommand".into()),
+    }
+}
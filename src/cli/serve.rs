@@ -0,0 +1,43 @@
+use clap::{Arg, ArgMatches, Command};
+use std::error::Error;
+use std::net::Ipv4Addr;
+use tokio::net::TcpListener;
+
+use crate::core::speech_server;
+
+use super::init_engines;
+
+// Port Kokoro-FastAPI listens on, which OpenAI speech clients are often set up for already
+const DEFAULT_PORT: &str = "8880";
+
+pub fn command() -> Command {
+    Command::new("serve")
+        .about("Serve the voices over an OpenAI compatible speech API on localhost")
+        .long_about(
+            "Serves POST /v1/audio/speech, taking the JSON body of the OpenAI speech API, \
+                    and GET /v1/voices on 127.0.0.1 until Ctrl+C.",
+        )
+        .arg(
+            Arg::new("port")
+                .short('p')
+                .long("port")
+                .help("Port to listen on")
+                .value_name("PORT")
+                .default_value(DEFAULT_PORT)
+                .value_parser(clap::value_parser!(u16)),
+        )
+}
+
+pub async fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let port = *matches.get_one::<u16>("port").unwrap();
+    init_engines().await?;
+
+    // Only local tools should reach the voices, there is no authentication
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .await
+        .map_err(|e| format!("Error: Failed to listen on port {}: {}", port, e))?;
+    println!("Serving speech on http://{}", listener.local_addr()?);
+    speech_server::serve(listener)
+        .await
+        .map_err(|e| format!("Error: Server failed: {}", e).into())
+}
@@ -0,0 +1,724 @@
+use clap::{parser::ValueSource, Arg, ArgMatches, Command};
+use std::error::Error;
+use std::future::Future;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::core::audiobook::{AudiobookExporter, Chapter};
+use crate::core::daemon::{self as core_daemon, DaemonRequest, SpeechRequest};
+use crate::core::sentence_player::play_sentences;
+use crate::core::speech_engine::EngineResult;
+use crate::core::voice_manager::VoiceManager;
+use crate::output::{
+    self,
+    subtitles::{self, SubtitleCue, SubtitleFormat},
+    AudioMetadata, ExportFormat,
+};
+use crate::utils::audio_effects::AudioEffects;
+use crate::utils::audio_player::{AudioOutput, AudioPlayer};
+use crate::utils::file_handler::FileHandler;
+use crate::utils::markdown;
+use crate::utils::pdf_highlighter::PdfHighlighter;
+use crate::utils::pdfium::{PageRange, PdfiumWrapper};
+use crate::utils::progress_tracker::ProgressTracker;
+use crate::utils::text;
+use pdfium_render::prelude::PdfDocumentMetadataTagType;
+
+use super::{daemon, init_voice, voice_args, VoiceOptions};
+
+pub fn speak_command() -> Command {
+    Command::new("speak")
+        .about("Read text, a file or stdin aloud, or save the speech to a file")
+        .args(voice_args())
+        .args(input_args())
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Path to save audio output to, in the format of its extension (if not specified, plays directly)")
+                .value_name("OUTPUT_PATH"),
+        )
+        .arg(
+            Arg::new("subtitles")
+                .long("subtitles")
+                .help("Also write captions of --output, as WebVTT for .vtt paths and SRT otherwise")
+                .value_name("SUBTITLES_PATH")
+                .requires("output"),
+        )
+        .args(tag_args())
+        .arg(
+            Arg::new("audio-output")
+                .long("audio-output")
+                .help("Where to play audio: device, null, null-instant or wav:<path>")
+                .value_name("AUDIO_OUTPUT")
+                .default_value("device")
+                .value_parser(|s: &str| s.parse::<AudioOutput>()),
+        )
+        .arg(
+            Arg::new("queue")
+                .long("queue")
+                .help("Read after the texts already queued in the daemon instead of interrupting them")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("no-daemon"),
+        )
+        .arg(
+            Arg::new("no-daemon")
+                .long("no-daemon")
+                .help("Load the voices and play in this process even if a daemon is running")
+                .action(clap::ArgAction::SetTrue),
+        )
+}
+
+pub fn export_command() -> Command {
+    Command::new("export")
+        .about("Export a whole document to an audiobook with chapter markers")
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Path of the audiobook, in the format of its extension")
+                .value_name("OUTPUT_PATH")
+                .required(true),
+        )
+        .args(voice_args())
+        .args(input_args())
+        .arg(
+            Arg::new("cue")
+                .long("cue")
+                .help("Also write the chapters to a CUE sheet next to the audiobook")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("subtitles")
+                .long("subtitles")
+                .help("Also write captions, as WebVTT for .vtt paths and SRT otherwise")
+                .value_name("SUBTITLES_PATH"),
+        )
+        .args(tag_args())
+}
+
+// Where the text comes from, stdin is read when none of these is given
+fn input_args() -> [Arg; 5] {
+    [
+        Arg::new("text")
+            .short('t')
+            .long("text")
+            .help("Text to synthesize")
+            .value_name("TEXT"),
+        Arg::new("input")
+            .short('i')
+            .long("input")
+            .visible_alias("file")
+            .help("Text, Markdown or PDF file to read")
+            .value_name("INPUT_PATH")
+            .conflicts_with("text"),
+        Arg::new("pdf")
+            .long("pdf")
+            .help("PDF document to read, whatever its extension")
+            .value_name("PDF_PATH")
+            .conflicts_with_all(["text", "input"]),
+        Arg::new("pages")
+            .long("pages")
+            .help("Only read these pages of the PDF, like 3-10, 5 or 12-")
+            .value_name("PAGES")
+            .value_parser(|s: &str| s.parse::<PageRange>()),
+        Arg::new("markdown")
+            .long("markdown")
+            .help("Strip Markdown formatting from --text or stdin (.md files are always stripped)")
+            .action(clap::ArgAction::SetTrue),
+    ]
+}
+
+fn tag_args() -> [Arg; 3] {
+    [
+        Arg::new("format")
+            .long("format")
+            .help("Audio format of saved files: wav, flac or opus (defaults to the file extension)")
+            .value_name("FORMAT")
+            .value_parser(|s: &str| s.parse::<ExportFormat>()),
+        Arg::new("title")
+            .long("title")
+            .help("Title tag of saved audio (defaults to the document title)")
+            .value_name("TITLE"),
+        Arg::new("author")
+            .long("author")
+            .help("Author tag of saved audio (defaults to the document author)")
+            .value_name("AUTHOR"),
+    ]
+}
+
+pub async fn run_speak(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if reads_stdin(matches) && matches.get_one::<String>("output").is_none() {
+        return speak_stdin(matches).await;
+    }
+    let document = read_document(matches)?;
+    let text = document.text.as_str();
+    let socket_path = daemon::socket_path();
+    if plays_with_daemon(matches) && core_daemon::is_running(&socket_path).await {
+        let request = daemon_request(matches, text.to_string(), true, true);
+        let read = core_daemon::send_request(&socket_path, &request);
+        return speak_with_daemon(&socket_path, async { read.await.map(|_| ()) }).await;
+    }
+    let voice = init_voice(matches).await?;
+    let (voice_style, speed) = (voice.voice_style.as_str(), voice.speed);
+    let audio_output = matches.get_one::<AudioOutput>("audio-output").unwrap();
+    let subtitles_path = matches.get_one::<String>("subtitles").map(String::as_str);
+
+    // Check if we're being called from speech dispatcher (via environment or other indicators)
+    let is_speech_dispatcher = std::env::var("MOZ_CRASHREPORTER_DATA_DIRECTORY").is_ok()
+        || std::env::var("SPEECHD_PORT").is_ok()
+        || std::env::var("SPEECHD_HOST").is_ok();
+
+    if let Some(output_path) = matches.get_one::<String>("output") {
+        if let Err(e) = FileHandler::ensure_all_paths_exists(output_path) {
+            let err_msg = format!("Error: Failed to create output directory: {}", e);
+            return Err(err_msg.into());
+        }
+
+        if !is_speech_dispatcher {
+            println!("Generating and saving speech to file...");
+        }
+        let format = format_for(matches, output_path);
+        // Engines only save raw WAV audio, without the timing captions need
+        let saved = if format != ExportFormat::Wav || subtitles_path.is_some() {
+            let exporter = AudiobookExporter::new(voice_style, speed, voice.volume, voice.effects);
+            let metadata = with_tag_flags(matches, document.metadata.clone());
+            save_encoded_speech(
+                exporter,
+                text,
+                output_path,
+                format,
+                metadata,
+                subtitles_path,
+            )
+            .await
+        } else if voice.volume == 1.0 && voice.effects.is_neutral() {
+            VoiceManager::save_speech_to_file(text, voice_style, speed, output_path).await
+        } else {
+            // Engines save raw speech, so processed audio is recorded through the player instead
+            save_processed_speech(
+                text,
+                voice_style,
+                speed,
+                output_path,
+                voice.volume,
+                voice.effects,
+            )
+            .await
+        };
+        match saved {
+            Ok(_) => {
+                if !is_speech_dispatcher {
+                    println!("Successfully saved audio to: {}", output_path);
+                }
+            }
+            Err(e) => {
+                let err_msg = format!("Error: Failed to save audio to file: {}", e);
+                return Err(err_msg.into());
+            }
+        }
+    } else {
+        let sentences = text::split_text_into_sentences(text);
+        if !is_speech_dispatcher {
+            println!("Reading {} sentences...", sentences.len());
+        }
+        let player = Arc::new(player_for(audio_output, &voice));
+        let sentences = tokio_stream::iter(sentences);
+        match play_sentences(sentences, voice_style, speed, player, || false).await {
+            Ok(_) => {
+                if !is_speech_dispatcher {
+                    println!("Audio playback completed.");
+                }
+            }
+            Err(e) => {
+                let err_msg = format!("Error: Failed to play audio: {}", e);
+                return Err(err_msg.into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Plain playback is left to a running daemon, which has the voices loaded already. Effects,
+// outputs and saving need the player of this process
+fn plays_with_daemon(matches: &ArgMatches) -> bool {
+    let is_default = |id| matches.value_source(id) == Some(ValueSource::DefaultValue);
+    let plays_plain = matches.get_one::<String>("output").is_none()
+        && ["volume", "pitch", "audio-output"]
+            .into_iter()
+            .all(is_default)
+        && !matches.get_flag("normalize");
+    plays_plain && !matches.get_flag("no-daemon")
+}
+
+// Text that doesn't `replace` what the daemon reads is queued, as is all text with --queue
+fn daemon_request(matches: &ArgMatches, text: String, replace: bool, wait: bool) -> DaemonRequest {
+    let request = SpeechRequest {
+        text,
+        voice: matches.get_one::<String>("voice").unwrap().clone(),
+        speed: *matches.get_one::<f32>("speed").unwrap(),
+        wait,
+    };
+    match replace && !matches.get_flag("queue") {
+        true => DaemonRequest::Speak(request),
+        false => DaemonRequest::Queue(request),
+    }
+}
+
+// Waits until the daemon has read the text, interrupting it if this process is stopped so
+// speech dispatcher can cancel speech by killing its script
+async fn speak_with_daemon(
+    socket_path: &Path,
+    read: impl Future<Output = EngineResult<()>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let stop = || async {
+        core_daemon::send_request(socket_path, &DaemonRequest::Stop)
+            .await
+            .map(|_| ())
+    };
+    let read = tokio::select! {
+        read = read => read,
+        _ = tokio::signal::ctrl_c() => stop().await,
+        _ = terminate.recv() => stop().await,
+    };
+    read.map_err(|e| format!("Error: Daemon failed to read text: {}", e).into())
+}
+
+// Piped text is spoken as it comes in, instead of once the input has ended
+async fn speak_stdin(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if std::io::stdin().is_terminal() {
+        return Err(NOTHING_TO_READ.into());
+    }
+    if matches.get_one::<PageRange>("pages").is_some() {
+        return Err("Error: --pages only applies to PDF documents".into());
+    }
+    let chunks = stdin_chunks(matches.get_flag("markdown"));
+
+    let socket_path = daemon::socket_path();
+    if plays_with_daemon(matches) && core_daemon::is_running(&socket_path).await {
+        let read = queue_with_daemon(matches, &socket_path, chunks);
+        return speak_with_daemon(&socket_path, read).await;
+    }
+
+    let voice = init_voice(matches).await?;
+    let audio_output = matches.get_one::<AudioOutput>("audio-output").unwrap();
+    let player = Arc::new(player_for(audio_output, &voice));
+    play_sentences(
+        sentences_of(chunks),
+        &voice.voice_style,
+        voice.speed,
+        player,
+        || false,
+    )
+    .await
+    .map_err(|e| format!("Error: Failed to play audio: {}", e).into())
+}
+
+// Each piece is queued behind the ones before it, the last one is only sent once the input
+// has ended so it can be waited for
+async fn queue_with_daemon(
+    matches: &ArgMatches,
+    socket_path: &Path,
+    mut chunks: mpsc::Receiver<String>,
+) -> EngineResult<()> {
+    let mut pending: Option<String> = None;
+    let mut first = true;
+    while let Some(chunk) = chunks.recv().await {
+        if let Some(text) = pending.replace(chunk) {
+            let request = daemon_request(matches, text, first, false);
+            core_daemon::send_request(socket_path, &request).await?;
+            first = false;
+        }
+    }
+    match pending {
+        Some(text) => {
+            let request = daemon_request(matches, text, first, true);
+            core_daemon::send_request(socket_path, &request)
+                .await
+                .map(|_| ())
+        }
+        None => Ok(()),
+    }
+}
+
+// Stdin a line at a time, like the sentence splitter reads text anyway. Markdown goes by
+// paragraph, as its blocks span lines
+fn stdin_chunks(markdown: bool) -> mpsc::Receiver<String> {
+    let (sender, receiver) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut paragraph = String::new();
+        loop {
+            let line = lines.next_line().await.ok().flatten();
+            let ends_chunk = line
+                .as_ref()
+                .is_none_or(|line| !markdown || line.trim().is_empty());
+            if let Some(line) = &line {
+                paragraph.push_str(line);
+                paragraph.push('\n');
+            }
+            if ends_chunk && !paragraph.trim().is_empty() {
+                let chunk = match markdown {
+                    true => markdown::strip_markdown_for_tts(&paragraph),
+                    false => paragraph.clone(),
+                };
+                if sender.send(chunk).await.is_err() {
+                    return;
+                }
+            }
+            if ends_chunk {
+                paragraph.clear();
+            }
+            if line.is_none() {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+fn sentences_of(mut chunks: mpsc::Receiver<String>) -> ReceiverStream<String> {
+    let (sender, sentences) = mpsc::channel(16);
+    tokio::spawn(async move {
+        while let Some(chunk) = chunks.recv().await {
+            for sentence in text::split_text_into_sentences(&chunk) {
+                if sender.send(sentence).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    ReceiverStream::new(sentences)
+}
+
+fn player_for(audio_output: &AudioOutput, voice: &VoiceOptions) -> AudioPlayer {
+    let player = AudioPlayer::new(audio_output.clone());
+    player.set_volume(voice.volume);
+    player.set_pitch(voice.effects.pitch);
+    player.set_normalize(voice.effects.normalize);
+    player
+}
+
+pub async fn run_export(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let document = read_document(matches)?;
+    let voice = init_voice(matches).await?;
+    let export_path = matches.get_one::<String>("output").unwrap();
+
+    let exporter =
+        AudiobookExporter::new(&voice.voice_style, voice.speed, voice.volume, voice.effects);
+    export_audiobook(
+        exporter,
+        document.chapters,
+        export_path,
+        format_for(matches, export_path),
+        with_tag_flags(matches, document.metadata),
+        matches.get_flag("cue"),
+        matches.get_one::<String>("subtitles").map(String::as_str),
+    )
+    .await
+}
+
+// Flags override the tags read from the document
+fn with_tag_flags(matches: &ArgMatches, metadata: AudioMetadata) -> AudioMetadata {
+    AudioMetadata {
+        title: matches
+            .get_one::<String>("title")
+            .cloned()
+            .unwrap_or(metadata.title),
+        author: matches
+            .get_one::<String>("author")
+            .cloned()
+            .unwrap_or(metadata.author),
+        ..metadata
+    }
+}
+
+fn format_for(matches: &ArgMatches, path: &str) -> ExportFormat {
+    matches
+        .get_one::<ExportFormat>("format")
+        .copied()
+        .or_else(|| ExportFormat::from_path(Path::new(path)))
+        .unwrap_or_default()
+}
+
+const NOTHING_TO_READ: &str = "Error: Nothing to read, pass --text, --input or pipe text to stdin";
+
+fn reads_stdin(matches: &ArgMatches) -> bool {
+    ["text", "input", "pdf"]
+        .into_iter()
+        .all(|id| matches.get_one::<String>(id).is_none())
+}
+
+fn read_document(matches: &ArgMatches) -> Result<Document, Box<dyn Error>> {
+    let input_path = matches.get_one::<String>("input");
+    let pdf_path = matches.get_one::<String>("pdf");
+    let text_arg = matches.get_one::<String>("text");
+    if reads_stdin(matches) && std::io::stdin().is_terminal() {
+        return Err(NOTHING_TO_READ.into());
+    }
+
+    let pages = matches.get_one::<PageRange>("pages").copied();
+    let document = match (pdf_path, input_path) {
+        (Some(pdf_path), _) => load_pdf(Path::new(pdf_path), pages.unwrap_or_default())
+            .map_err(|e| format!("Error: Failed to read {}: {}", pdf_path, e))?,
+        (None, Some(input_path)) => {
+            load_document(Path::new(input_path), matches.get_flag("markdown"), pages)
+                .map_err(|e| format!("Error: Failed to read {}: {}", input_path, e))?
+        }
+        (None, None) if pages.is_some() => {
+            return Err("Error: --pages only applies to PDF documents".into());
+        }
+        (None, None) => {
+            let text = match text_arg {
+                Some(text) => text.clone(),
+                None => std::io::read_to_string(std::io::stdin())
+                    .map_err(|e| format!("Error: Failed to read stdin: {}", e))?,
+            };
+            Document::from_text(text, "Text", matches.get_flag("markdown"))
+        }
+    };
+    if document.text.trim().is_empty() {
+        return Err("Error: No text to read".into());
+    }
+    Ok(document)
+}
+
+// Text to read along with the chapters and tags it is exported with
+struct Document {
+    text: String,
+    chapters: Vec<Chapter>,
+    metadata: AudioMetadata,
+}
+
+impl Document {
+    fn from_text(text: String, title: &str, markdown: bool) -> Self {
+        let text = if markdown {
+            markdown::strip_markdown_for_tts(&text)
+        } else {
+            text
+        };
+        Document {
+            chapters: Chapter::from_text(&text, title),
+            text,
+            metadata: AudioMetadata::default(),
+        }
+    }
+}
+
+// PDFs are read with their outline and tags, other files are read as text, split at
+// headings and named after the file
+fn load_document(
+    input_path: &Path,
+    markdown: bool,
+    pages: Option<PageRange>,
+) -> Result<Document, Box<dyn Error>> {
+    let extension = input_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if extension == "pdf" {
+        return load_pdf(input_path, pages.unwrap_or_default());
+    }
+    if pages.is_some() {
+        return Err("--pages only applies to PDF documents".into());
+    }
+
+    let file_name = file_name(input_path);
+    let text = std::fs::read_to_string(input_path)?;
+    let markdown = markdown || extension == "md" || extension == "markdown";
+    Ok(Document {
+        metadata: AudioMetadata {
+            title: file_name.clone(),
+            ..Default::default()
+        },
+        ..Document::from_text(text, &file_name, markdown)
+    })
+}
+
+// Reading blocks of the pages in range, the same ones the reader highlights, with a
+// chapter per outline entry and the document title and author
+fn load_pdf(input_path: &Path, pages: PageRange) -> Result<Document, Box<dyn Error>> {
+    let mut pdf_wrapper = PdfiumWrapper::default();
+    // Pdfium is downloaded on first use by blocking on the runtime we're already in
+    tokio::task::block_in_place(|| pdf_wrapper.init())?;
+    pdf_wrapper.load_document(input_path)?;
+
+    let chapters = match pdf_wrapper.get_document() {
+        Some(document) => {
+            let page_count = document.pages().len();
+            if pages.first() >= page_count {
+                return Err(format!(
+                    "Page {} is past the end of the document, which has {} pages",
+                    pages.first() + 1,
+                    page_count
+                )
+                .into());
+            }
+            PdfHighlighter::generate_document_chapters(document, &pdf_wrapper.get_outline(), pages)
+        }
+        None => return Err("Failed to load PDF document".into()),
+    };
+    let metadata = AudioMetadata {
+        title: pdf_wrapper
+            .get_metadata_tag(PdfDocumentMetadataTagType::Title)
+            .unwrap_or_else(|| file_name(input_path)),
+        author: pdf_wrapper
+            .get_metadata_tag(PdfDocumentMetadataTagType::Author)
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+    pdf_wrapper.remove_pdf();
+
+    // Blocks are whole paragraphs, kept apart so they aren't read as one sentence
+    let text = chapters
+        .iter()
+        .flat_map(|chapter| chapter.blocks.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    Ok(Document {
+        text,
+        chapters,
+        metadata,
+    })
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+async fn export_audiobook(
+    exporter: AudiobookExporter,
+    chapters: Vec<Chapter>,
+    output_path: &str,
+    format: ExportFormat,
+    metadata: AudioMetadata,
+    write_cue: bool,
+    subtitles_path: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    if let Err(e) = FileHandler::ensure_all_paths_exists(output_path) {
+        let err_msg = format!("Error: Failed to create output directory: {}", e);
+        return Err(err_msg.into());
+    }
+
+    let exporter = Arc::new(exporter);
+    let cancel_on_ctrl_c = tokio::spawn({
+        let exporter = exporter.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                println!("\nCancelling export...");
+                exporter.cancel();
+            }
+        }
+    });
+
+    let blocks = chapters.iter().map(|c| c.blocks.len()).sum::<usize>();
+    println!(
+        "Exporting {} blocks in {} chapters...",
+        blocks,
+        chapters.len()
+    );
+
+    let progress = ProgressTracker::default().get_terminal_progress_callback();
+    let output = PathBuf::from(output_path);
+    let tags = metadata.clone();
+    let exported = tokio::task::spawn_blocking(move || {
+        exporter.export(&chapters, &output, format, &tags, Some(progress))
+    })
+    .await;
+    cancel_on_ctrl_c.abort();
+
+    match exported? {
+        Ok(timeline) => {
+            println!("Successfully exported audiobook to: {}", output_path);
+            for marker in timeline.markers.iter() {
+                println!("  {}  {}", format_timestamp(marker.start()), marker.title);
+            }
+
+            if write_cue {
+                let cue_path =
+                    output::write_cue_sheet(Path::new(output_path), &metadata, &timeline.markers)
+                        .map_err(|e| format!("Error: Failed to write CUE sheet: {}", e))?;
+                println!("Saved chapters to: {}", cue_path.display());
+            }
+            if let Some(subtitles_path) = subtitles_path {
+                save_subtitles(subtitles_path, &timeline.cues)
+                    .map_err(|e| format!("Error: Failed to write subtitles: {}", e))?;
+                println!("Saved subtitles to: {}", subtitles_path);
+            }
+            Ok(())
+        }
+        Err(e) => {
+            let err_msg = format!("Error: Failed to export audiobook: {}", e);
+            Err(err_msg.into())
+        }
+    }
+}
+
+fn format_timestamp(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+// The whole text is synthesized as one block, like engines do when saving WAV files,
+// unless captions are wanted, which get a cue per sentence
+async fn save_encoded_speech(
+    exporter: AudiobookExporter,
+    text: &str,
+    output_path: &str,
+    format: ExportFormat,
+    metadata: AudioMetadata,
+    subtitles_path: Option<&str>,
+) -> EngineResult<()> {
+    let blocks = match subtitles_path {
+        Some(_) => text::split_text_into_sentences(text),
+        None => vec![text.to_string()],
+    };
+    let chapters = vec![Chapter {
+        title: metadata.title.clone(),
+        blocks,
+    }];
+    let output = PathBuf::from(output_path);
+
+    let timeline = tokio::task::spawn_blocking(move || {
+        exporter.export(&chapters, &output, format, &metadata, None)
+    })
+    .await??;
+
+    match subtitles_path {
+        Some(subtitles_path) => save_subtitles(subtitles_path, &timeline.cues),
+        None => Ok(()),
+    }
+}
+
+pub(super) fn save_subtitles(path: &str, cues: &[SubtitleCue]) -> EngineResult<()> {
+    FileHandler::ensure_all_paths_exists(path)?;
+    let format = SubtitleFormat::from_path(Path::new(path)).unwrap_or(SubtitleFormat::Srt);
+    subtitles::write_subtitles(Path::new(path), format, cues)
+}
+
+async fn save_processed_speech(
+    text: &str,
+    voice_style: &str,
+    speed: f32,
+    output_path: &str,
+    volume: f32,
+    effects: AudioEffects,
+) -> EngineResult<()> {
+    let audio_buffer = VoiceManager::generate_speech(text, voice_style, speed).await?;
+
+    let player = AudioPlayer::new(AudioOutput::Wav(output_path.into()));
+    player.set_volume(volume);
+    player.set_pitch(effects.pitch);
+    player.set_normalize(effects.normalize);
+    player.play_audio(audio_buffer)
+}
@@ -0,0 +1,124 @@
+use clap::{Arg, ArgMatches, Command};
+use rodio::{source::UniformSourceIterator, Decoder};
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::time::Duration;
+use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+
+use crate::core::speech_engine::EngineResult;
+use crate::output::subtitles::SubtitleCue;
+use crate::paths::whisper_config;
+use crate::utils::{progress_tracker::ProgressTracker, whisper_downloader};
+
+use super::speak::save_subtitles;
+
+// Whisper only takes 16kHz mono audio
+const WHISPER_SAMPLE_RATE: u32 = 16_000;
+
+pub fn command() -> Command {
+    Command::new("transcribe")
+        .about("Transcribe speech from an audio file with a Whisper model")
+        .arg(
+            Arg::new("audio")
+                .help("WAV, FLAC, MP3 or Ogg Vorbis file to transcribe")
+                .value_name("AUDIO_PATH")
+                .required(true),
+        )
+        .arg(
+            Arg::new("model")
+                .short('m')
+                .long("model")
+                .help("Whisper model, downloaded when missing (defaults to the first downloaded one, or base)")
+                .value_name("MODEL")
+                .value_parser(whisper_config::get_whisper_models_names()),
+        )
+        .arg(
+            Arg::new("language")
+                .short('l')
+                .long("language")
+                .help("Language spoken in the audio, like en (detected when not given)")
+                .value_name("LANGUAGE"),
+        )
+        .arg(
+            Arg::new("subtitles")
+                .long("subtitles")
+                .help("Also write the transcript as captions, as WebVTT for .vtt paths and SRT otherwise")
+                .value_name("SUBTITLES_PATH"),
+        )
+}
+
+pub async fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let audio_path = matches.get_one::<String>("audio").unwrap().clone();
+    let model = match matches.get_one::<String>("model") {
+        Some(model) => model.clone(),
+        None => whisper_downloader::get_downloaded_models()
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| "base".to_string()),
+    };
+
+    if !whisper_downloader::get_downloaded_models().contains(&model) {
+        eprintln!("Downloading whisper model {}...", model);
+        let progress = ProgressTracker::default().get_terminal_progress_callback();
+        whisper_downloader::download_model(&model, Some(progress))
+            .await
+            .map_err(|e| format!("Error: {}", e))?;
+    }
+
+    let language = matches.get_one::<String>("language").cloned();
+    let cues = tokio::task::spawn_blocking(move || {
+        let audio =
+            load_audio(&audio_path).map_err(|e| format!("Failed to read {}: {}", audio_path, e))?;
+        transcribe(&whisper_config::get_model_path(&model), &audio, language)
+    })
+    .await?
+    .map_err(|e| format!("Error: Failed to transcribe audio: {}", e))?;
+
+    for cue in cues.iter() {
+        println!("{}", cue.text);
+    }
+
+    if let Some(subtitles_path) = matches.get_one::<String>("subtitles") {
+        save_subtitles(subtitles_path, &cues)
+            .map_err(|e| format!("Error: Failed to write subtitles: {}", e))?;
+        eprintln!("Saved subtitles to: {}", subtitles_path);
+    }
+    Ok(())
+}
+
+fn load_audio(path: &str) -> EngineResult<Vec<f32>> {
+    let decoder = Decoder::new(BufReader::new(File::open(path)?))?;
+    Ok(UniformSourceIterator::<_, f32>::new(decoder, 1, WHISPER_SAMPLE_RATE).collect())
+}
+
+// A cue per segment Whisper splits the speech into
+fn transcribe(
+    model_path: &str,
+    audio: &[f32],
+    language: Option<String>,
+) -> EngineResult<Vec<SubtitleCue>> {
+    let whisper_ctx =
+        WhisperContext::new_with_params(model_path, WhisperContextParameters::default())?;
+
+    let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+    params.set_language(language.as_deref());
+    params.set_print_progress(false);
+    params.set_print_special(false);
+    params.set_print_realtime(false);
+
+    let mut state = whisper_ctx.create_state()?;
+    state.full(params, audio)?;
+
+    // Segment times are counted in hundredths of a second
+    let centiseconds = |t: i64| Duration::from_millis(t.max(0) as u64 * 10);
+    (0..state.full_n_segments()?)
+        .map(|i| {
+            Ok(SubtitleCue {
+                text: state.full_get_segment_text(i)?.trim().to_string(),
+                start: centiseconds(state.full_get_segment_t0(i)?),
+                end: centiseconds(state.full_get_segment_t1(i)?),
+            })
+        })
+        .collect()
+}
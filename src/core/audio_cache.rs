@@ -0,0 +1,101 @@
+use rodio::{buffer::SamplesBuffer, Source};
+use std::collections::VecDeque;
+
+use super::word_timing::{align_words, WordTiming};
+
+// Audio of a block together with where each of its words starts
+#[derive(Clone)]
+pub struct SynthesizedBlock {
+    pub audio: SamplesBuffer<f32>,
+    pub words: Vec<WordTiming>,
+}
+
+impl SynthesizedBlock {
+    pub fn new(text: &str, audio: SamplesBuffer<f32>) -> Self {
+        let samples = audio.clone().collect::<Vec<_>>();
+        let words = align_words(text, &samples, audio.channels());
+        Self { audio, words }
+    }
+}
+
+// Least recently used blocks are evicted first once the cache is full, so memory
+// stays bounded on long documents while recently read blocks survive a Prev
+pub struct AudioCache {
+    capacity: usize,
+    entries: VecDeque<(usize, SynthesizedBlock)>,
+}
+
+impl AudioCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn get(&mut self, block_idx: usize) -> Option<SynthesizedBlock> {
+        let position = self.entries.iter().position(|(idx, _)| *idx == block_idx)?;
+        let entry = self.entries.remove(position)?;
+        let block = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(block)
+    }
+
+    pub fn contains(&self, block_idx: usize) -> bool {
+        self.entries.iter().any(|(idx, _)| *idx == block_idx)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn insert(&mut self, block_idx: usize, block: SynthesizedBlock) {
+        self.entries.retain(|(idx, _)| *idx != block_idx);
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((block_idx, block));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audio(len: usize) -> SynthesizedBlock {
+        SynthesizedBlock::new("block", SamplesBuffer::new(1, 8000, vec![0.0; len]))
+    }
+
+    #[test]
+    fn test_get_returns_inserted_audio() {
+        let mut cache = AudioCache::new(2);
+        cache.insert(3, audio(10));
+
+        assert_eq!(cache.get(3).map(|b| b.audio.count()), Some(10));
+        assert!(cache.get(4).is_none());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = AudioCache::new(2);
+        cache.insert(0, audio(1));
+        cache.insert(1, audio(1));
+        cache.get(0);
+        cache.insert(2, audio(1));
+
+        assert!(cache.contains(0));
+        assert!(!cache.contains(1));
+        assert!(cache.contains(2));
+    }
+
+    #[test]
+    fn test_insert_replaces_existing_block() {
+        let mut cache = AudioCache::new(2);
+        cache.insert(0, audio(1));
+        cache.insert(0, audio(5));
+        cache.insert(1, audio(1));
+
+        assert!(cache.contains(0));
+        assert_eq!(cache.get(0).map(|b| b.audio.count()), Some(5));
+    }
+}
@@ -0,0 +1,488 @@
+use rodio::Source;
+use std::{
+    fs::{self, File},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use super::{speech_engine::EngineResult, voice_manager::VoiceManager};
+use crate::output::{self, subtitles::SubtitleCue, AudioEncoder, AudioMetadata, ExportFormat};
+use crate::utils::{
+    audio_effects::{clamp_volume, AudioEffects},
+    pdfium::OutlineEntry,
+    progress_tracker::ProgressCallback,
+    text,
+};
+
+// Silence put between chapters so they don't run into each other
+const CHAPTER_GAP: Duration = Duration::from_secs(1);
+// Longest single line paragraph still taken for a heading
+const MAX_HEADING_LEN: usize = 80;
+// Chapter of the pages before the first outline entry
+const FRONT_MATTER_TITLE: &str = "Front Matter";
+
+pub const CANCELLED_ERROR: &str = "Export cancelled";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: String,
+    pub blocks: Vec<String>,
+}
+
+impl Chapter {
+    // Splits text into chapters at headings, paragraphs made of a single short line that
+    // doesn't end like a sentence. Text before the first heading goes under `title`
+    pub fn from_text(text: &str, title: &str) -> Vec<Chapter> {
+        let mut chapters = Vec::new();
+        let mut current = Chapter {
+            title: title.to_string(),
+            blocks: Vec::new(),
+        };
+
+        for paragraph in text.split("\n\n").map(str::trim) {
+            if paragraph.is_empty() {
+                continue;
+            }
+
+            if let Some(heading) = as_heading(paragraph) {
+                if !current.blocks.is_empty() {
+                    chapters.push(current);
+                }
+                current = Chapter {
+                    title: heading.to_string(),
+                    blocks: vec![heading.to_string()],
+                };
+            } else {
+                current
+                    .blocks
+                    .extend(text::split_text_into_sentences(paragraph));
+            }
+        }
+
+        if !current.blocks.is_empty() {
+            chapters.push(current);
+        }
+        chapters
+    }
+
+    // Groups the blocks of each page into a chapter per outline entry, running up to the
+    // page of the next entry. Without an outline every page is a chapter of its own
+    pub fn from_pages(pages: Vec<Vec<String>>, outline: &[OutlineEntry]) -> Vec<Chapter> {
+        let mut chapters: Vec<Chapter> = Vec::new();
+
+        for (page_idx, blocks) in pages.into_iter().enumerate() {
+            let entry = outline.iter().find(|e| e.page as usize == page_idx);
+            let title = match entry {
+                Some(entry) => Some(entry.title.clone()),
+                None if outline.is_empty() => Some(format!("Page {}", page_idx + 1)),
+                None if chapters.is_empty() => Some(FRONT_MATTER_TITLE.to_string()),
+                None => None,
+            };
+
+            if let Some(title) = title {
+                chapters.push(Chapter {
+                    title,
+                    blocks: Vec::new(),
+                });
+            }
+            if let Some(chapter) = chapters.last_mut() {
+                chapter.blocks.extend(blocks);
+            }
+        }
+
+        chapters.retain(|c| !c.blocks.is_empty());
+        chapters
+    }
+}
+
+fn as_heading(paragraph: &str) -> Option<&str> {
+    if paragraph.contains('\n') || paragraph.chars().count() > MAX_HEADING_LEN {
+        return None;
+    }
+    if paragraph.ends_with(['.', '!', '?', ',', ';', ':']) {
+        return None;
+    }
+
+    let heading = paragraph.trim_start_matches('#').trim();
+    (!heading.is_empty()).then_some(heading)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChapterMarker {
+    pub title: String,
+    // Frame of the exported audio at which the chapter starts
+    pub frame: u32,
+    pub sample_rate: u32,
+}
+
+impl ChapterMarker {
+    pub fn start(&self) -> Duration {
+        Duration::from_secs_f64(self.frame as f64 / self.sample_rate as f64)
+    }
+}
+
+// Where chapters start in the exported audio and the span every block was spoken in
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportTimeline {
+    pub markers: Vec<ChapterMarker>,
+    pub cues: Vec<SubtitleCue>,
+}
+
+// Synthesizes whole documents into a single audio file, block after block, so memory use
+// doesn't grow with the length of the document
+pub struct AudiobookExporter {
+    voice: String,
+    speed: f32,
+    volume: f32,
+    effects: AudioEffects,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl AudiobookExporter {
+    pub fn new(voice: &str, speed: f32, volume: f32, effects: AudioEffects) -> Self {
+        Self {
+            voice: voice.to_string(),
+            speed,
+            volume: clamp_volume(volume),
+            effects,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Stops the export before the next block, the partial file is removed
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    // Blocking, as engines synthesize synchronously, so it's meant for `spawn_blocking`.
+    // Chapters are stored as markers where the format supports them and returned with
+    // the timing of every block. The voice tag of `metadata` is filled in from the exporter
+    pub fn export(
+        &self,
+        chapters: &[Chapter],
+        output_path: &Path,
+        format: ExportFormat,
+        metadata: &AudioMetadata,
+        progress: Option<ProgressCallback>,
+    ) -> EngineResult<ExportTimeline> {
+        let metadata = AudioMetadata {
+            voice: self.voice.clone(),
+            ..metadata.clone()
+        };
+        let result = self.write_chapters(chapters, output_path, format, &metadata, progress);
+        if result.is_err() {
+            let _ = fs::remove_file(output_path);
+        }
+        result
+    }
+
+    fn write_chapters(
+        &self,
+        chapters: &[Chapter],
+        output_path: &Path,
+        format: ExportFormat,
+        metadata: &AudioMetadata,
+        progress: Option<ProgressCallback>,
+    ) -> EngineResult<ExportTimeline> {
+        let total_blocks = chapters.iter().map(|c| c.blocks.len()).sum::<usize>();
+        if total_blocks == 0 {
+            return Err("Nothing to export, the document has no text".into());
+        }
+
+        let engine = VoiceManager::get_engine_for_voice(&self.voice)?;
+        // Created from the first block, as only then the channels and rate are known
+        let mut encoder: Option<(Box<dyn AudioEncoder<File>>, u16, u32)> = None;
+        let mut timeline = ExportTimeline::default();
+        let mut written_blocks = 0;
+
+        for chapter in chapters.iter().filter(|c| !c.blocks.is_empty()) {
+            if let Some((encoder, channels, sample_rate)) = encoder.as_mut() {
+                let gap = CHAPTER_GAP.as_secs_f64() * *sample_rate as f64;
+                encoder.write_samples(&vec![0.0; gap as usize * *channels as usize])?;
+            }
+
+            let mut chapter_title = Some(chapter.title.clone());
+            for block in chapter.blocks.iter() {
+                if self.is_cancelled() {
+                    return Err(CANCELLED_ERROR.into());
+                }
+
+                let audio = engine.generate_speech(block, &self.voice, self.speed)?;
+                let audio = self.effects.apply(audio);
+
+                let (encoder, _, sample_rate) = match encoder.as_mut() {
+                    Some(encoder) => encoder,
+                    None => {
+                        let (channels, sample_rate) = (audio.channels(), audio.sample_rate());
+                        let created = output::create_encoder(
+                            format,
+                            output::create_file(output_path)?,
+                            channels,
+                            sample_rate,
+                            metadata,
+                        )?;
+                        encoder.insert((created, channels, sample_rate))
+                    }
+                };
+
+                if let Some(title) = chapter_title.take() {
+                    timeline.markers.push(ChapterMarker {
+                        title,
+                        frame: encoder.frames_written() as u32,
+                        sample_rate: *sample_rate,
+                    });
+                }
+
+                let samples = audio.map(|s| s * self.volume).collect::<Vec<_>>();
+                let start = encoder.frames_written();
+                encoder.write_samples(&samples)?;
+
+                let to_duration =
+                    |frames: u64| Duration::from_secs_f64(frames as f64 / *sample_rate as f64);
+                timeline.cues.push(SubtitleCue {
+                    text: block.clone(),
+                    start: to_duration(start),
+                    end: to_duration(encoder.frames_written()),
+                });
+
+                written_blocks += 1;
+                if let Some(callback) = progress.as_ref() {
+                    callback.lock().unwrap()(written_blocks as f32 / total_blocks as f32);
+                }
+            }
+        }
+
+        if let Some((encoder, _, _)) = encoder {
+            encoder.finish(&timeline.markers)?;
+        }
+
+        Ok(timeline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::mock_engine::{mock_engine, MockEngine, FAIL_MARKER, SAMPLE_RATE};
+    use std::sync::Mutex;
+
+    fn chapter(title: &str, blocks: &[&str]) -> Chapter {
+        Chapter {
+            title: title.to_string(),
+            blocks: blocks.iter().map(|b| b.to_string()).collect(),
+        }
+    }
+
+    fn export_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("fox-reader-{}-{}.wav", name, std::process::id()))
+    }
+
+    // Labels of the `labl` chunks in file order
+    fn read_labels(bytes: &[u8]) -> Vec<String> {
+        bytes
+            .windows(4)
+            .enumerate()
+            .filter(|(_, id)| *id == b"labl")
+            .map(|(pos, _)| {
+                let size = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap());
+                let text = &bytes[pos + 12..pos + 8 + size as usize - 1];
+                String::from_utf8(text.to_vec()).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chapters_split_at_headings() {
+        let text = "Intro sentence that comes first.\n\n# Chapter One\n\n\
+                    The first chapter starts here. It has two sentences.\n\n\
+                    Chapter Two\n\nThe second chapter is short.";
+
+        let chapters = Chapter::from_text(text, "Document");
+
+        let titles = chapters
+            .iter()
+            .map(|c| c.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, vec!["Document", "Chapter One", "Chapter Two"]);
+        assert_eq!(chapters[1].blocks[0], "Chapter One");
+        assert_eq!(chapters[1].blocks.len(), 3);
+    }
+
+    #[test]
+    fn test_sentences_and_long_lines_are_not_headings() {
+        assert_eq!(as_heading("Just a sentence."), None);
+        assert_eq!(as_heading("First line\nsecond line"), None);
+        assert_eq!(as_heading(&"word ".repeat(20)), None);
+        assert_eq!(as_heading("## Part 2"), Some("Part 2"));
+        assert!(Chapter::from_text(" \n\n ", "Empty").is_empty());
+    }
+
+    #[test]
+    fn test_pages_grouped_by_outline() {
+        let pages = vec![
+            vec!["Cover.".to_string()],
+            vec!["Intro.".to_string()],
+            vec![],
+            vec!["More.".to_string(), "Text.".to_string()],
+            vec!["End.".to_string()],
+        ];
+        let outline = vec![
+            OutlineEntry {
+                title: "Introduction".to_string(),
+                page: 1,
+            },
+            OutlineEntry {
+                title: "Blank".to_string(),
+                page: 2,
+            },
+            OutlineEntry {
+                title: "Chapter 1".to_string(),
+                page: 3,
+            },
+        ];
+
+        let chapters = Chapter::from_pages(pages.clone(), &outline);
+
+        assert_eq!(
+            chapters,
+            vec![
+                chapter(FRONT_MATTER_TITLE, &["Cover."]),
+                chapter("Introduction", &["Intro."]),
+                chapter("Chapter 1", &["More.", "Text.", "End."]),
+            ]
+        );
+        let titles = Chapter::from_pages(pages, &[])
+            .into_iter()
+            .map(|c| c.title)
+            .collect::<Vec<_>>();
+        assert_eq!(titles, vec!["Page 1", "Page 2", "Page 4", "Page 5"]);
+    }
+
+    #[test]
+    fn test_export_writes_audio_markers_and_cues() {
+        mock_engine();
+        let voice = "mock_export";
+        let path = export_path("audiobook");
+        let chapters = vec![
+            chapter("Page 1", &["First block.", "Second block."]),
+            chapter("Page 2", &["Third block."]),
+        ];
+
+        let progress_values = Arc::new(Mutex::new(Vec::new()));
+        let progress_values_clone = progress_values.clone();
+        let progress: ProgressCallback = Arc::new(Mutex::new(move |value: f32| {
+            progress_values_clone.lock().unwrap().push(value);
+        }));
+
+        let exporter = AudiobookExporter::new(voice, 1.0, 1.0, AudioEffects::default());
+        let timeline = exporter
+            .export(
+                &chapters,
+                &path,
+                ExportFormat::Wav,
+                &AudioMetadata::default(),
+                Some(progress),
+            )
+            .unwrap();
+        let markers = &timeline.markers;
+
+        let first_chapter_len = MockEngine::synthesize("First block.", 1.0).len()
+            + MockEngine::synthesize("Second block.", 1.0).len();
+        let gap = CHAPTER_GAP.as_secs() as usize * SAMPLE_RATE as usize;
+        assert_eq!(markers.len(), 2);
+        assert_eq!(markers[0].frame, 0);
+        assert_eq!(markers[1].frame as usize, first_chapter_len + gap);
+        assert_eq!(markers[1].title, "Page 2");
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        let third_len = MockEngine::synthesize("Third block.", 1.0).len();
+        assert_eq!(
+            reader.duration() as usize,
+            first_chapter_len + gap + third_len
+        );
+
+        // Cues cover the blocks but not the gap between chapters
+        let seconds = |frames: usize| Duration::from_secs_f64(frames as f64 / SAMPLE_RATE as f64);
+        let cues = &timeline.cues;
+        assert_eq!(cues.len(), 3);
+        assert_eq!(cues[0].start, Duration::ZERO);
+        assert_eq!(cues[1].start, cues[0].end);
+        assert_eq!(cues[1].end, seconds(first_chapter_len));
+        assert_eq!(cues[2].text, "Third block.");
+        assert_eq!(cues[2].start, seconds(first_chapter_len + gap));
+        assert_eq!(cues[2].end, seconds(first_chapter_len + gap + third_len));
+
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(read_labels(&bytes), vec!["Page 1", "Page 2"]);
+        assert!(bytes.windows(18).any(|w| w == b"Voice: mock_export"));
+        assert_eq!(
+            u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize,
+            bytes.len() - 8
+        );
+
+        assert_eq!(
+            *progress_values.lock().unwrap(),
+            vec![1.0 / 3.0, 2.0 / 3.0, 1.0]
+        );
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_cancelled_export_removes_file() {
+        mock_engine();
+        let path = export_path("cancelled");
+        let exporter = Arc::new(AudiobookExporter::new(
+            "mock_export_cancel",
+            1.0,
+            1.0,
+            AudioEffects::default(),
+        ));
+
+        let exporter_clone = exporter.clone();
+        let progress: ProgressCallback = Arc::new(Mutex::new(move |_: f32| {
+            exporter_clone.cancel();
+        }));
+        let chapters = vec![chapter("Page 1", &["One block.", "Another block."])];
+
+        let err = exporter
+            .export(
+                &chapters,
+                &path,
+                ExportFormat::Wav,
+                &AudioMetadata::default(),
+                Some(progress),
+            )
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), CANCELLED_ERROR);
+        assert!(!path.exists());
+        assert_eq!(mock_engine().calls_for("mock_export_cancel").len(), 1);
+    }
+
+    #[test]
+    fn test_failed_block_aborts_export() {
+        mock_engine();
+        let path = export_path("failed");
+        let exporter =
+            AudiobookExporter::new("mock_export_fail", 1.0, 1.0, AudioEffects::default());
+        let failing = format!("Broken {}", FAIL_MARKER);
+        let chapters = vec![chapter("Page 1", &["Fine block.", &failing])];
+
+        let metadata = AudioMetadata::default();
+        assert!(exporter
+            .export(&chapters, &path, ExportFormat::Flac, &metadata, None)
+            .is_err());
+        assert!(!path.exists());
+        assert!(exporter
+            .export(&[], &path, ExportFormat::Flac, &metadata, None)
+            .is_err());
+    }
+}
@@ -0,0 +1,368 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{DirBuilder, Permissions},
+    os::unix::fs::{DirBuilderExt, PermissionsExt},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    signal::unix::{signal, SignalKind},
+    sync::{mpsc, oneshot, Notify},
+};
+
+use crate::{
+    core::{
+        sentence_player::play_sentences, speech_engine::EngineResult, voice_manager::VoiceManager,
+    },
+    utils::{
+        audio_player::{AudioOutput, AudioPlayer},
+        text,
+    },
+};
+
+// Text to read and the voice to read it with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpeechRequest {
+    pub text: String,
+    pub voice: String,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    // Answer once the text has been read or stopped, instead of as soon as it is queued
+    #[serde(default)]
+    pub wait: bool,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+// Requests are sent one JSON object per line, like {"command":"speak","text":"Hi","voice":"af_heart"},
+// and each gets a line with a `DaemonResponse` back
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum DaemonRequest {
+    // Stops whatever is being read and reads the text
+    Speak(SpeechRequest),
+    // Reads the text after everything queued before it
+    Queue(SpeechRequest),
+    Stop,
+    Pause,
+    Resume,
+    Status,
+    Shutdown,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DaemonResponse {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub playing: bool,
+    pub paused: bool,
+    // Texts waiting to be read, the one being read included
+    pub pending: usize,
+}
+
+struct Job {
+    request: SpeechRequest,
+    generation: u64,
+    done: oneshot::Sender<Result<(), String>>,
+}
+
+// Keeps the speech engines loaded between requests and reads queued texts one by one
+struct Daemon {
+    player: Arc<AudioPlayer>,
+    // Bumped on every stop, jobs queued before it are dropped
+    generation: AtomicU64,
+    pending: AtomicUsize,
+    jobs: mpsc::UnboundedSender<Job>,
+    shutdown: Notify,
+}
+
+impl Daemon {
+    async fn handle(&self, request: DaemonRequest) -> DaemonResponse {
+        let handled = match request {
+            DaemonRequest::Speak(request) => {
+                self.stop();
+                self.enqueue(request).await
+            }
+            DaemonRequest::Queue(request) => self.enqueue(request).await,
+            DaemonRequest::Stop => {
+                self.stop();
+                Ok(())
+            }
+            DaemonRequest::Pause => match self.player.is_playing() {
+                true => self.player.pause().map_err(|e| e.to_string()),
+                false => Ok(()),
+            },
+            DaemonRequest::Resume => {
+                self.player.play();
+                Ok(())
+            }
+            DaemonRequest::Status => Ok(()),
+            DaemonRequest::Shutdown => {
+                self.stop();
+                self.shutdown.notify_one();
+                Ok(())
+            }
+        };
+        self.status(handled)
+    }
+
+    fn status(&self, handled: Result<(), String>) -> DaemonResponse {
+        DaemonResponse {
+            ok: handled.is_ok(),
+            error: handled.err(),
+            playing: self.player.is_playing(),
+            paused: self.player.is_paused(),
+            pending: self.pending.load(Ordering::SeqCst),
+        }
+    }
+
+    fn stop(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.player.stop();
+    }
+
+    fn is_stale(&self, generation: u64) -> bool {
+        self.generation.load(Ordering::SeqCst) != generation
+    }
+
+    async fn enqueue(&self, request: SpeechRequest) -> Result<(), String> {
+        if VoiceManager::get_engine_for_voice(&request.voice).is_err() {
+            return Err(format!("Invalid voice style '{}'", request.voice));
+        }
+        if !(0.5..=2.0).contains(&request.speed) {
+            return Err("Speed must be between 0.5 and 2.0".to_string());
+        }
+
+        let wait = request.wait;
+        let (done, finished) = oneshot::channel();
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        let job = Job {
+            request,
+            generation: self.generation.load(Ordering::SeqCst),
+            done,
+        };
+        if self.jobs.send(job).is_err() {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            return Err("Daemon is shutting down".to_string());
+        }
+
+        match wait {
+            true => finished.await.unwrap_or(Ok(())),
+            false => Ok(()),
+        }
+    }
+
+    async fn run_jobs(self: Arc<Self>, mut jobs: mpsc::UnboundedReceiver<Job>) {
+        while let Some(job) = jobs.recv().await {
+            let read = self.read(&job).await.map_err(|e| e.to_string());
+            if let Err(e) = &read {
+                eprintln!("Failed to read text: {}", e);
+            }
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            let _ = job.done.send(read);
+        }
+    }
+
+    // Sentences are queued for gapless playback a few ahead, like the CLI reads them.
+    // Superseded jobs don't start synthesizing, they would only hold up the engine
+    async fn read(&self, job: &Job) -> EngineResult<()> {
+        let sentences = text::split_text_into_sentences(&job.request.text);
+        play_sentences(
+            tokio_stream::iter(sentences),
+            &job.request.voice,
+            job.request.speed,
+            self.player.clone(),
+            || self.is_stale(job.generation),
+        )
+        .await
+    }
+
+    async fn serve_connection(self: Arc<Self>, stream: UnixStream) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str::<DaemonRequest>(&line) {
+                Ok(request) => self.handle(request).await,
+                Err(e) => self.status(Err(format!("Invalid request: {}", e))),
+            };
+            let mut json = serde_json::to_string(&response)?;
+            json.push('\n');
+            writer.write_all(json.as_bytes()).await?;
+        }
+        Ok(())
+    }
+}
+
+// Listens on the socket until a shutdown request, Ctrl+C or SIGTERM. Engines have to be
+// initialized before, the daemon only reads with the voices they provide
+pub async fn serve(socket_path: &Path, output: AudioOutput) -> EngineResult<()> {
+    if socket_path.exists() {
+        if is_running(socket_path).await {
+            let err_msg = format!("A daemon is already listening on {}", socket_path.display());
+            return Err(err_msg.into());
+        }
+        // Left behind by a daemon that didn't shut down cleanly
+        std::fs::remove_file(socket_path)?;
+    }
+    // Anyone able to connect could have text read aloud, so only the owner may
+    if let Some(parent) = socket_path.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(parent)?;
+    }
+    let listener = UnixListener::bind(socket_path)?;
+    std::fs::set_permissions(socket_path, Permissions::from_mode(0o600))?;
+
+    let (jobs, receiver) = mpsc::unbounded_channel();
+    let daemon = Arc::new(Daemon {
+        player: Arc::new(AudioPlayer::new(output)),
+        generation: AtomicU64::new(0),
+        pending: AtomicUsize::new(0),
+        jobs,
+        shutdown: Notify::new(),
+    });
+    let worker = tokio::spawn(daemon.clone().run_jobs(receiver));
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(daemon.clone().serve_connection(stream));
+                }
+                Err(e) => eprintln!("Failed to accept connection: {}", e),
+            },
+            _ = daemon.shutdown.notified() => break,
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+    }
+
+    worker.abort();
+    daemon.stop();
+    std::fs::remove_file(socket_path)?;
+    Ok(())
+}
+
+pub async fn is_running(socket_path: &Path) -> bool {
+    UnixStream::connect(socket_path).await.is_ok()
+}
+
+// Sends a request to the daemon listening on the socket, errors it answers with included
+pub async fn send_request(
+    socket_path: &Path,
+    request: &DaemonRequest,
+) -> EngineResult<DaemonResponse> {
+    let stream = UnixStream::connect(socket_path).await?;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut json = serde_json::to_string(request)?;
+    json.push('\n');
+    writer.write_all(json.as_bytes()).await?;
+
+    let line = BufReader::new(reader)
+        .lines()
+        .next_line()
+        .await?
+        .ok_or("Daemon closed the connection")?;
+    let response = serde_json::from_str::<DaemonResponse>(&line)?;
+    match response.error {
+        Some(error) => Err(error.into()),
+        None => Ok(response),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::mock_engine::{mock_engine, MockEngine};
+    use crate::utils::audio_player::Pacing;
+
+    #[test]
+    fn test_request_format() {
+        let request = r#"{"command":"speak","text":"Hello there.","voice":"af_heart"}"#;
+        assert_eq!(
+            serde_json::from_str::<DaemonRequest>(request).unwrap(),
+            DaemonRequest::Speak(SpeechRequest {
+                text: "Hello there.".to_string(),
+                voice: "af_heart".to_string(),
+                speed: 1.0,
+                wait: false,
+            })
+        );
+        assert_eq!(
+            serde_json::from_str::<DaemonRequest>(r#"{"command":"stop"}"#).unwrap(),
+            DaemonRequest::Stop
+        );
+        assert!(serde_json::from_str::<DaemonRequest>(r#"{"command":"sing"}"#).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_daemon_reads_queued_text() {
+        let engine = mock_engine();
+        let voice = format!("{}daemon", MockEngine::VOICE_PREFIX);
+        let socket_path =
+            std::env::temp_dir().join(format!("fox-reader-test-{}.sock", std::process::id()));
+        let daemon = tokio::spawn({
+            let socket_path = socket_path.clone();
+            async move { serve(&socket_path, AudioOutput::Null(Pacing::Instant)).await }
+        });
+        while !is_running(&socket_path).await {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let mode = std::fs::metadata(&socket_path)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let queue = |text: &str| {
+            DaemonRequest::Queue(SpeechRequest {
+                text: text.to_string(),
+                voice: voice.clone(),
+                speed: 1.0,
+                wait: true,
+            })
+        };
+        let response = send_request(
+            &socket_path,
+            &queue("First sentence here. Second one here."),
+        )
+        .await
+        .unwrap();
+        assert!(response.ok);
+        assert_eq!(response.pending, 0);
+        assert_eq!(
+            engine.calls_for(&voice),
+            vec!["First sentence here.", "Second one here."]
+        );
+
+        let invalid = DaemonRequest::Speak(SpeechRequest {
+            voice: "missing_voice".to_string(),
+            ..match queue("Text") {
+                DaemonRequest::Queue(request) => request,
+                _ => unreachable!(),
+            }
+        });
+        assert!(send_request(&socket_path, &invalid).await.is_err());
+
+        send_request(&socket_path, &DaemonRequest::Shutdown)
+            .await
+            .unwrap();
+        daemon.await.unwrap().unwrap();
+        assert!(!socket_path.exists());
+    }
+}
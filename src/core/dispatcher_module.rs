@@ -0,0 +1,529 @@
+use rodio::buffer::SamplesBuffer;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, Lines},
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+use crate::{
+    core::{
+        speech_dispatcher::DispatcherVoice,
+        speech_engine::EngineResult,
+        voice_manager::{grade_rank, VoiceFilter, VoiceManager},
+    },
+    utils::{
+        audio_effects::MAX_PITCH_SHIFT,
+        audio_player::{AudioOutput, AudioPlayer},
+        ssml::{parse_ssml, SsmlPart},
+        text,
+    },
+};
+
+// Voice settings Speech Dispatcher sends with SET, the voice is picked from them when
+// a message is spoken
+#[derive(Debug, Clone, Default)]
+struct VoiceSettings {
+    synthesis_voice: Option<String>,
+    language: Option<String>,
+    // Like MALE1 or FEMALE2
+    voice_type: Option<String>,
+    speed: f32,
+}
+
+// What the module config sets up, Speech Dispatcher passes its path when starting the module
+#[derive(Debug, Clone, Default)]
+pub struct ModuleConfig {
+    pub default_voice: String,
+    pub voices: Vec<DispatcherVoice>,
+    // Languages with the voice set for them
+    pub language_voices: Vec<(String, String)>,
+}
+
+// Speech Dispatcher output module speaking its protocol over `input` and `output`,
+// until QUIT or the end of the input
+pub async fn run_module<R, W>(
+    input: R,
+    output: W,
+    audio_output: AudioOutput,
+    config: ModuleConfig,
+) -> EngineResult<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let (events, mut replies) = mpsc::unbounded_channel::<String>();
+    // Replies and events of the message being spoken go out in the order they are sent
+    let writer = tokio::spawn(async move {
+        let mut output = output;
+        while let Some(reply) = replies.recv().await {
+            output.write_all(reply.as_bytes()).await?;
+            output.write_all(b"\n").await?;
+            output.flush().await?;
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    let module = Arc::new(Module {
+        player: Arc::new(AudioPlayer::new(audio_output)),
+        settings: Mutex::new(VoiceSettings {
+            speed: 1.0,
+            ..Default::default()
+        }),
+        config,
+        generation: AtomicU64::new(0),
+        pause_requested: AtomicBool::new(false),
+        speaking: Mutex::new(None),
+        events,
+    });
+
+    let mut lines = input.lines();
+    while let Some(line) = lines.next_line().await? {
+        match line.trim_end() {
+            "INIT" => match VoiceManager::get_voices().is_empty() {
+                true => module.reply(
+                    "399-No voices, download the Kokoros model in Fox Reader first\n\
+                     399 ERR CANT INIT MODULE",
+                ),
+                false => module.reply("299-Fox Reader initialized\n299 OK LOADED SUCCESSFULLY"),
+            },
+            "SPEAK" | "CHAR" | "KEY" => {
+                let kind = line.trim_end().to_string();
+                module.reply("202 OK RECEIVING MESSAGE");
+                let message = read_block(&mut lines).await?;
+                module.reply("200 OK SPEAKING");
+                let parts = match kind.as_str() {
+                    "SPEAK" => parse_ssml(&message),
+                    // Keys come as names like shift_a or space
+                    _ => vec![SsmlPart::Text {
+                        text: message.replace('_', " "),
+                        rate: 1.0,
+                    }],
+                };
+                module.speak(parts);
+            }
+            "SOUND_ICON" => {
+                module.reply("202 OK RECEIVING MESSAGE");
+                read_block(&mut lines).await?;
+                module.reply("200 OK SPEAKING");
+                // There are no sounds to play, the icon is only acknowledged
+                module.speak(Vec::new());
+            }
+            "STOP" => module.stop(),
+            "PAUSE" => module.pause_requested.store(true, Ordering::SeqCst),
+            "SET" => {
+                module.reply("203 OK RECEIVING SETTINGS");
+                for line in read_block(&mut lines).await?.lines() {
+                    if let Some((key, value)) = line.split_once('=') {
+                        module.set(key, value);
+                    }
+                }
+                module.reply("203 OK SETTINGS RECEIVED");
+            }
+            // Audio is played by the module itself, so these are only acknowledged
+            "AUDIO" => {
+                module.reply("207 OK RECEIVING AUDIO SETTINGS");
+                read_block(&mut lines).await?;
+                module.reply("203 OK AUDIO INITIALIZED");
+            }
+            "LOGLEVEL" => {
+                module.reply("207 OK RECEIVING LOGLEVEL SETTINGS");
+                read_block(&mut lines).await?;
+                module.reply("203 OK LOGLEVEL SET");
+            }
+            "LIST VOICES" => {
+                let mut reply = VoiceManager::get_voice_rows()
+                    .into_iter()
+                    .filter(|voice| VoiceManager::get_engine_for_voice(&voice.key).is_ok())
+                    .map(|voice| format!("200-{}\t{}\tnone\n", voice.key, voice.language.code))
+                    .collect::<String>();
+                reply.push_str("200 OK VOICE LIST SENT");
+                module.reply(&reply);
+            }
+            "QUIT" => {
+                module.stop();
+                module.reply("210 OK QUIT");
+                break;
+            }
+            _ => module.reply("300 ERR UNKNOWN COMMAND"),
+        }
+    }
+
+    // Without QUIT the message being spoken is finished first
+    drop(module);
+    writer.await??;
+    Ok(())
+}
+
+// Lines up to the one holding a single dot, lines starting with a dot have it doubled
+async fn read_block<R: AsyncBufRead + Unpin>(lines: &mut Lines<R>) -> EngineResult<String> {
+    let mut block = Vec::new();
+    while let Some(line) = lines.next_line().await? {
+        match line.trim_end_matches('\r') {
+            "." => return Ok(block.join("\n")),
+            line => block.push(line.strip_prefix('.').unwrap_or(line).to_string()),
+        }
+    }
+    Err("Input ended in the middle of a message".into())
+}
+
+struct Module {
+    player: Arc<AudioPlayer>,
+    settings: Mutex<VoiceSettings>,
+    config: ModuleConfig,
+    // Bumped on every stop, the message being spoken stops at the next check
+    generation: AtomicU64,
+    // Speaking stops at the next index mark, where Speech Dispatcher resumes from
+    pause_requested: AtomicBool,
+    // Message being spoken, the next one begins once it has reported how it ended
+    speaking: Mutex<Option<JoinHandle<()>>>,
+    events: mpsc::UnboundedSender<String>,
+}
+
+impl Module {
+    fn reply(&self, reply: &str) {
+        let _ = self.events.send(reply.to_string());
+    }
+
+    fn stop(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.player.stop();
+    }
+
+    fn is_stale(&self, generation: u64) -> bool {
+        self.generation.load(Ordering::SeqCst) != generation
+    }
+
+    // Values come as in the SSIP SET command, rate, pitch and volume from -100 to 100
+    fn set(&self, key: &str, value: &str) {
+        let number = value.parse::<f32>().ok().map(|n| n.clamp(-100.0, 100.0));
+        let mut settings = self.settings.lock().unwrap();
+        match (key, number) {
+            // Rates below 0 slow down to half the speed and above 0 speed up to twice it
+            ("rate", Some(rate)) if rate < 0.0 => settings.speed = 1.0 + rate / 200.0,
+            ("rate", Some(rate)) => settings.speed = 1.0 + rate / 100.0,
+            ("pitch", Some(pitch)) => self.player.set_pitch(pitch / 100.0 * MAX_PITCH_SHIFT),
+            ("volume", Some(volume)) => self.player.set_volume(1.0 + volume / 100.0),
+            ("synthesis_voice", _) => {
+                settings.synthesis_voice = Some(value.to_string()).filter(|v| v != "NULL")
+            }
+            ("language", _) => settings.language = Some(value.to_string()).filter(|v| v != "NULL"),
+            ("voice", _) => {
+                settings.voice_type = Some(value.to_uppercase()).filter(|v| v != "NULL")
+            }
+            _ => {}
+        }
+    }
+
+    // The synthesis voice when it's one of ours. Speech Dispatcher sends a language and
+    // voice type with every message, the voice set for the language comes first, then the
+    // default voice when it speaks the language, the voice the module config lists for the
+    // language and voice type, and the best voice of the language and gender or of the
+    // language alone. Falls back to the default voice
+    fn voice(&self) -> String {
+        let settings = self.settings.lock().unwrap().clone();
+        let is_available = |voice: &str| VoiceManager::get_engine_for_voice(voice).is_ok();
+        if let Some(voice) = settings.synthesis_voice.filter(|voice| is_available(voice)) {
+            return voice;
+        }
+        let default_voice = self.config.default_voice.clone();
+        let Some(language) = settings.language.clone() else {
+            let configured = self.config.voices.iter().find(|voice| {
+                settings.voice_type.as_ref() == Some(&voice.voice_type) && is_available(&voice.name)
+            });
+            return configured.map_or(default_voice, |voice| voice.name.clone());
+        };
+
+        let mut rows = VoiceManager::get_voice_rows();
+        rows.sort_by_key(|voice| std::cmp::Reverse(grade_rank(&voice.quality)));
+        let set_for_language = self
+            .config
+            .language_voices
+            .iter()
+            .filter(|(code, _)| languages_match(code, &language))
+            .map(|(_, voice)| voice.clone());
+        let default_for_language = rows
+            .iter()
+            .find(|voice| voice.key == default_voice)
+            .filter(|voice| languages_match(&voice.language.code, &language))
+            .map(|voice| voice.key.clone());
+        let configured = self
+            .config
+            .voices
+            .iter()
+            .filter(|voice| {
+                languages_match(&voice.language, &language)
+                    && settings
+                        .voice_type
+                        .as_ref()
+                        .is_none_or(|voice_type| *voice_type == voice.voice_type)
+            })
+            .map(|voice| voice.name.clone());
+        // Voice filters only match locales of a language asked for, not the other way around
+        let gender_filter = VoiceFilter {
+            gender: match settings.voice_type.as_deref() {
+                Some(v) if v.contains("FEMALE") => Some('f'),
+                Some(v) if v.contains("MALE") => Some('m'),
+                _ => None,
+            },
+            ..Default::default()
+        };
+        let of_language = rows
+            .iter()
+            .filter(|voice| languages_match(&voice.language.code, &language));
+        let by_gender = of_language
+            .clone()
+            .filter(|voice| gender_filter.matches(voice));
+
+        set_for_language
+            .chain(default_for_language)
+            .chain(configured)
+            .chain(by_gender.chain(of_language).map(|voice| voice.key.clone()))
+            .find(|voice| is_available(voice))
+            .unwrap_or(default_voice)
+    }
+
+    fn speak(self: &Arc<Self>, parts: Vec<SsmlPart>) {
+        // A new message replaces the one being spoken
+        self.stop();
+        self.pause_requested.store(false, Ordering::SeqCst);
+        let generation = self.generation.load(Ordering::SeqCst);
+        let (voice, speed) = (self.voice(), self.settings.lock().unwrap().speed);
+
+        let module = self.clone();
+        let mut speaking = self.speaking.lock().unwrap();
+        let previous = speaking.take();
+        *speaking = Some(tokio::spawn(async move {
+            // Speech Dispatcher takes the stopped message's event for this one otherwise
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+            module.reply("701 BEGIN");
+            let event = match module.read(parts, &voice, speed, generation).await {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("Failed to speak message: {}", e);
+                    "702 END"
+                }
+            };
+            module.reply(event);
+        }));
+    }
+
+    // Plays the text parts and breaks while reporting the marks between them, sentences are
+    // synthesized one ahead of the one being played. Returns the event that ended speaking
+    async fn read(
+        &self,
+        parts: Vec<SsmlPart>,
+        voice: &str,
+        speed: f32,
+        generation: u64,
+    ) -> EngineResult<&'static str> {
+        let engine = VoiceManager::get_engine_for_voice(voice)?;
+        let synthesize = |(sentence, speed): (String, f32)| {
+            let (engine, voice) = (engine.clone(), voice.to_string());
+            tokio::task::spawn_blocking(move || engine.generate_speech(&sentence, &voice, speed))
+        };
+
+        // <prosody rate> scales the rate Speech Dispatcher set
+        let part_sentences = parts
+            .iter()
+            .map(|part| match part {
+                SsmlPart::Text { text, rate } => split_sentences(text)
+                    .into_iter()
+                    .map(|sentence| (sentence, (speed * rate).clamp(0.5, 2.0)))
+                    .collect(),
+                _ => Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        let mut sentences = part_sentences.clone().into_iter().flatten();
+        let mut next = sentences.next().map(synthesize);
+
+        for (part, part_sentences) in parts.iter().zip(part_sentences) {
+            if self.is_stale(generation) {
+                return Ok("703 STOPPED");
+            }
+            match part {
+                SsmlPart::Mark(name) => {
+                    self.reply(&format!("700-{}\n700 INDEX MARK", name));
+                    if self.pause_requested.swap(false, Ordering::SeqCst) {
+                        return Ok("704 PAUSED");
+                    }
+                }
+                // Silence goes through the player too, so stopping cuts it short
+                SsmlPart::Break(pause) => {
+                    let sample_rate = engine.sample_rate();
+                    let samples = (pause.as_secs_f64() * sample_rate as f64) as usize;
+                    let silence = SamplesBuffer::new(1, sample_rate, vec![0.0; samples]);
+                    let player = self.player.clone();
+                    tokio::task::spawn_blocking(move || player.play_audio(silence)).await??;
+                }
+                SsmlPart::Text { .. } => {
+                    for _ in part_sentences {
+                        let Some(synthesis) = next.take() else {
+                            break;
+                        };
+                        let audio = synthesis.await??;
+                        next = sentences.next().map(synthesize);
+                        if self.is_stale(generation) {
+                            return Ok("703 STOPPED");
+                        }
+                        let player = self.player.clone();
+                        tokio::task::spawn_blocking(move || player.play_audio(audio)).await??;
+                    }
+                }
+            }
+        }
+
+        match self.is_stale(generation) {
+            true => Ok("703 STOPPED"),
+            false => Ok("702 END"),
+        }
+    }
+}
+
+// Screen readers send short phrases, which are spoken whole instead of being dropped
+// by the sentence splitter
+fn split_sentences(text: &str) -> Vec<String> {
+    match text::split_text_into_sentences(text) {
+        sentences if sentences.is_empty() => vec![text.to_string()],
+        sentences => sentences,
+    }
+}
+
+// Locales match themselves and their language, like fr-FR and fr
+fn languages_match(a: &str, b: &str) -> bool {
+    let normalize = |code: &str| code.to_lowercase().replace('_', "-");
+    let (a, b) = (normalize(a), normalize(b));
+    let language = |code: &str| code.split('-').next().unwrap_or("").to_string();
+    a == b || (!a.contains('-') || !b.contains('-')) && language(&a) == language(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::mock_engine::{mock_engine, MockEngine, SLOW_MARKER};
+    use crate::utils::audio_player::Pacing;
+    use tokio::io::{AsyncReadExt, BufReader};
+
+    // Sends the commands and returns everything the module wrote until the input ended
+    async fn run_session(commands: &str) -> String {
+        let (input, mut client) = tokio::io::duplex(64 * 1024);
+        let (output, mut module_output) = tokio::io::duplex(64 * 1024);
+        client.write_all(commands.as_bytes()).await.unwrap();
+        drop(client);
+
+        let module = tokio::spawn(run_module(
+            BufReader::new(input),
+            output,
+            AudioOutput::Null(Pacing::Instant),
+            ModuleConfig {
+                default_voice: "unused".to_string(),
+                voices: vec![DispatcherVoice {
+                    language: "pl".to_string(),
+                    voice_type: "MALE2".to_string(),
+                    name: format!("{}configured", MockEngine::VOICE_PREFIX),
+                }],
+                language_voices: vec![(
+                    "fr".to_string(),
+                    format!("{}french", MockEngine::VOICE_PREFIX),
+                )],
+            },
+        ));
+        let mut written = String::new();
+        module_output.read_to_string(&mut written).await.unwrap();
+        module.await.unwrap().unwrap();
+        written
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_speaks_message_with_marks() {
+        let engine = mock_engine();
+        let voice = format!("{}module", MockEngine::VOICE_PREFIX);
+        let written = run_session(&format!(
+            "INIT\nSET\nsynthesis_voice={}\nrate=100\n.\nSPEAK\n\
+             <speak><mark name=\"a\"/>Hello.<break time=\"10ms\"/><mark name=\"b\"/>\
+             <prosody rate=\"50%\">How are you today?</prosody></speak>\n.\n",
+            voice
+        ))
+        .await;
+
+        // Without QUIT the message is spoken to the end
+        let written = written.lines().collect::<Vec<_>>();
+        assert_eq!(
+            &written[..4],
+            [
+                "299-Fox Reader initialized",
+                "299 OK LOADED SUCCESSFULLY",
+                "203 OK RECEIVING SETTINGS",
+                "203 OK SETTINGS RECEIVED",
+            ]
+        );
+        assert_eq!(
+            &written[4..6],
+            ["202 OK RECEIVING MESSAGE", "200 OK SPEAKING"]
+        );
+        assert_eq!(written[6], "701 BEGIN");
+        assert!(written.contains(&"700-a") && written.contains(&"700-b"));
+        assert_eq!(written.last(), Some(&"702 END"));
+        assert_eq!(
+            engine.speed_calls_for(&voice),
+            vec![
+                ("Hello.".to_string(), 2.0),
+                ("How are you today?".to_string(), 1.0)
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_voice_picked_from_config() {
+        let engine = mock_engine();
+        let voice = format!("{}configured", MockEngine::VOICE_PREFIX);
+        run_session("SET\nlanguage=pl\nvoice=male2\n.\nSPEAK\nDzień dobry.\n.\n").await;
+        assert_eq!(engine.calls_for(&voice), vec!["Dzień dobry."]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_voice_set_for_language() {
+        let engine = mock_engine();
+        let voice = format!("{}french", MockEngine::VOICE_PREFIX);
+        run_session("SET\nlanguage=fr-FR\nvoice=male1\n.\nSPEAK\nBonjour.\n.\n").await;
+        assert_eq!(engine.calls_for(&voice), vec!["Bonjour."]);
+        assert!(languages_match("fr_FR", "fr") && !languages_match("en-GB", "en-US"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_stop_interrupts_speaking() {
+        mock_engine();
+        let voice = format!("{}module_stop", MockEngine::VOICE_PREFIX);
+        let written = run_session(&format!(
+            "SET\nsynthesis_voice={}\n.\nSPEAK\n{} A slow sentence.\n.\nSTOP\nQUIT\n",
+            voice, SLOW_MARKER
+        ))
+        .await;
+
+        assert!(written.contains("703 STOPPED\n"));
+        assert!(!written.contains("702 END"));
+        assert!(written.contains("210 OK QUIT\n"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_replaced_message_ends_before_next_begins() {
+        mock_engine();
+        let voice = format!("{}module_replace", MockEngine::VOICE_PREFIX);
+        let written = run_session(&format!(
+            "SET\nsynthesis_voice={}\n.\nSPEAK\n{} A slow sentence.\n.\nSPEAK\nNext.\n.\n",
+            voice, SLOW_MARKER
+        ))
+        .await;
+
+        let events = written
+            .lines()
+            .filter(|line| line.starts_with("70"))
+            .collect::<Vec<_>>();
+        assert_eq!(events, ["701 BEGIN", "703 STOPPED", "701 BEGIN", "702 END"]);
+    }
+}
@@ -1,9 +1,14 @@
 use kokoros::tts::koko::{InitConfig, TTSKoko, TTSOpts};
 use rodio::buffer::SamplesBuffer;
 use std::error::Error;
-use std::sync::Arc;
-use tokio::sync::Mutex;
-
+use std::sync::{Arc, Mutex};
+
+use crate::core::{
+    speech_engine::{EngineResult, SpeechEngine},
+    voice_catalog::VoiceCatalog,
+    voice_manager::VoiceManager,
+    voice_presets::{VoicePreset, VoicePresets},
+};
 use crate::paths::voice_config;
 
 pub struct KokorosTTS {
@@ -12,6 +17,8 @@ pub struct KokorosTTS {
 }
 
 impl KokorosTTS {
+    pub const ENGINE_ID: &'static str = "kokoros";
+
     pub async fn new() -> Result<Self, Box<dyn Error + Send + Sync>> {
         crate::utils::espeak_handler::EspeakHandler::set_espeak_environment();
 
@@ -29,16 +36,69 @@ impl KokorosTTS {
         })
     }
 
-    pub async fn generate_speech(
+    // Voices of the catalog the voices file has
+    pub fn get_available_voices() -> Vec<String> {
+        VoiceCatalog::get().available_voices()
+    }
+
+    // Presets are read as the mix of their styles in the language of the main one, at their
+    // speed relative to the one asked for
+    fn resolve_voice(voice_style: &str, speed: f32) -> (String, String, f32) {
+        match VoicePresets::find(voice_style) {
+            Some(preset) => (
+                preset.kokoros_style(),
+                Self::espeak_language(preset.main_style()),
+                speed * preset.speed,
+            ),
+            None => (
+                voice_style.to_string(),
+                Self::espeak_language(voice_style),
+                speed,
+            ),
+        }
+    }
+
+    // Text is phonemized by espeak-ng in the language of the voice, by the name espeak-ng
+    // knows it under
+    fn espeak_language(voice_style: &str) -> String {
+        match VoiceCatalog::get().language(voice_style) {
+            Some(language) => language
+                .espeak
+                .clone()
+                .unwrap_or_else(|| language.code.to_lowercase()),
+            None => VoiceManager::get_voice_language(voice_style).to_lowercase(),
+        }
+    }
+}
+
+impl SpeechEngine for KokorosTTS {
+    fn id(&self) -> &'static str {
+        Self::ENGINE_ID
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn available_voices(&self) -> Vec<String> {
+        let mut voices = Self::get_available_voices();
+        voices.extend(VoicePresets::load().iter().map(VoicePreset::key));
+        voices
+    }
+
+    fn generate_speech(
         &self,
         text: &str,
         voice_style: &str,
         speed: f32,
-    ) -> Result<SamplesBuffer<f32>, Box<dyn Error + Send + Sync>> {
-        let tts_engine = self.tts_engine.lock().await;
-
+    ) -> EngineResult<SamplesBuffer<f32>> {
+        let (style, language, speed) = Self::resolve_voice(voice_style, speed);
+        let tts_engine = self
+            .tts_engine
+            .lock()
+            .map_err(|_| "Kokoros TTS engine lock poisoned")?;
         let audio_data = tts_engine
-            .tts_raw_audio(text, "en", voice_style, speed, Some(0))
+            .tts_raw_audio(text, &language, &style, speed, Some(0))
             .map_err(|e| format!("TTS generation failed: {}", e))?;
 
         let samples_buffer = SamplesBuffer::new(1, self.sample_rate, audio_data);
@@ -46,19 +106,22 @@ impl KokorosTTS {
         Ok(samples_buffer)
     }
 
-    pub async fn save_speech_to_file(
+    fn save_speech_to_file(
         &self,
         text: &str,
         voice_style: &str,
         speed: f32,
         output_path: &str,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let tts_engine = self.tts_engine.lock().await;
-
+    ) -> EngineResult<()> {
+        let (style, language, speed) = Self::resolve_voice(voice_style, speed);
+        let tts_engine = self
+            .tts_engine
+            .lock()
+            .map_err(|_| "Kokoros TTS engine lock poisoned")?;
         let opts = TTSOpts {
             txt: text,
-            lan: "en",
-            style_name: voice_style,
+            lan: &language,
+            style_name: &style,
             save_path: output_path,
             mono: true,
             speed,
@@ -73,72 +136,4 @@ impl KokorosTTS {
 
         Ok(())
     }
-
-    pub fn get_available_voices() -> Vec<String> {
-        vec![
-            // American English (🇺🇸)
-            "af_heart".to_string(),
-            "af_alloy".to_string(),
-            "af_aoede".to_string(),
-            "af_bella".to_string(),
-            "af_jessica".to_string(),
-            "af_kore".to_string(),
-            "af_nicole".to_string(),
-            "af_nova".to_string(),
-            "af_river".to_string(),
-            "af_sarah".to_string(),
-            "af_sky".to_string(),
-            "am_adam".to_string(),
-            "am_echo".to_string(),
-            "am_eric".to_string(),
-            "am_fenrir".to_string(),
-            "am_liam".to_string(),
-            "am_michael".to_string(),
-            "am_onyx".to_string(),
-            "am_puck".to_string(),
-            "am_santa".to_string(),
-            // British English (🇬🇧)
-            "bf_alice".to_string(),
-            "bf_emma".to_string(),
-            "bf_isabella".to_string(),
-            "bf_lily".to_string(),
-            "bm_daniel".to_string(),
-            "bm_fable".to_string(),
-            "bm_george".to_string(),
-            "bm_lewis".to_string(),
-            // Japanese (🇯🇵)
-            "jf_alpha".to_string(),
-            "jf_gongitsune".to_string(),
-            "jf_nezumi".to_string(),
-            "jf_tebukuro".to_string(),
-            "jm_kumo".to_string(),
-            // Mandarin Chinese (🇨🇳)
-            "zf_xiaobei".to_string(),
-            "zf_xiaoni".to_string(),
-            "zf_xiaoxiao".to_string(),
-            "zf_xiaoyi".to_string(),
-            "zm_yunjian".to_string(),
-            "zm_yunxi".to_string(),
-            "zm_yunxia".to_string(),
-            "zm_yunyang".to_string(),
-            // Spanish (🇪🇸)
-            "ef_dora".to_string(),
-            "em_alex".to_string(),
-            "em_santa".to_string(),
-            // French (🇫🇷)
-            "ff_siwis".to_string(),
-            // Hindi (🇮🇳)
-            "hf_alpha".to_string(),
-            "hf_beta".to_string(),
-            "hm_omega".to_string(),
-            "hm_psi".to_string(),
-            // Italian (🇮🇹)
-            "if_sara".to_string(),
-            "im_nicola".to_string(),
-            // Brazilian Portuguese (🇧🇷)
-            "pf_dora".to_string(),
-            "pm_alex".to_string(),
-            "pm_santa".to_string(),
-        ]
-    }
 }
@@ -0,0 +1,140 @@
+use rodio::buffer::SamplesBuffer;
+use std::{
+    f32::consts::PI,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use super::{
+    speech_engine::{EngineResult, SpeechEngine},
+    voice_manager::VoiceManager,
+};
+
+pub const SAMPLE_RATE: u32 = 8000;
+// 2ms of audio per character, long enough to interact with and short enough to keep tests fast
+pub const SAMPLES_PER_CHAR: usize = 16;
+pub const FAIL_MARKER: &str = "[fail]";
+// Blocks containing this marker take `SLOW_SYNTHESIS` to generate
+pub const SLOW_MARKER: &str = "[slow]";
+pub const SLOW_SYNTHESIS: std::time::Duration = std::time::Duration::from_millis(300);
+
+const TONE_FREQUENCY: f32 = 440.0;
+
+static MOCK_ENGINE: LazyLock<Arc<MockEngine>> = LazyLock::new(|| {
+    let engine = Arc::new(MockEngine::default());
+    VoiceManager::register_engine(engine.clone());
+    engine
+});
+
+// Registers the mock engine on first use, every voice starting with `mock_` is served by it
+pub fn mock_engine() -> Arc<MockEngine> {
+    MOCK_ENGINE.clone()
+}
+
+#[derive(Default)]
+pub struct MockEngine {
+    calls: Mutex<Vec<(String, String, f32)>>,
+}
+
+impl MockEngine {
+    pub const ENGINE_ID: &'static str = "mock";
+    pub const VOICE_PREFIX: &'static str = "mock_";
+
+    // Letters produce a sine tone and everything else silence, so the length of
+    // the buffer is always proportional to the text
+    pub fn synthesize(text: &str, speed: f32) -> Vec<f32> {
+        let samples_per_char = (SAMPLES_PER_CHAR as f32 / speed.max(0.1)).round() as usize;
+        let mut samples = Vec::with_capacity(text.chars().count() * samples_per_char);
+
+        for c in text.chars() {
+            for i in 0..samples_per_char {
+                if c.is_alphanumeric() {
+                    let t = i as f32 / SAMPLE_RATE as f32;
+                    samples.push((2.0 * PI * TONE_FREQUENCY * t).sin() * 0.5);
+                } else {
+                    samples.push(0.0);
+                }
+            }
+        }
+
+        samples
+    }
+
+    pub fn duration_of(text: &str) -> std::time::Duration {
+        let samples = text.chars().count() * SAMPLES_PER_CHAR;
+        std::time::Duration::from_secs_f64(samples as f64 / SAMPLE_RATE as f64)
+    }
+
+    pub fn calls_for(&self, voice_style: &str) -> Vec<String> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(voice, _, _)| voice == voice_style)
+            .map(|(_, text, _)| text.clone())
+            .collect()
+    }
+
+    pub fn speed_calls_for(&self, voice_style: &str) -> Vec<(String, f32)> {
+        self.calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(voice, _, _)| voice == voice_style)
+            .map(|(_, text, speed)| (text.clone(), *speed))
+            .collect()
+    }
+}
+
+impl SpeechEngine for MockEngine {
+    fn id(&self) -> &'static str {
+        Self::ENGINE_ID
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn available_voices(&self) -> Vec<String> {
+        vec![format!("{}voice", Self::VOICE_PREFIX)]
+    }
+
+    fn has_voice(&self, voice_style: &str) -> bool {
+        voice_style.starts_with(Self::VOICE_PREFIX)
+    }
+
+    fn generate_speech(
+        &self,
+        text: &str,
+        voice_style: &str,
+        speed: f32,
+    ) -> EngineResult<SamplesBuffer<f32>> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push((voice_style.to_string(), text.to_string(), speed));
+
+        if text.contains(SLOW_MARKER) {
+            std::thread::sleep(SLOW_SYNTHESIS);
+        }
+
+        if text.contains(FAIL_MARKER) {
+            return Err(format!("Mock engine failed on: {}", text).into());
+        }
+
+        Ok(SamplesBuffer::new(
+            1,
+            SAMPLE_RATE,
+            Self::synthesize(text, speed),
+        ))
+    }
+
+    fn save_speech_to_file(
+        &self,
+        _text: &str,
+        _voice_style: &str,
+        _speed: f32,
+        _output_path: &str,
+    ) -> EngineResult<()> {
+        Err("Mock engine does not support saving to file".into())
+    }
+}
@@ -1,6 +1,18 @@
+pub mod audio_cache;
+pub mod audiobook;
+pub mod daemon;
+pub mod dispatcher_module;
 pub mod kokoros_manager;
 pub mod llm_manager;
+#[cfg(test)]
+pub mod mock_engine;
 pub mod runtime;
+pub mod sentence_player;
 pub mod speech_dispatcher;
+pub mod speech_engine;
+pub mod speech_server;
 pub mod tts;
+pub mod voice_catalog;
 pub mod voice_manager;
+pub mod voice_presets;
+pub mod word_timing;
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::{
+    core::{speech_engine::EngineResult, voice_manager::VoiceManager},
+    utils::audio_player::AudioPlayer,
+};
+
+// Sentences queued ahead of the one being played, the next one gets synthesized in time
+// while long texts don't end up in memory as a whole
+const QUEUED_SENTENCES: usize = 2;
+
+// Each sentence is queued for gapless playback as soon as it is synthesized, so playback
+// starts with the first one while the rest of the text may still be coming in. Returns once
+// everything has been played, or as soon as `is_stopped` tells so after the player stopped
+pub async fn play_sentences(
+    mut sentences: impl Stream<Item = String> + Unpin,
+    voice_style: &str,
+    speed: f32,
+    player: Arc<AudioPlayer>,
+    is_stopped: impl Fn() -> bool,
+) -> EngineResult<()> {
+    let mut audible = player.subscribe_audible();
+    let mut queued = 0;
+    while let Some(sentence) = sentences.next().await {
+        // Counted by the blocks still to finish, nothing is audible before the first one
+        // starts and whenever synthesis falls behind
+        audible
+            .wait_for(|_| is_stopped() || player.queued_blocks() <= QUEUED_SENTENCES)
+            .await?;
+        if is_stopped() {
+            return Ok(());
+        }
+        let audio = VoiceManager::generate_speech(&sentence, voice_style, speed)
+            .await
+            .map_err(|e| format!("Failed to generate speech: {}", e))?;
+        // Synthesis takes a while, the player may have been stopped in the meantime
+        if is_stopped() {
+            return Ok(());
+        }
+        player.enqueue(queued, audio, Vec::new())?;
+        queued += 1;
+    }
+    tokio::task::spawn_blocking(move || player.sleep_until_end()).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::mock_engine::{mock_engine, MockEngine};
+    use crate::utils::audio_player::{AudioOutput, Pacing};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_sentences_play_in_order() {
+        let engine = mock_engine();
+        let voice = format!("{}sentences", MockEngine::VOICE_PREFIX);
+        let path =
+            std::env::temp_dir().join(format!("fox-reader-sentences-{}.wav", std::process::id()));
+        let player = Arc::new(AudioPlayer::new(AudioOutput::Wav(path.clone())));
+
+        let sentences = ["One.", "Two here.", "Three is here.", "Four."].map(String::from);
+        let sentence_stream = tokio_stream::iter(sentences.clone());
+        play_sentences(sentence_stream, &voice, 1.0, player.clone(), || false)
+            .await
+            .unwrap();
+        assert_eq!(engine.calls_for(&voice), sentences);
+        drop(player);
+
+        // Back to back, without gaps between the sentences
+        let samples = hound::WavReader::open(&path)
+            .unwrap()
+            .into_samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let expected = sentences
+            .iter()
+            .flat_map(|sentence| MockEngine::synthesize(sentence, 1.0))
+            .collect::<Vec<_>>();
+        assert_eq!(samples, expected);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_synthesis_stays_ahead_by_a_few_sentences() {
+        let engine = mock_engine();
+        let voice = format!("{}bounded", MockEngine::VOICE_PREFIX);
+        let player = Arc::new(AudioPlayer::new(AudioOutput::Null(Pacing::RealTime)));
+        // Half a second each, while synthesis is instant
+        let sentences = (0..10)
+            .map(|i| format!("{} {}.", i, "word ".repeat(50)))
+            .collect::<Vec<_>>();
+
+        let playing = tokio::spawn({
+            let (voice, player) = (voice.clone(), player.clone());
+            let sentences = tokio_stream::iter(sentences);
+            async move { play_sentences(sentences, &voice, 1.0, player, || false).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert_eq!(engine.calls_for(&voice).len(), QUEUED_SENTENCES + 1);
+        assert_eq!(player.queued_blocks(), QUEUED_SENTENCES + 1);
+
+        playing.abort();
+        player.stop();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_stop_ends_playback() {
+        let engine = mock_engine();
+        let voice = format!("{}stopped", MockEngine::VOICE_PREFIX);
+        let player = Arc::new(AudioPlayer::new(AudioOutput::Null(Pacing::RealTime)));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let sentences = (0..20)
+            .map(|i| format!("Sentence number {} is read.", i))
+            .collect::<Vec<_>>();
+
+        let playing = tokio::spawn({
+            let (voice, player, stopped) = (voice.clone(), player.clone(), stopped.clone());
+            async move {
+                let is_stopped = move || stopped.load(Ordering::SeqCst);
+                play_sentences(
+                    tokio_stream::iter(sentences),
+                    &voice,
+                    1.0,
+                    player,
+                    is_stopped,
+                )
+                .await
+            }
+        });
+        let mut audible = player.subscribe_audible();
+        audible.wait_for(Option::is_some).await.unwrap();
+
+        // Like the chat stops its reply
+        stopped.store(true, Ordering::SeqCst);
+        player.stop();
+        tokio::time::timeout(std::time::Duration::from_secs(1), playing)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let synthesized = engine.calls_for(&voice).len();
+        assert!(synthesized <= QUEUED_SENTENCES + 2);
+        assert!(!player.is_playing());
+    }
+}
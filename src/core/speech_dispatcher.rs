@@ -1,53 +1,209 @@
+use std::collections::HashMap;
 use std::error::Error;
+use std::fs;
 
-use crate::core::voice_manager::VoiceManager;
+use crate::core::voice_manager::{grade_rank, Voice, VoiceManager};
 use crate::{paths::dispatcher_config, utils::file_handler::FileHandler};
 
-const FOX_READER_SCRIPT: &[u8] = include_bytes!("../../scripts/fox-piper.sh");
+const MODULE_NAME: &str = "fox-reader";
+
+// Voice set for a language, written like LanguageVoice "fr" "ff_siwis" in the module config
+const LANGUAGE_VOICE: &str = "LanguageVoice";
+
+// Speech Dispatcher knows three voice types per gender
+const VOICE_TYPES_PER_GENDER: usize = 3;
+
+// Fox Reader only changes the lines between these markers, the rest belongs to the user
+const BLOCK_START: &str = "# >>> Fox Reader >>>";
+const BLOCK_END: &str = "# <<< Fox Reader <<<";
+
+// Header of the whole speechd.conf older versions wrote
+const LEGACY_HEADER: [&str; 3] = [
+    "# Fox Reader",
+    "# Speech Dispatcher Configuration",
+    "# Please do not modify this file as it can cause issues with application",
+];
+
+// Voice Speech Dispatcher can ask the module for by language and voice type, written
+// like AddVoice "en-GB" "FEMALE1" "bf_emma" in the module config
+#[derive(Debug, Clone, PartialEq)]
+pub struct DispatcherVoice {
+    pub language: String,
+    pub voice_type: String,
+    pub name: String,
+}
 
 pub struct SpeechDispatcher {}
 
 impl SpeechDispatcher {
     pub fn init() -> Result<(), Box<dyn Error>> {
+        Self::init_module_binary()?;
         Self::init_speechd_config()?;
         Self::init_module_config()?;
-        Self::init_script()?;
         Ok(())
     }
 
+    // Writes every file of Fox Reader again, the ones left by older versions included
+    pub fn reinstall() -> Result<(), Box<dyn Error>> {
+        FileHandler::remove_file(&dispatcher_config::get_module_config_path())?;
+        FileHandler::remove_file(&dispatcher_config::get_script_path())?;
+        Self::remove_module_binary()?;
+        Self::init()
+    }
+
+    // Takes Fox Reader out of the user's config and removes the files only it uses. The
+    // backup stays when the config has changed since it was taken
+    pub fn uninstall() -> Result<(), Box<dyn Error>> {
+        let config_file = &dispatcher_config::get_config_file_path();
+        if FileHandler::does_file_exist(config_file) {
+            let config = remove_block(&fs::read_to_string(config_file)?);
+            let backup_path = dispatcher_config::get_config_backup_path();
+            match fs::read_to_string(&backup_path) {
+                Ok(backup) => {
+                    fs::write(config_file, &config)?;
+                    if backup.trim() == config.trim() {
+                        fs::remove_file(&backup_path)?;
+                    }
+                }
+                // Created by Fox Reader, so it goes if nothing was added to it
+                Err(_) if config.trim() == base_config().trim() => fs::remove_file(config_file)?,
+                Err(_) => fs::write(config_file, &config)?,
+            }
+        }
+
+        FileHandler::remove_file(&dispatcher_config::get_module_config_path())?;
+        FileHandler::remove_file(&dispatcher_config::get_script_path())?;
+        Self::remove_module_binary()
+    }
+
     fn init_speechd_config() -> Result<(), Box<dyn Error>> {
         let config_file = &dispatcher_config::get_config_file_path();
-        if !FileHandler::does_file_exist(config_file) {
-            FileHandler::ensure_all_paths_exists(config_file)?;
-            FileHandler::save_bytes(config_file, config_template("en-GB").trim().as_bytes())?;
+        let existing = fs::read_to_string(config_file).ok();
+
+        // Only the user's own config is backed up, not one Fox Reader wrote
+        let add_module = format!(r#"AddModule "{}""#, MODULE_NAME);
+        let backup_path = dispatcher_config::get_config_backup_path();
+        if let Some(config) = existing.as_ref().filter(|c| !c.contains(&add_module)) {
+            if !FileHandler::does_file_exist(&backup_path) {
+                fs::write(&backup_path, config)?;
+            }
+        }
+
+        let config = existing.clone().unwrap_or_else(base_config);
+        let merged = merge_block(&config, &speechd_block(&config, existing.is_none()));
+        if existing.as_deref() != Some(merged.as_str()) {
+            FileHandler::save_bytes(config_file, merged.as_bytes())?;
         }
         Ok(())
     }
 
+    // The voice lines follow the voices of the installed version, the default voice and
+    // lines the user added stay as they are
     fn init_module_config() -> Result<(), Box<dyn Error>> {
         let module_path = &dispatcher_config::get_module_config_path();
-        if !FileHandler::does_file_exist(module_path) {
-            FileHandler::save_bytes(module_path, module_template().trim().as_bytes())?;
+        let existing = fs::read_to_string(module_path).ok();
+
+        let mut config = existing.clone().unwrap_or_else(module_template);
+        // Voices of the sd_generic config were named differently, only the default one is kept
+        if config.contains("GenericExecuteSynth") {
+            config = config
+                .lines()
+                .filter(|line| !line.trim_start().starts_with("AddVoice"))
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+        if Self::default_voice_in(&config).is_none() {
+            config = format!(
+                "{}\nDefaultVoice \"{}\"\n",
+                config.trim_end(),
+                first_voice()
+            );
+        }
+        let merged = merge_block(&config, &voice_lines(&VoiceManager::get_voice_rows()));
+        if existing.as_deref() != Some(merged.as_str()) {
+            FileHandler::save_bytes(module_path, merged.as_bytes())?;
         }
         Ok(())
     }
 
-    pub fn init_script() -> Result<(), Box<dyn Error>> {
-        let script_path = &dispatcher_config::get_script_path();
-        if !FileHandler::does_file_exist(script_path) {
-            FileHandler::save_bytes(script_path, FOX_READER_SCRIPT)?;
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                let metadata = std::fs::metadata(script_path)?;
-                let mut perms = metadata.permissions();
-                perms.set_mode(0o755); // rwxr-xr-x
-                std::fs::set_permissions(script_path, perms)?;
-            }
+    // Links the module name to this binary, again when the binary has moved
+    fn init_module_binary() -> Result<(), Box<dyn Error>> {
+        let binary_path = dispatcher_config::get_module_binary_path();
+        let current_exe = std::env::current_exe()?;
+        if fs::read_link(&binary_path).is_ok_and(|target| target == current_exe) {
+            return Ok(());
+        }
+        Self::remove_module_binary()?;
+        FileHandler::ensure_all_paths_exists(&binary_path)?;
+        std::os::unix::fs::symlink(current_exe, &binary_path)?;
+        Ok(())
+    }
+
+    fn remove_module_binary() -> Result<(), Box<dyn Error>> {
+        let binary_path = dispatcher_config::get_module_binary_path();
+        // Broken links don't count as existing files
+        if fs::symlink_metadata(&binary_path).is_ok() {
+            fs::remove_file(&binary_path)?;
         }
         Ok(())
     }
 
+    // Voice set with `set_default_voice`, read by the module when it starts
+    pub fn default_voice(module_config_path: &str) -> Option<String> {
+        FileHandler::get_default_voice_from_config(module_config_path)
+            .ok()
+            .flatten()
+            .map(|voice| voice.trim_matches('"').to_string())
+    }
+
+    fn default_voice_in(config: &str) -> Option<&str> {
+        config
+            .lines()
+            .find(|line| line.trim_start().starts_with("DefaultVoice"))
+            .and_then(|line| line.split_whitespace().nth(1))
+    }
+
+    // Voices of the module config, the ones the user added before the generated ones
+    pub fn configured_voices(module_config_path: &str) -> Vec<DispatcherVoice> {
+        let Ok(config) = fs::read_to_string(module_config_path) else {
+            return Vec::new();
+        };
+        config
+            .lines()
+            .filter(|line| line.trim_start().starts_with("AddVoice"))
+            .filter_map(|line| match quoted_values(line)[..] {
+                [language, voice_type, name, ..] => Some(DispatcherVoice {
+                    language: language.to_string(),
+                    voice_type: voice_type.to_uppercase(),
+                    name: name.to_string(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Languages with the voice set for them, in the order of the module config
+    pub fn language_voices(module_config_path: &str) -> Vec<(String, String)> {
+        let Ok(config) = fs::read_to_string(module_config_path) else {
+            return Vec::new();
+        };
+        config
+            .lines()
+            .filter(|line| line.trim_start().starts_with(LANGUAGE_VOICE))
+            .filter_map(|line| match quoted_values(line)[..] {
+                [language, voice, ..] => Some((language.to_string(), voice.to_string())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn set_language_voice(language: &str, voice: &str) -> Result<(), Box<dyn Error>> {
+        let module_path = &dispatcher_config::get_module_config_path();
+        let config = fs::read_to_string(module_path)?;
+        fs::write(module_path, with_language_voice(&config, language, voice))?;
+        Ok(())
+    }
+
     pub fn set_default_voice(default_voice: &str) -> Result<(), Box<dyn Error>> {
         FileHandler::upsert_value_in_module_config(
             &dispatcher_config::get_module_config_path(),
@@ -57,14 +213,192 @@ impl SpeechDispatcher {
     }
 }
 
+// A user config replaces the system one, so a new one starts as a copy of it
+fn base_config() -> String {
+    fs::read_to_string(dispatcher_config::get_system_config_path())
+        .unwrap_or_else(|_| config_template(&system_language()))
+}
+
+// The locale of the session, like fr-FR for LANG=fr_FR.UTF-8
+fn system_language() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .find(|locale| !locale.is_empty())
+        .map(|locale| {
+            locale
+                .split(['.', '@'])
+                .next()
+                .unwrap_or("")
+                .replace('_', "-")
+        })
+        .filter(|locale| !locale.is_empty() && locale != "C" && locale != "POSIX")
+        .unwrap_or_else(|| "en-GB".to_string())
+}
+
+// Values are the quoted parts, at every odd position between quotes
+fn quoted_values(line: &str) -> Vec<&str> {
+    line.split('"').skip(1).step_by(2).collect()
+}
+
+// Replaces the line of the language, or adds one after the default voice
+fn with_language_voice(config: &str, language: &str, voice: &str) -> String {
+    let line = format!(r#"{} "{}" "{}""#, LANGUAGE_VOICE, language, voice);
+    let mut lines = config.lines().map(str::to_string).collect::<Vec<_>>();
+    let existing = lines.iter().position(|existing| {
+        existing.trim_start().starts_with(LANGUAGE_VOICE)
+            && quoted_values(existing)
+                .first()
+                .is_some_and(|code| code.eq_ignore_ascii_case(language))
+    });
+    match existing {
+        Some(index) => lines[index] = line,
+        None => {
+            let index = lines
+                .iter()
+                .position(|line| line.trim_start().starts_with("DefaultVoice"))
+                .map_or(0, |index| index + 1);
+            lines.insert(index, line);
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+fn speechd_block(config: &str, is_new: bool) -> Vec<String> {
+    let mut block = vec![format!(
+        r#"AddModule "{}" "{}" "fox-reader.conf""#,
+        MODULE_NAME,
+        dispatcher_config::get_module_binary_path()
+    )];
+
+    // A default module the user picked is kept, the block comes last so it wins otherwise
+    let set_default = if config.contains(BLOCK_START) {
+        block_lines(config).any(|line| line.starts_with("DefaultModule"))
+    } else {
+        is_new
+            || config.contains(LEGACY_HEADER[1])
+            || !remove_block(config)
+                .lines()
+                .any(|line| line.trim_start().starts_with("DefaultModule"))
+    };
+    if set_default {
+        block.push(format!(r#"DefaultModule "{}""#, MODULE_NAME));
+    }
+    block
+}
+
+fn block_lines(config: &str) -> impl Iterator<Item = &str> {
+    config
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| *line != BLOCK_START)
+        .take_while(|line| *line != BLOCK_END)
+}
+
+// Replaces the managed block, or adds it at the end
+fn merge_block(config: &str, block: &[String]) -> String {
+    let mut merged = remove_block(config);
+    if !merged.is_empty() {
+        merged.push('\n');
+    }
+    merged.push_str(BLOCK_START);
+    merged.push('\n');
+    for line in block {
+        merged.push_str(line);
+        merged.push('\n');
+    }
+    merged.push_str(BLOCK_END);
+    merged.push('\n');
+    merged
+}
+
+// The config without the managed block and the lines older versions wrote without one
+fn remove_block(config: &str) -> String {
+    let mut in_block = false;
+    let mut lines = Vec::new();
+    for line in config.lines() {
+        match line.trim() {
+            BLOCK_START => in_block = true,
+            BLOCK_END => in_block = false,
+            _ if in_block || is_legacy_line(line) => {}
+            _ => lines.push(line),
+        }
+    }
+
+    let mut config = lines
+        .join("\n")
+        .trim_start_matches('\n')
+        .trim_end()
+        .to_string();
+    if !config.is_empty() {
+        config.push('\n');
+    }
+    config
+}
+
+fn is_legacy_line(line: &str) -> bool {
+    let line = line.trim();
+    let module = format!(r#""{}""#, MODULE_NAME);
+    LEGACY_HEADER.contains(&line)
+        || ["AddModule", "DefaultModule"]
+            .iter()
+            .any(|key| line.starts_with(key) && line.contains(&module))
+        || line.starts_with("GenericExecuteSynth")
+}
+
+// The voice types of each language go to its best graded voices of the gender. None of the
+// voices is a child's, so the child types get the best one of their gender
+fn voice_lines(voices: &[Voice]) -> Vec<String> {
+    let mut voices = voices.iter().collect::<Vec<_>>();
+    // Stable, voices of the same grade keep their order
+    voices.sort_by_key(|voice| {
+        (
+            voice.language.code.clone(),
+            std::cmp::Reverse(grade_rank(&voice.quality)),
+        )
+    });
+
+    let mut counts = HashMap::new();
+    let mut lines =
+        vec!["# Regenerated by Fox Reader, add your own voices outside of this block".to_string()];
+    for voice in voices {
+        // Voice types are by gender, voices that don't tell theirs get none
+        let gender = match VoiceManager::get_voice_gender(&voice.key) {
+            Some('m') => "MALE",
+            Some(_) => "FEMALE",
+            None => continue,
+        };
+        let count = counts
+            .entry((voice.language.code.as_str(), gender))
+            .or_insert(0);
+        *count += 1;
+        if *count > VOICE_TYPES_PER_GENDER {
+            continue;
+        }
+        let add_voice = |voice_type: &str| {
+            format!(
+                r#"AddVoice "{}" "{}" "{}""#,
+                voice.language.code, voice_type, voice.key
+            )
+        };
+        lines.push(add_voice(&format!("{}{}", gender, count)));
+        if *count == 1 {
+            lines.push(add_voice(&format!("CHILD_{}", gender)));
+        }
+    }
+    lines
+}
+
+fn first_voice() -> String {
+    VoiceManager::get_kokoros_voice_rows()
+        .first()
+        .map(|voice| voice.key.clone())
+        .unwrap_or_else(|| "af_heart".to_string())
+}
+
 fn config_template(default_lang: &str) -> String {
     format!(
-        r#"
-# Fox Reader 
-# Speech Dispatcher Configuration
-# Please do not modify this file as it can cause issues with application
-
-# Symbol preprocessing files
+        r#"# Symbol preprocessing files
 SymbolsPreproc "char"
 SymbolsPreprocFile "gender-neutral.dic"
 SymbolsPreprocFile "font-variants.dic"
@@ -73,43 +407,111 @@ SymbolsPreprocFile "emojis.dic"
 SymbolsPreprocFile "orca.dic"
 SymbolsPreprocFile "orca-chars.dic"
 
-AddModule "fox-reader" "sd_generic" "fox-reader.conf"
-
 DefaultLanguage "{}"
-DefaultModule "fox-reader""#,
+"#,
         default_lang
     )
 }
 
 fn module_template() -> String {
-    let voices = VoiceManager::get_kokoros_voice_rows();
-    let add_voice_lines: String = voices
-        .iter()
-        .map(|voice| {
-            let gender = if voice.traits.contains("♂️") {
-                "male1"
-            } else {
-                "female1"
-            };
+    format!(
+        r#"# Speech Dispatcher module configuration of Fox Reader
+
+DefaultVoice "{}"
+"#,
+        first_voice()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_merge_keeps_user_lines() {
+        let user_config = "DefaultLanguage \"pl\"\nDefaultRate 20\n";
+        let merged = merge_block(user_config, &block(&[r#"AddModule "fox-reader" "sd" "c""#]));
+        assert_eq!(
+            merged,
             format!(
-                r#"AddVoice "{}" "{}" "{}""#,
-                format!("{}_{}", voice.language.code, voice.key),
-                gender,
-                voice.key
+                "DefaultLanguage \"pl\"\nDefaultRate 20\n\n{}\nAddModule \"fox-reader\" \"sd\" \"c\"\n{}\n",
+                BLOCK_START, BLOCK_END
             )
-        })
-        .collect::<Vec<_>>()
-        .join("\n");
-    let default_voice = voices.first().unwrap().key.clone();
+        );
 
-    format!(
-        r#"
-GenericExecuteSynth "export DATA='$DATA';export RATE='$RATE';export VOICE='$VOICE';{}"
-DefaultVoice "{}"
+        // Merging again replaces the block instead of adding another
+        let remerged = merge_block(&merged, &block(&["DefaultModule \"fox-reader\""]));
+        assert_eq!(remerged.matches(BLOCK_START).count(), 1);
+        assert!(!remerged.contains("AddModule"));
+        assert_eq!(remove_block(&remerged), user_config);
+    }
 
-{}"#,
-        dispatcher_config::get_script_path(),
-        default_voice,
-        add_voice_lines,
-    )
+    #[test]
+    fn test_legacy_lines_are_replaced() {
+        let legacy = format!(
+            "{}\n{}\n{}\n\nSymbolsPreproc \"char\"\nAddModule \"fox-reader\" \"sd_generic\" \"fox-reader.conf\"\n\nDefaultLanguage \"en-GB\"\nDefaultModule \"fox-reader\"",
+            LEGACY_HEADER[0], LEGACY_HEADER[1], LEGACY_HEADER[2]
+        );
+        assert_eq!(
+            remove_block(&legacy),
+            "SymbolsPreproc \"char\"\n\nDefaultLanguage \"en-GB\"\n"
+        );
+        assert!(speechd_block(&legacy, false)
+            .iter()
+            .any(|line| line.starts_with("DefaultModule")));
+    }
+
+    #[test]
+    fn test_user_default_module_is_kept() {
+        let user_config = "DefaultModule espeak-ng\n";
+        assert_eq!(speechd_block(user_config, false).len(), 1);
+        assert_eq!(speechd_block("DefaultRate 20\n", false).len(), 2);
+
+        // Once merged the block keeps its choice, whatever the rest of the config sets
+        let merged = merge_block(user_config, &speechd_block(user_config, false));
+        assert_eq!(speechd_block(&merged, false).len(), 1);
+        let merged = merge_block("", &speechd_block("", true));
+        assert_eq!(
+            speechd_block(&format!("DefaultModule x\n{}", merged), false).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_voice_types_per_language() {
+        let mut voices = VoiceManager::get_kokoros_voice_rows();
+        let mut unknown = voices[0].clone();
+        unknown.key = "custom".to_string();
+        voices.push(unknown);
+        let lines = voice_lines(&voices);
+        assert!(!lines.iter().any(|line| line.contains(r#""custom""#)));
+        assert!(lines.contains(&r#"AddVoice "fr" "FEMALE1" "ff_siwis""#.to_string()));
+        assert!(lines.contains(&r#"AddVoice "fr" "CHILD_FEMALE" "ff_siwis""#.to_string()));
+        // The best graded voice of a language gets the first type
+        assert!(lines.contains(&r#"AddVoice "en-US" "FEMALE1" "af_heart""#.to_string()));
+
+        let voice_types = lines
+            .iter()
+            .filter_map(|line| quoted_values(line).get(1).copied())
+            .collect::<Vec<_>>();
+        assert!(!voice_types.contains(&"FEMALE4"));
+        assert!(voice_types.contains(&"MALE3"));
+    }
+
+    #[test]
+    fn test_language_voice_is_replaced() {
+        let config = "# Header\nDefaultVoice \"af_heart\"\n";
+        let config = with_language_voice(config, "fr", "ff_siwis");
+        assert_eq!(
+            config,
+            "# Header\nDefaultVoice \"af_heart\"\nLanguageVoice \"fr\" \"ff_siwis\"\n"
+        );
+        let config = with_language_voice(&config, "FR", "other");
+        assert_eq!(config.matches(LANGUAGE_VOICE).count(), 1);
+        assert!(config.contains(r#"LanguageVoice "FR" "other""#));
+    }
 }
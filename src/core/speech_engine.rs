@@ -0,0 +1,33 @@
+use rodio::buffer::SamplesBuffer;
+use std::error::Error;
+
+pub type EngineResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+// Every TTS backend (Kokoros, Piper, espeak-ng, test stubs...) implements this trait
+// and gets registered in `VoiceManager`, which dispatches requests by engine id or voice
+pub trait SpeechEngine: Send + Sync {
+    fn id(&self) -> &'static str;
+
+    fn sample_rate(&self) -> u32;
+
+    fn available_voices(&self) -> Vec<String>;
+
+    fn generate_speech(
+        &self,
+        text: &str,
+        voice_style: &str,
+        speed: f32,
+    ) -> EngineResult<SamplesBuffer<f32>>;
+
+    fn save_speech_to_file(
+        &self,
+        text: &str,
+        voice_style: &str,
+        speed: f32,
+        output_path: &str,
+    ) -> EngineResult<()>;
+
+    fn has_voice(&self, voice_style: &str) -> bool {
+        self.available_voices().iter().any(|v| v == voice_style)
+    }
+}
@@ -0,0 +1,371 @@
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::{
+    body::{Bytes, Incoming},
+    header::{CONTENT_TYPE, ORIGIN},
+    server::conn::http1,
+    service::service_fn,
+    HeaderMap, Method, Request, Response, StatusCode, Uri,
+};
+use hyper_util::rt::TokioIo;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{convert::Infallible, io::Cursor, str::FromStr};
+use tokio::{
+    net::TcpListener,
+    signal::unix::{signal, SignalKind},
+};
+
+use crate::{
+    core::{
+        speech_engine::EngineResult,
+        voice_manager::{Voice, VoiceManager},
+    },
+    output::{self, AudioMetadata, ExportFormat},
+    utils::text,
+};
+
+// Longer inputs are rejected by OpenAI too, and requests this size are read into memory
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+// Body of `POST /v1/audio/speech`. The model is accepted for compatibility but every voice
+// is served by the engine providing it, and fields like `instructions` are ignored
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SpeechRequest {
+    pub input: String,
+    pub voice: String,
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    #[serde(default)]
+    pub response_format: Option<String>,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ResponseFormat {
+    Encoded(ExportFormat),
+    // Raw 16-bit little endian samples at the engine's sample rate
+    Pcm,
+}
+
+impl ResponseFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ResponseFormat::Encoded(ExportFormat::Wav) => "audio/wav",
+            ResponseFormat::Encoded(ExportFormat::Flac) => "audio/flac",
+            ResponseFormat::Encoded(ExportFormat::OggOpus) => "audio/ogg",
+            ResponseFormat::Pcm => "audio/pcm",
+        }
+    }
+}
+
+impl FromStr for ResponseFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pcm" => Ok(ResponseFormat::Pcm),
+            // Opus also comes in Ogg for OpenAI, mp3 and aac have no encoder here
+            _ => s.parse().map(ResponseFormat::Encoded).map_err(|_| {
+                format!(
+                    "Unsupported response_format '{}', expected one of: wav, flac, opus, pcm",
+                    s
+                )
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct VoiceEntry {
+    id: String,
+    // Language and grade, known for Kokoros voices
+    #[serde(flatten)]
+    details: Option<Voice>,
+}
+
+// Serves speech over HTTP until Ctrl+C or SIGTERM. Engines have to be initialized before
+pub async fn serve(listener: TcpListener) -> EngineResult<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    eprintln!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            },
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+            _ = terminate.recv() => return Ok(()),
+        };
+
+        tokio::spawn(async move {
+            let connection = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service_fn(handle_request));
+            if let Err(e) = connection.await {
+                eprintln!("Failed to serve connection: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_request(request: Request<Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    if is_foreign_origin(request.headers()) {
+        let err_msg = "Requests from web pages of other sites are not allowed";
+        return Ok(error_response(StatusCode::FORBIDDEN, err_msg));
+    }
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::POST, "/v1/audio/speech") => match read_speech_request(request).await {
+            Ok(speech) => synthesize(speech).await,
+            Err(e) => error_response(StatusCode::BAD_REQUEST, &e),
+        },
+        (&Method::GET, "/v1/voices") => json_response(&json!({ "voices": list_voices() })),
+        _ => error_response(StatusCode::NOT_FOUND, "Not found"),
+    };
+    Ok(response)
+}
+
+// Browsers tell the site of the page making a request, any page could otherwise have text
+// read aloud on localhost. Clients outside of browsers send no origin
+fn is_foreign_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(ORIGIN) else {
+        return false;
+    };
+    let host = origin
+        .to_str()
+        .ok()
+        .and_then(|origin| origin.parse::<Uri>().ok())
+        .and_then(|origin| origin.host().map(str::to_string));
+    !matches!(
+        host.as_deref(),
+        Some("localhost" | "127.0.0.1" | "[::1]" | "::1")
+    )
+}
+
+async fn read_speech_request(request: Request<Incoming>) -> Result<SpeechRequest, String> {
+    let body = Limited::new(request.into_body(), MAX_BODY_BYTES)
+        .collect()
+        .await
+        .map_err(|e| format!("Failed to read request: {}", e))?
+        .to_bytes();
+    serde_json::from_slice(&body).map_err(|e| format!("Invalid request: {}", e))
+}
+
+async fn synthesize(request: SpeechRequest) -> Response<Full<Bytes>> {
+    let format = match request
+        .response_format
+        .as_deref()
+        .unwrap_or("wav")
+        .parse::<ResponseFormat>()
+    {
+        Ok(format) => format,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &e),
+    };
+    if request.input.trim().is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "Input must not be empty");
+    }
+    if !(0.5..=2.0).contains(&request.speed) {
+        return error_response(StatusCode::BAD_REQUEST, "Speed must be between 0.5 and 2.0");
+    }
+    if VoiceManager::get_engine_for_voice(&request.voice).is_err() {
+        let err_msg = format!(
+            "Invalid voice '{}', GET /v1/voices lists the available ones",
+            request.voice
+        );
+        return error_response(StatusCode::BAD_REQUEST, &err_msg);
+    }
+
+    // Engines synthesize on the calling thread
+    let audio = tokio::task::spawn_blocking(move || encode_speech(&request, format)).await;
+    match audio {
+        Ok(Ok(audio)) => Response::builder()
+            .header(CONTENT_TYPE, format.content_type())
+            .body(Full::new(Bytes::from(audio)))
+            .unwrap(),
+        Ok(Err(e)) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+fn encode_speech(request: &SpeechRequest, format: ResponseFormat) -> EngineResult<Vec<u8>> {
+    let engine = VoiceManager::get_engine_for_voice(&request.voice)?;
+    let (mut channels, mut sample_rate) = (1, engine.sample_rate());
+    let mut samples = Vec::new();
+    for sentence in text::split_text_into_sentences(&request.input) {
+        let audio = engine.generate_speech(&sentence, &request.voice, request.speed)?;
+        (channels, sample_rate) = (audio.channels(), audio.sample_rate());
+        samples.extend(audio);
+    }
+
+    let format = match format {
+        ResponseFormat::Pcm => {
+            return Ok(samples
+                .iter()
+                .flat_map(|sample| {
+                    ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes()
+                })
+                .collect())
+        }
+        ResponseFormat::Encoded(format) => format,
+    };
+
+    let metadata = AudioMetadata {
+        voice: request.voice.clone(),
+        ..Default::default()
+    };
+    let mut encoder = output::create_encoder(
+        format,
+        Cursor::new(Vec::new()),
+        channels,
+        sample_rate,
+        &metadata,
+    )?;
+    encoder.write_samples(&samples)?;
+    Ok(encoder.finish(&[])?.into_inner())
+}
+
+fn list_voices() -> Vec<VoiceEntry> {
+    let rows = VoiceManager::get_voice_rows();
+    let mut voices = VoiceManager::get_voices();
+    voices.sort();
+    voices
+        .into_iter()
+        .map(|id| VoiceEntry {
+            details: rows.iter().find(|voice| voice.key == id).cloned(),
+            id,
+        })
+        .collect()
+}
+
+fn json_response(body: &serde_json::Value) -> Response<Full<Bytes>> {
+    Response::builder()
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .unwrap()
+}
+
+// Errors take the shape OpenAI clients know how to show
+fn error_response(status: StatusCode, message: &str) -> Response<Full<Bytes>> {
+    let mut response = json_response(&json!({
+        "error": { "message": message, "type": "invalid_request_error" }
+    }));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::mock_engine::{mock_engine, MockEngine};
+
+    async fn start_server() -> String {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener));
+        format!("http://{}", address)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_speech_endpoint() {
+        mock_engine();
+        let voice = format!("{}server", MockEngine::VOICE_PREFIX);
+        let url = start_server().await;
+        let client = reqwest::Client::new();
+        let speech = |body: serde_json::Value| {
+            client
+                .post(format!("{}/v1/audio/speech", url))
+                .json(&body)
+                .send()
+        };
+
+        let input = "First sentence here. Second one here.";
+        let response = speech(json!({ "model": "tts-1", "input": input, "voice": voice }))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "audio/wav");
+        assert!(response.bytes().await.unwrap().starts_with(b"RIFF"));
+
+        let response = speech(json!({ "input": input, "voice": voice, "response_format": "flac" }))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[CONTENT_TYPE], "audio/flac");
+        assert!(response.bytes().await.unwrap().starts_with(b"fLaC"));
+
+        let response = speech(json!({ "input": input, "voice": voice, "response_format": "pcm" }))
+            .await
+            .unwrap();
+        let expected_samples = text::split_text_into_sentences(input)
+            .iter()
+            .map(|sentence| MockEngine::synthesize(sentence, 1.0).len())
+            .sum::<usize>();
+        assert_eq!(response.bytes().await.unwrap().len(), expected_samples * 2);
+
+        for invalid in [
+            json!({ "input": input, "voice": voice, "response_format": "mp4" }),
+            // Asked for by OpenAI clients by default, answering with another format would
+            // leave them with files of the wrong kind
+            json!({ "input": input, "voice": voice, "response_format": "mp3" }),
+            json!({ "input": input, "voice": "missing_voice" }),
+            json!({ "input": input, "voice": voice, "speed": 4.0 }),
+            json!({ "voice": voice }),
+        ] {
+            let response = speech(invalid).await.unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = response.json::<serde_json::Value>().await.unwrap();
+            assert!(body["error"]["message"].is_string());
+        }
+
+        let from_page = |origin: &str| {
+            client
+                .post(format!("{}/v1/audio/speech", url))
+                .header(ORIGIN, origin)
+                .json(&json!({ "input": input, "voice": voice }))
+                .send()
+        };
+        let response = from_page("https://example.com").await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = from_page("http://localhost:3000").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_foreign_origin() {
+        let with_origin = |origin: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(ORIGIN, origin.parse().unwrap());
+            headers
+        };
+        assert!(!is_foreign_origin(&HeaderMap::new()));
+        assert!(!is_foreign_origin(&with_origin("http://127.0.0.1:8880")));
+        assert!(!is_foreign_origin(&with_origin("http://[::1]:8080")));
+        assert!(is_foreign_origin(&with_origin("http://localhost.example")));
+        assert!(is_foreign_origin(&with_origin("null")));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_voices_endpoint() {
+        mock_engine();
+        let url = start_server().await;
+
+        let voices = reqwest::get(format!("{}/v1/voices", url))
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+        let mock_voice = format!("{}voice", MockEngine::VOICE_PREFIX);
+        assert!(voices["voices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|voice| voice["id"] == mock_voice.as_str()));
+
+        let missing = reqwest::get(format!("{}/v1/missing", url)).await.unwrap();
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,19 +1,35 @@
-use super::{runtime::runtime, voice_manager::VoiceManager};
-use crate::{
-    core::runtime::spawn_tokio,
-    utils::{audio_player::AudioPlayer, highlighter::ReadingBlock},
+use super::{
+    audio_cache::{AudioCache, SynthesizedBlock},
+    runtime::runtime,
+    speech_engine::EngineResult,
+    voice_manager::VoiceManager,
+};
+use crate::utils::{
+    audio_player::{AudioOutput, AudioPlayer},
+    highlighter::ReadingBlock,
+    language_detector,
 };
-use rodio::buffer::SamplesBuffer;
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::BTreeMap,
     error::Error,
     fmt,
+    ops::Range,
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
 };
-use tokio::sync::broadcast::{self, Sender};
+use tokio::{
+    sync::{
+        broadcast::{self, Sender},
+        Notify,
+    },
+    task::JoinHandle,
+};
+
+const DEFAULT_LOOKAHEAD: usize = 3;
+// Already played blocks kept around on top of the lookahead, so going back is instant
+const CACHED_PLAYED_BLOCKS: usize = 4;
 
 #[derive(Clone)]
 pub struct Tts {
@@ -21,17 +37,47 @@ pub struct Tts {
     current_id: Arc<AtomicUsize>,
     reading_speed: Arc<AtomicUsize>,
     audio_player: Arc<AudioPlayer>,
+    repeat: Arc<Notify>,
+    speed_changed: Arc<Notify>,
+    lookahead: Arc<AtomicUsize>,
+    detect_language: Arc<AtomicBool>,
 }
 
 #[derive(Debug, Clone)]
 pub enum TTSEvent {
-    Progress { block_id: u32 },
+    Progress {
+        block_id: u32,
+    },
+    // Characters of the block text that are being spoken
+    WordProgress {
+        block_id: u32,
+        char_range: Range<usize>,
+    },
+    // Block is waiting for synthesis before it can be played
+    Buffering {
+        block_id: u32,
+    },
     Stop,
     Next,
     Prev,
     Error(String),
 }
 
+// Cancels the synthesis once nobody waits for its result anymore. The engine can't be
+// interrupted, so only a synthesis it hasn't started on yet is skipped
+struct PendingSynthesis {
+    block_idx: usize,
+    cancelled: Arc<AtomicBool>,
+    handle: JoinHandle<EngineResult<SynthesizedBlock>>,
+}
+
+impl Drop for PendingSynthesis {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.handle.abort();
+    }
+}
+
 impl fmt::Debug for Tts {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Tts").field("sender", &self.sender).finish()
@@ -46,12 +92,21 @@ impl Default for Tts {
 
 impl Tts {
     pub fn new() -> Self {
-        let (sender, _) = broadcast::channel(4);
+        Self::with_output(AudioOutput::default())
+    }
+
+    pub fn with_output(output: AudioOutput) -> Self {
+        // Room for a burst of word events, readers that lag behind skip the oldest ones
+        let (sender, _) = broadcast::channel(32);
         Self {
             sender: Arc::new(sender),
             current_id: Arc::new(AtomicUsize::new(0)),
             reading_speed: Arc::new(AtomicUsize::new(100)),
-            audio_player: Arc::new(AudioPlayer::default()),
+            audio_player: Arc::new(AudioPlayer::new(output)),
+            repeat: Arc::new(Notify::new()),
+            speed_changed: Arc::new(Notify::new()),
+            lookahead: Arc::new(AtomicUsize::new(DEFAULT_LOOKAHEAD)),
+            detect_language: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -69,156 +124,194 @@ impl Tts {
         }
 
         self.current_id.store(start_from as usize, Ordering::SeqCst);
-        let mut processed_blocks: HashMap<usize, SamplesBuffer<f32>> = HashMap::new();
 
-        while self.current_id.load(Ordering::SeqCst) < blocks_map.len() {
-            let current_idx = self.current_id.load(Ordering::SeqCst);
-            let reading_speed_value = self.reading_speed.load(Ordering::SeqCst);
-            let speed = Self::spin_value_to_rate_percent(reading_speed_value);
-
-            let reading_block = &blocks_map.get(&(current_idx as u32)).unwrap();
-            if let std::collections::hash_map::Entry::Vacant(e) =
-                processed_blocks.entry(current_idx)
-            {
-                let source_audio =
-                    VoiceManager::generate_kokoros_speech(&reading_block.get_text(), &voice, speed)
-                        .await
-                        .map_err(|e| e as Box<dyn Error>)?;
-                e.insert(source_audio);
-            }
-
-            let source_audio = processed_blocks.get(&current_idx).unwrap().clone();
-
-            self.sender.send(TTSEvent::Progress {
-                block_id: current_idx as u32,
-            })?;
-
-            let event = self
-                .play_block_with_prefetch(
-                    source_audio,
-                    current_idx,
-                    &blocks_map,
-                    &voice,
-                    speed,
-                    &mut processed_blocks,
-                )
-                .await?;
-
-            match event {
-                Some(TTSEvent::Stop) => {
-                    self.current_id.store(0, Ordering::SeqCst);
-                    processed_blocks.clear();
-                    break;
-                }
-                Some(TTSEvent::Next) => {
-                    if current_idx + 1 < blocks_map.len() {
-                        self.current_id.store(current_idx + 1, Ordering::SeqCst);
-                    }
-                    continue;
-                }
-                Some(TTSEvent::Prev) => {
-                    if current_idx > 0 {
-                        self.current_id.store(current_idx - 1, Ordering::SeqCst);
-                    }
-                    continue;
-                }
-                Some(TTSEvent::Error(e)) => return Err(e.into()),
-                _ => {
-                    self.current_id.fetch_add(1, Ordering::SeqCst);
-                    continue;
-                }
-            };
-        }
+        let result = self.play_blocks(&voice, &blocks_map).await;
 
+        self.audio_player.stop();
         self.current_id.store(0, Ordering::SeqCst);
 
-        Ok(())
+        result
     }
 
-    async fn play_block_with_prefetch<T>(
+    // Synthesizes up to `lookahead` blocks ahead of the audible one and keeps the next block
+    // queued in the player, so consecutive blocks play without gaps. Progress follows what is
+    // actually audible
+    async fn play_blocks<T>(
         &self,
-        source_audio: SamplesBuffer<f32>,
-        current_idx: usize,
-        blocks_map: &BTreeMap<u32, T>,
         voice: &str,
-        speed: f32,
-        processed_blocks: &mut HashMap<usize, SamplesBuffer<f32>>,
-    ) -> Result<Option<TTSEvent>, Box<dyn Error>>
+        blocks_map: &BTreeMap<u32, T>,
+    ) -> Result<(), Box<dyn Error>>
     where
         T: ReadingBlock + Send + Sync + 'static + Clone,
     {
-        let event_future = self.read_block_of_text(source_audio.clone());
-        let next_block = current_idx + 1;
-
-        if !processed_blocks.contains_key(&next_block) && next_block < blocks_map.len() {
-            let reading_block = blocks_map.get(&(next_block as u32)).unwrap().clone();
-            let voice_clone = voice.to_string();
-            let voice_future = spawn_tokio(async move {
-                match VoiceManager::generate_kokoros_speech(
-                    &reading_block.get_text(),
-                    &voice_clone,
-                    speed,
-                )
-                .await
-                {
-                    Ok(audio) => Ok::<_, Box<dyn std::error::Error + Send + Sync>>(audio),
-                    Err(e) => Err(format!("Error generating speech: {}", e).into()),
-                }
-            });
-
-            let (event_res, next_audio_result) = tokio::join!(event_future, voice_future);
+        let mut receiver = self.sender.subscribe();
+        let mut audible = self.audio_player.subscribe_audible();
+        let repeat = self.repeat.notified();
+        tokio::pin!(repeat);
+        let speed_changed = self.speed_changed.notified();
+        tokio::pin!(speed_changed);
+
+        let lookahead = self.lookahead.load(Ordering::SeqCst).max(1);
+        let mut cache = AudioCache::new(lookahead + CACHED_PLAYED_BLOCKS);
+        let mut cached_speed = self.reading_speed.load(Ordering::SeqCst);
+        let mut next_to_queue = self.current_id.load(Ordering::SeqCst);
+        let mut synthesis: Option<PendingSynthesis> = None;
+        let mut buffering: Option<usize> = None;
+        let mut reported_block: Option<u32> = None;
+        let mut queued_words: BTreeMap<usize, Vec<Range<usize>>> = BTreeMap::new();
+
+        loop {
+            let current_idx = self.current_id.load(Ordering::SeqCst);
+            if current_idx >= blocks_map.len() {
+                return Ok(());
+            }
 
-            match next_audio_result {
-                Ok(audio) => {
-                    processed_blocks.insert(next_block, audio);
+            // Audio synthesized at the old speed is stale, the audible block plays on
+            // and everything after it gets synthesized again
+            let speed = self.reading_speed.load(Ordering::SeqCst);
+            if speed != cached_speed {
+                cached_speed = speed;
+                cache.clear();
+                synthesis = None;
+                if let Some(first_discarded) = self.audio_player.discard_upcoming() {
+                    next_to_queue = first_discarded as usize;
                 }
-                Err(e) => return Err(e),
             }
 
-            event_res
-        } else {
-            event_future.await
-        }
-    }
-
-    pub async fn read_block_of_text(
-        &self,
-        source_audio: SamplesBuffer<f32>,
-    ) -> Result<Option<TTSEvent>, Box<dyn Error>> {
-        let mut receiver = self.sender.subscribe();
-        let audio_player = self.audio_player.clone();
+            if next_to_queue < blocks_map.len() && next_to_queue <= current_idx + 1 {
+                if let Some(block) = cache.get(next_to_queue) {
+                    let marks = block.words.iter().map(|w| w.sample_offset).collect();
+                    self.audio_player
+                        .enqueue(next_to_queue as u32, block.audio, marks)
+                        .map_err(|e| e as Box<dyn Error>)?;
+                    queued_words.insert(
+                        next_to_queue,
+                        block.words.into_iter().map(|w| w.char_range).collect(),
+                    );
+                    next_to_queue += 1;
+                    continue;
+                }
+            }
 
-        let result = runtime()
-            .spawn(async move {
-                let play_handle = spawn_tokio(async move { audio_player.play_audio(source_audio) });
+            if next_to_queue == current_idx && buffering != Some(current_idx) {
+                buffering = Some(current_idx);
+                self.sender.send(TTSEvent::Buffering {
+                    block_id: current_idx as u32,
+                })?;
+            }
 
-                tokio::select! {
-                    play_result = play_handle => {
-                        match play_result {
-                            Ok(_) => None,
-                            Err(e) => Some(TTSEvent::Error(e.to_string())),
+            if synthesis.is_none() {
+                let window_end = (current_idx + lookahead).min(blocks_map.len() - 1);
+                let missing = (next_to_queue..=window_end).find(|idx| !cache.contains(*idx));
+
+                if let Some(block_idx) = missing {
+                    let reading_block = blocks_map.get(&(block_idx as u32)).unwrap();
+                    let text = reading_block.get_text();
+                    let voice = if self.detect_language.load(Ordering::SeqCst) {
+                        language_detector::voice_for_text(voice, &text)
+                    } else {
+                        voice.to_string()
+                    };
+                    let speed = Self::spin_value_to_rate_percent(speed);
+
+                    let cancelled = Arc::new(AtomicBool::new(false));
+                    let handle = runtime().spawn_blocking({
+                        let cancelled = cancelled.clone();
+                        move || {
+                            // Cancelled before a blocking thread picked it up
+                            if cancelled.load(Ordering::SeqCst) {
+                                return Err("Synthesis cancelled".into());
+                            }
+                            VoiceManager::generate_speech_blocking(&text, &voice, speed)
+                                .map(|audio| SynthesizedBlock::new(&text, audio))
                         }
-                    }
-                    Ok(event) = receiver.recv() => {
-                        match event {
-                            TTSEvent::Stop => Some(TTSEvent::Stop),
-                            TTSEvent::Next => Some(TTSEvent::Next),
-                            TTSEvent::Prev => Some(TTSEvent::Prev),
-                            _ => None,
+                    });
+                    synthesis = Some(PendingSynthesis {
+                        block_idx,
+                        cancelled,
+                        handle,
+                    });
+                }
+            }
+
+            let jump_to = tokio::select! {
+                biased;
+
+                event = receiver.recv() => match event {
+                    Ok(TTSEvent::Stop) => return Ok(()),
+                    Ok(TTSEvent::Next) => Some((current_idx + 1).min(blocks_map.len() - 1)),
+                    Ok(TTSEvent::Prev) => Some(current_idx.saturating_sub(1)),
+                    _ => None,
+                },
+                _ = &mut repeat => {
+                    repeat.set(self.repeat.notified());
+                    Some(current_idx)
+                }
+                _ = &mut speed_changed => {
+                    speed_changed.set(self.speed_changed.notified());
+                    None
+                }
+                Ok(()) = audible.changed() => {
+                    let position = *audible.borrow_and_update();
+                    match position {
+                        Some(position) => {
+                            let block_id = position.block_id;
+                            buffering = None;
+                            self.current_id.store(block_id as usize, Ordering::SeqCst);
+                            if reported_block != Some(block_id) {
+                                reported_block = Some(block_id);
+                                queued_words.retain(|idx, _| *idx >= block_id as usize);
+                                self.sender.send(TTSEvent::Progress { block_id })?;
+                            }
+                            let word = position.mark.and_then(|mark| {
+                                queued_words.get(&(block_id as usize))?.get(mark).cloned()
+                            });
+                            if let Some(char_range) = word {
+                                self.sender.send(TTSEvent::WordProgress { block_id, char_range })?;
+                            }
                         }
+                        // Everything queued has been played
+                        None => self.current_id.store(next_to_queue, Ordering::SeqCst),
                     }
+                    None
                 }
-            })
-            .await?;
+                result = async { (&mut synthesis.as_mut().unwrap().handle).await }, if synthesis.is_some() => {
+                    let block_idx = synthesis.take().unwrap().block_idx;
+                    let block = result?.map_err(|e| e as Box<dyn Error>)?;
+                    cache.insert(block_idx, block);
+                    None
+                }
+            };
 
-        Ok(result)
+            if let Some(target) = jump_to {
+                // Synthesis outside of the new window is stale, dropping it cancels it
+                if synthesis
+                    .as_ref()
+                    .is_some_and(|s| s.block_idx < target || s.block_idx > target + lookahead)
+                {
+                    synthesis = None;
+                }
+                self.audio_player.stop();
+                audible.mark_unchanged();
+                self.current_id.store(target, Ordering::SeqCst);
+                next_to_queue = target;
+                buffering = None;
+                reported_block = None;
+                queued_words.clear();
+            }
+        }
     }
 
     pub async fn stop(&self, send_event: bool) -> Result<(), Box<dyn Error>> {
+        // Event has to go out before the player stops, otherwise the reading loop
+        // could treat the cleared queue as finished and move on
+        let sent = if send_event {
+            self.sender.send(TTSEvent::Stop).map(|_| ())
+        } else {
+            Ok(())
+        };
         self.audio_player.stop();
-        if send_event {
-            self.sender.send(TTSEvent::Stop)?;
-        }
+        sent?;
         Ok(())
     }
 
@@ -243,17 +336,17 @@ impl Tts {
 
     pub async fn prev(&self) -> Result<(), Box<dyn Error>> {
         self.sender.send(TTSEvent::Prev)?;
-        self.stop(false).await
+        Ok(())
     }
 
     pub async fn next(&self) -> Result<(), Box<dyn Error>> {
         self.sender.send(TTSEvent::Next)?;
-        self.stop(false).await
+        Ok(())
     }
 
+    // Restarts the audible block, e.g. after the reading speed changed
     pub async fn repeat_block(&self) -> Result<(), Box<dyn Error>> {
-        self.current_id.fetch_sub(1, Ordering::SeqCst);
-        self.stop(false).await?;
+        self.repeat.notify_waiters();
         Ok(())
     }
 
@@ -265,8 +358,37 @@ impl Tts {
         self.audio_player.is_playing()
     }
 
+    // Number of blocks synthesized ahead of the audible one, applies from the next reading
+    pub fn set_lookahead(&self, blocks: usize) {
+        self.lookahead.store(blocks.max(1), Ordering::SeqCst);
+    }
+
+    // Blocks in another language than the voice's are read by a voice of that language,
+    // applies to blocks synthesized from now on
+    pub fn set_language_detection(&self, detect: bool) {
+        self.detect_language.store(detect, Ordering::SeqCst);
+    }
+
+    // Blocks after the audible one are synthesized again at the new speed,
+    // `repeat_block` restarts the audible one too
     pub fn set_speed(&self, speed: f64) {
-        self.reading_speed.store(speed as usize, Ordering::SeqCst);
+        let speed = speed as usize;
+        if self.reading_speed.swap(speed, Ordering::SeqCst) != speed {
+            self.speed_changed.notify_waiters();
+        }
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.audio_player.set_volume(volume);
+    }
+
+    // Pitch shift in semitones, applies from the next block
+    pub fn set_pitch(&self, pitch: f32) {
+        self.audio_player.set_pitch(pitch);
+    }
+
+    pub fn set_normalize(&self, normalize: bool) {
+        self.audio_player.set_normalize(normalize);
     }
 
     pub fn get_speed(&self) -> u8 {
@@ -279,3 +401,381 @@ impl Tts {
         return (spin_value as f32 / 100.0) as f32;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::mock_engine::{mock_engine, MockEngine, FAIL_MARKER, SLOW_MARKER};
+    use crate::utils::audio_player::Pacing;
+    use std::{sync::atomic::AtomicBool, time::Instant};
+
+    #[derive(Debug, Clone)]
+    struct TestBlock {
+        id: u32,
+        text: String,
+    }
+
+    impl ReadingBlock for TestBlock {
+        fn get_text(&self) -> String {
+            self.text.clone()
+        }
+
+        fn get_id(&self) -> u32 {
+            self.id
+        }
+    }
+
+    enum Action {
+        Next,
+        Prev,
+        Repeat,
+        Stop,
+    }
+
+    fn create_blocks(texts: &[&str]) -> BTreeMap<u32, TestBlock> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let block = TestBlock {
+                    id: i as u32,
+                    text: text.to_string(),
+                };
+                (block.id, block)
+            })
+            .collect()
+    }
+
+    fn progress_ids(events: &[TTSEvent]) -> Vec<u32> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                TTSEvent::Progress { block_id } => Some(*block_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn buffering_ids(events: &[TTSEvent]) -> Vec<u32> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                TTSEvent::Buffering { block_id } => Some(*block_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Runs the reader and records every event from the broadcast channel, `control`
+    // can react to an event by sending Next/Prev/Stop the same way the UI does
+    async fn read_and_collect(
+        tts: &Tts,
+        voice: &str,
+        blocks: BTreeMap<u32, TestBlock>,
+        start_from: u32,
+        control: impl Fn(&TTSEvent) -> Option<Action>,
+    ) -> (Result<(), String>, Vec<TTSEvent>) {
+        mock_engine();
+        let mut receiver = tts.sender.subscribe();
+        let (done_tx, mut done_rx) = tokio::sync::oneshot::channel::<()>();
+
+        let reader = async {
+            let result = tts
+                .read_blocks_by_voice(voice.to_string(), blocks, start_from)
+                .await
+                .map_err(|e| e.to_string());
+            let _ = done_tx.send(());
+            result
+        };
+
+        let collector = async {
+            let mut events = Vec::new();
+            loop {
+                tokio::select! {
+                    Ok(event) = receiver.recv() => {
+                        let action = control(&event);
+                        events.push(event);
+                        let _ = match action {
+                            Some(Action::Next) => tts.next().await,
+                            Some(Action::Prev) => tts.prev().await,
+                            Some(Action::Repeat) => tts.repeat_block().await,
+                            Some(Action::Stop) => tts.stop(true).await,
+                            None => Ok(()),
+                        };
+                    }
+                    _ = &mut done_rx => break,
+                }
+            }
+            while let Ok(event) = receiver.try_recv() {
+                events.push(event);
+            }
+            events
+        };
+
+        tokio::join!(reader, collector)
+    }
+
+    fn long_text() -> String {
+        "long block ".repeat(300)
+    }
+
+    fn word_ranges(events: &[TTSEvent], block: u32) -> Vec<Range<usize>> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                TTSEvent::WordProgress {
+                    block_id,
+                    char_range,
+                } if *block_id == block => Some(char_range.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Long enough to react to while it plays, short enough to be played in full
+    fn medium_text() -> String {
+        "medium block ".repeat(20)
+    }
+
+    #[tokio::test]
+    async fn test_reads_all_blocks_in_order() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        let blocks = create_blocks(&["First block.", "Second block.", "Third block."]);
+
+        let (result, events) = read_and_collect(&tts, "mock_in_order", blocks, 0, |_| None).await;
+
+        assert!(result.is_ok());
+        assert_eq!(progress_ids(&events), vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_starts_from_given_block() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        let blocks = create_blocks(&["First block.", "Second block.", "Third block."]);
+
+        let (result, events) = read_and_collect(&tts, "mock_start_from", blocks, 1, |_| None).await;
+
+        assert!(result.is_ok());
+        assert_eq!(progress_ids(&events), vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_empty_blocks_emit_nothing() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+
+        let (result, events) =
+            read_and_collect(&tts, "mock_empty", create_blocks(&[]), 0, |_| None).await;
+
+        assert!(result.is_ok());
+        assert!(events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_each_block_is_generated_once() {
+        let voice = "mock_generated_once";
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        let texts = ["First block.", "Second block.", "Third block."];
+
+        let (result, _) = read_and_collect(&tts, voice, create_blocks(&texts), 0, |_| None).await;
+
+        assert!(result.is_ok());
+        assert_eq!(mock_engine().calls_for(voice), texts);
+    }
+
+    #[tokio::test]
+    async fn test_next_skips_rest_of_block() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        let long = long_text();
+        let blocks = create_blocks(&[&long, "Second block.", "Third block."]);
+
+        let started = Instant::now();
+        let (result, events) = read_and_collect(&tts, "mock_next", blocks, 0, |event| {
+            matches!(event, TTSEvent::Progress { block_id: 0 }).then_some(Action::Next)
+        })
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(progress_ids(&events), vec![0, 1, 2]);
+        assert!(started.elapsed() < MockEngine::duration_of(&long));
+    }
+
+    #[tokio::test]
+    async fn test_prev_goes_back_one_block() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        let medium = medium_text();
+        let blocks = create_blocks(&["First block.", &medium, "Third block."]);
+        let went_back = AtomicBool::new(false);
+
+        let (result, events) = read_and_collect(&tts, "mock_prev", blocks, 0, |event| {
+            let on_second = matches!(event, TTSEvent::Progress { block_id: 1 });
+            (on_second && !went_back.swap(true, Ordering::SeqCst)).then_some(Action::Prev)
+        })
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(progress_ids(&events), vec![0, 1, 0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_repeat_restarts_audible_block() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        let medium = medium_text();
+        let blocks = create_blocks(&[&medium, "Second block.", "Third block."]);
+        let repeated = AtomicBool::new(false);
+
+        let (result, events) = read_and_collect(&tts, "mock_repeat", blocks, 0, |event| {
+            let on_first = matches!(event, TTSEvent::Progress { block_id: 0 });
+            (on_first && !repeated.swap(true, Ordering::SeqCst)).then_some(Action::Repeat)
+        })
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(progress_ids(&events), vec![0, 0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_word_progress_follows_audio() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        let medium = medium_text();
+        let blocks = create_blocks(&[&medium, "Second block."]);
+
+        let (result, events) = read_and_collect(&tts, "mock_words", blocks, 0, |_| None).await;
+
+        assert!(result.is_ok());
+        let words = word_ranges(&events, 0);
+        assert!(words.len() > 1);
+        assert!(words.windows(2).all(|pair| pair[0].end <= pair[1].start));
+        for range in &words {
+            let word = medium
+                .chars()
+                .skip(range.start)
+                .take(range.len())
+                .collect::<String>();
+            assert!(word == "medium" || word == "block", "{}", word);
+        }
+
+        // Words of a block are only reported once the block itself is
+        let second_started = events
+            .iter()
+            .position(|e| matches!(e, TTSEvent::Progress { block_id: 1 }))
+            .unwrap();
+        let second_word = events
+            .iter()
+            .position(|e| matches!(e, TTSEvent::WordProgress { block_id: 1, .. }))
+            .unwrap();
+        assert!(second_started < second_word);
+        assert_eq!(word_ranges(&events, 1).last(), Some(&(7..13)));
+    }
+
+    #[tokio::test]
+    async fn test_speed_change_applies_to_next_block() {
+        let voice = "mock_speed_change";
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        let medium = medium_text();
+        let blocks = create_blocks(&[&medium, "Second block.", "Third block."]);
+
+        let (result, events) = read_and_collect(&tts, voice, blocks, 0, |event| {
+            if matches!(event, TTSEvent::Progress { block_id: 0 }) {
+                tts.set_speed(200.0);
+            }
+            None
+        })
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(progress_ids(&events), vec![0, 1, 2]);
+
+        let calls = mock_engine().speed_calls_for(voice);
+        assert_eq!(calls.first(), Some(&(medium.clone(), 1.0)));
+        assert_eq!(calls.last(), Some(&("Third block.".to_string(), 2.0)));
+        assert!(calls.contains(&("Second block.".to_string(), 2.0)));
+    }
+
+    #[tokio::test]
+    async fn test_stop_ends_reading() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        let long = long_text();
+        let blocks = create_blocks(&[&long, "Second block.", "Third block."]);
+
+        let started = Instant::now();
+        let (result, events) = read_and_collect(&tts, "mock_stop", blocks, 0, |event| {
+            matches!(event, TTSEvent::Progress { block_id: 0 }).then_some(Action::Stop)
+        })
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(progress_ids(&events), vec![0]);
+        assert!(events.iter().any(|e| matches!(e, TTSEvent::Stop)));
+        assert!(started.elapsed() < MockEngine::duration_of(&long));
+        assert!(!tts.is_playing());
+    }
+
+    #[tokio::test]
+    async fn test_buffering_reported_before_first_block() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        let blocks = create_blocks(&["First block.", "Second block.", "Third block."]);
+
+        let (result, events) = read_and_collect(&tts, "mock_buffering", blocks, 0, |_| None).await;
+
+        assert!(result.is_ok());
+        assert!(matches!(events[0], TTSEvent::Buffering { block_id: 0 }));
+        assert_eq!(buffering_ids(&events), vec![0]);
+    }
+
+    // First block plays long enough to synthesize both slow blocks only when
+    // the lookahead reaches past the next one
+    fn slow_blocks() -> BTreeMap<u32, TestBlock> {
+        let first = "long block ".repeat(50);
+        let slow_first = format!("{} one.", SLOW_MARKER);
+        let slow_second = format!("{} two.", SLOW_MARKER);
+        create_blocks(&[&first, &slow_first, &slow_second])
+    }
+
+    #[tokio::test]
+    async fn test_lookahead_synthesizes_blocks_in_advance() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        tts.set_lookahead(2);
+
+        let (result, events) =
+            read_and_collect(&tts, "mock_lookahead", slow_blocks(), 0, |_| None).await;
+
+        assert!(result.is_ok());
+        assert_eq!(progress_ids(&events), vec![0, 1, 2]);
+        assert_eq!(buffering_ids(&events), vec![0]);
+    }
+
+    #[tokio::test]
+    async fn test_short_lookahead_buffers_slow_blocks() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        tts.set_lookahead(1);
+
+        let (result, events) =
+            read_and_collect(&tts, "mock_short_lookahead", slow_blocks(), 0, |_| None).await;
+
+        assert!(result.is_ok());
+        assert_eq!(progress_ids(&events), vec![0, 1, 2]);
+        assert_eq!(buffering_ids(&events), vec![0, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_engine_error_is_returned() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        let failing = format!("{} block.", FAIL_MARKER);
+        let blocks = create_blocks(&["First block.", &failing]);
+
+        let (result, _) = read_and_collect(&tts, "mock_error", blocks, 0, |_| None).await;
+
+        assert!(result.unwrap_err().contains(FAIL_MARKER));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_voice_is_rejected() {
+        let tts = Tts::with_output(AudioOutput::Null(Pacing::RealTime));
+        let blocks = create_blocks(&["First block."]);
+
+        let (result, events) = read_and_collect(&tts, "unknown_voice", blocks, 0, |_| None).await;
+
+        assert!(result.is_err());
+        assert!(progress_ids(&events).is_empty());
+    }
+}
@@ -0,0 +1,263 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{LazyLock, OnceLock};
+
+use crate::paths::voice_config;
+
+const BUNDLED_CATALOG: &str = include_str!("../../resources/voices.json");
+
+static CATALOG: LazyLock<VoiceCatalog> = LazyLock::new(VoiceCatalog::load);
+// Voices the voices file contains, read once it has been downloaded
+static FILE_VOICES: OnceLock<Vec<String>> = OnceLock::new();
+
+// Languages and grades of the Kokoros voices. The bundled catalog can be extended or
+// corrected by a file of the same shape in the data directory, its entries win
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct VoiceCatalog {
+    // By the first letter of the voice keys, like a for af_heart
+    #[serde(default)]
+    languages: BTreeMap<String, CatalogLanguage>,
+    #[serde(default)]
+    voices: Vec<CatalogVoice>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CatalogLanguage {
+    pub code: String,
+    pub name: String,
+    pub region: String,
+    pub flag: String,
+    // Name espeak-ng phonemizes the language under, when it isn't the lowercased code
+    #[serde(default)]
+    pub espeak: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CatalogVoice {
+    pub key: String,
+    pub grade: Option<String>,
+    // Shown after the gender, which the key tells
+    #[serde(default)]
+    pub traits: String,
+}
+
+impl VoiceCatalog {
+    pub fn get() -> &'static VoiceCatalog {
+        &CATALOG
+    }
+
+    fn load() -> Self {
+        Self::load_from(BUNDLED_CATALOG, &voice_config::get_catalog_override_path())
+    }
+
+    // Bundled catalog with the entries of the override file, when there is one
+    pub fn load_from(bundled: &str, override_path: &str) -> Self {
+        let mut catalog: VoiceCatalog =
+            serde_json::from_str(bundled).expect("Bundled voice catalog is invalid");
+        if let Ok(content) = std::fs::read_to_string(override_path) {
+            match serde_json::from_str(&content) {
+                Ok(user_catalog) => catalog.merge(user_catalog),
+                Err(e) => eprintln!("Failed to read voice catalog {}: {}", override_path, e),
+            }
+        }
+        catalog
+    }
+
+    fn merge(&mut self, other: VoiceCatalog) {
+        self.languages.extend(other.languages);
+        for voice in other.voices {
+            match self.voices.iter_mut().find(|known| known.key == voice.key) {
+                Some(known) => *known = voice,
+                None => self.voices.push(voice),
+            }
+        }
+    }
+
+    pub fn language(&self, voice_style: &str) -> Option<&CatalogLanguage> {
+        self.languages.get(voice_style.get(0..1)?)
+    }
+
+    pub fn voice(&self, voice_style: &str) -> Option<&CatalogVoice> {
+        self.voices.iter().find(|voice| voice.key == voice_style)
+    }
+
+    pub fn available_voices(&self) -> Vec<String> {
+        self.available_voices_in(file_voices().map(Vec::as_slice))
+    }
+
+    // Voices of the catalog the voices file has, followed by the ones only the file has.
+    // Until the file is downloaded the whole catalog is listed
+    pub fn available_voices_in(&self, file_voices: Option<&[String]>) -> Vec<String> {
+        let catalog_voices = self.voices.iter().map(|voice| voice.key.clone());
+        let Some(file_voices) = file_voices else {
+            return catalog_voices.collect();
+        };
+        catalog_voices
+            .filter(|key| file_voices.contains(key))
+            .chain(
+                file_voices
+                    .iter()
+                    .filter(|key| self.voice(key).is_none())
+                    .cloned(),
+            )
+            .collect()
+    }
+}
+
+fn file_voices() -> Option<&'static Vec<String>> {
+    if let Some(voices) = FILE_VOICES.get() {
+        return Some(voices);
+    }
+    let mut names = npz_entry_names(&voice_config::get_kokoros_voices_path()).ok()?;
+    names.sort();
+    Some(FILE_VOICES.get_or_init(|| names))
+}
+
+// The voices file is a NumPy archive, a zip with an af_heart.npy entry per voice. Only the
+// central directory at the end of the zip is read for the names
+fn npz_entry_names(path: &str) -> io::Result<Vec<String>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut file = File::open(path)?;
+    let file_len = file.seek(SeekFrom::End(0))?;
+
+    // The end of central directory record is 22 bytes, followed by a comment of up to 64 KiB
+    let tail_len = file_len.min(22 + u16::MAX as u64);
+    file.seek(SeekFrom::Start(file_len - tail_len))?;
+    let mut tail = vec![0; tail_len as usize];
+    file.read_exact(&mut tail)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|i| tail[*i..*i + 4] == [0x50, 0x4b, 0x05, 0x06])
+        .ok_or_else(|| invalid("Voices file is not a zip archive"))?;
+    let u16_at = |bytes: &[u8], at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]) as usize;
+    let u32_at = |bytes: &[u8], at: usize| {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]) as usize
+    };
+    let entries = u16_at(&tail, end + 10);
+    let directory_len = u32_at(&tail, end + 12);
+    let directory_start = u32_at(&tail, end + 16);
+
+    file.seek(SeekFrom::Start(directory_start as u64))?;
+    let mut directory = vec![0; directory_len];
+    file.read_exact(&mut directory)?;
+
+    let mut names = Vec::with_capacity(entries);
+    let mut at = 0;
+    for _ in 0..entries {
+        if directory.get(at..at + 4) != Some(&[0x50, 0x4b, 0x01, 0x02][..])
+            || at + 46 > directory.len()
+        {
+            return Err(invalid("Voices file has a broken zip directory"));
+        }
+        let name_len = u16_at(&directory, at + 28);
+        let extra_len = u16_at(&directory, at + 30);
+        let comment_len = u16_at(&directory, at + 32);
+        let name = directory
+            .get(at + 46..at + 46 + name_len)
+            .ok_or_else(|| invalid("Voices file has a broken zip directory"))?;
+        let name = String::from_utf8_lossy(name);
+        if let Some(voice) = name.strip_suffix(".npy") {
+            names.push(voice.to_string());
+        }
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Zip with only the central directory, which is all that is read
+    fn zip_directory(names: &[&str]) -> Vec<u8> {
+        let mut directory = Vec::new();
+        for name in names {
+            let mut header = vec![0; 46];
+            header[..4].copy_from_slice(&[0x50, 0x4b, 0x01, 0x02]);
+            header[28..30].copy_from_slice(&(name.len() as u16).to_le_bytes());
+            directory.extend(header);
+            directory.extend(name.as_bytes());
+        }
+        let mut end = vec![0; 22];
+        end[..4].copy_from_slice(&[0x50, 0x4b, 0x05, 0x06]);
+        end[10..12].copy_from_slice(&(names.len() as u16).to_le_bytes());
+        end[12..16].copy_from_slice(&(directory.len() as u32).to_le_bytes());
+        directory.extend(end);
+        directory
+    }
+
+    #[test]
+    fn test_npz_entry_names() {
+        let path =
+            std::env::temp_dir().join(format!("fox-reader-voices-{}.bin", std::process::id()));
+        std::fs::write(
+            &path,
+            zip_directory(&["af_heart.npy", "xf_new.npy", "notes.txt"]),
+        )
+        .unwrap();
+        let names = npz_entry_names(&path.to_string_lossy()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(names, vec!["af_heart", "xf_new"]);
+
+        std::fs::write(&path, b"not a zip").unwrap();
+        assert!(npz_entry_names(&path.to_string_lossy()).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_user_catalog_is_merged() {
+        let path =
+            std::env::temp_dir().join(format!("fox-reader-catalog-{}.json", std::process::id()));
+        let path = path.to_string_lossy();
+        let _ = std::fs::remove_file(&*path);
+        let catalog = VoiceCatalog::load_from(BUNDLED_CATALOG, &path);
+        let bundled_count = catalog.voices.len();
+        assert_eq!(
+            catalog.voice("af_heart").unwrap().grade.as_deref(),
+            Some("A")
+        );
+        assert_eq!(catalog.language("ff_siwis").unwrap().code, "fr");
+        assert_eq!(
+            catalog.language("zf_xiaobei").unwrap().espeak.as_deref(),
+            Some("cmn")
+        );
+        assert_eq!(catalog.language("af_heart").unwrap().espeak, None);
+
+        std::fs::write(
+            &*path,
+            r#"{
+                "languages": { "x": { "code": "xx", "name": "New", "region": "Nowhere", "flag": "🏳️" } },
+                "voices": [{ "key": "af_heart", "grade": "B" }, { "key": "xf_new" }]
+            }"#,
+        )
+        .unwrap();
+        let catalog = VoiceCatalog::load_from(BUNDLED_CATALOG, &path);
+        assert_eq!(catalog.voices.len(), bundled_count + 1);
+        assert_eq!(
+            catalog.voice("af_heart").unwrap().grade.as_deref(),
+            Some("B")
+        );
+        assert_eq!(catalog.language("xf_new").unwrap().code, "xx");
+        assert_eq!(catalog.language("bf_emma").unwrap().code, "en-GB");
+
+        // A broken override leaves the bundled catalog as it is
+        std::fs::write(&*path, "{ not json").unwrap();
+        let catalog = VoiceCatalog::load_from(BUNDLED_CATALOG, &path);
+        assert_eq!(catalog.voices.len(), bundled_count);
+        std::fs::remove_file(&*path).unwrap();
+    }
+
+    #[test]
+    fn test_available_voices_follow_voices_file() {
+        let catalog = VoiceCatalog::load_from(BUNDLED_CATALOG, "");
+        let all = catalog.available_voices_in(None);
+        assert_eq!(all.len(), catalog.voices.len());
+        assert_eq!(all[0], catalog.voices[0].key);
+
+        let file_voices = ["xf_new", "bf_emma", "af_heart"].map(String::from);
+        let voices = catalog.available_voices_in(Some(&file_voices));
+        assert_eq!(voices, vec!["af_heart", "bf_emma", "xf_new"]);
+    }
+}
@@ -1,12 +1,16 @@
 use crate::core::kokoros_manager::KokorosTTS;
+use crate::core::speech_engine::SpeechEngine;
+use crate::core::voice_catalog::VoiceCatalog;
+use crate::core::voice_presets::VoicePresets;
 use rodio::buffer::SamplesBuffer;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::error::Error;
-use std::sync::Arc;
-use tokio::sync::OnceCell;
+use std::sync::{Arc, LazyLock, RwLock};
 
-static KOKOROS_TTS: OnceCell<Arc<KokorosTTS>> = OnceCell::const_new();
+type EngineRegistry = RwLock<BTreeMap<&'static str, Arc<dyn SpeechEngine>>>;
+
+static ENGINES: LazyLock<EngineRegistry> = LazyLock::new(|| RwLock::new(BTreeMap::new()));
 
 pub struct VoiceManager {}
 
@@ -33,36 +37,152 @@ pub struct Voice {
     pub is_default: Option<bool>,
 }
 
+// Criteria to pick voices by, the ones left unset match every voice
+#[derive(Debug, Clone, Default)]
+pub struct VoiceFilter {
+    // A locale like en-GB, or a language like en to match all of its locales
+    pub language: Option<String>,
+    // 'f' or 'm', as the second letter of voice keys tells
+    pub gender: Option<char>,
+    pub min_grade: Option<String>,
+}
+
+impl VoiceFilter {
+    pub fn matches(&self, voice: &Voice) -> bool {
+        let language_matches = self.language.as_deref().is_none_or(|language| {
+            let code = voice.language.code.to_lowercase();
+            let language = language.to_lowercase();
+            code == language || code.split('-').next() == Some(language.as_str())
+        });
+        let gender_matches = self.gender.is_none_or(|gender| {
+            VoiceManager::get_voice_gender(&voice.key) == Some(gender.to_ascii_lowercase())
+        });
+        let grade_matches = self
+            .min_grade
+            .as_deref()
+            .is_none_or(|min_grade| grade_rank(&voice.quality) >= grade_rank(min_grade));
+        language_matches && gender_matches && grade_matches
+    }
+}
+
+// Orders quality grades like A, B-, C+ from best to worst, None for anything else
+pub fn grade_rank(grade: &str) -> Option<u8> {
+    let mut chars = grade.trim().chars();
+    let letter = match chars.next()?.to_ascii_uppercase() {
+        'A' => 4,
+        'B' => 3,
+        'C' => 2,
+        'D' => 1,
+        'F' => 0,
+        _ => return None,
+    };
+    let modifier = match (chars.next(), chars.next()) {
+        (None, _) => 1,
+        (Some('+'), None) => 2,
+        (Some('-'), None) => 0,
+        _ => return None,
+    };
+    Some(letter * 3 + modifier)
+}
+
 impl VoiceManager {
     pub async fn init_kokoros() -> Result<(), Box<dyn Error + Send + Sync>> {
         let kokoros = KokorosTTS::new().await?;
-        KOKOROS_TTS
-            .set(Arc::new(kokoros))
-            .map_err(|_| "Failed to initialize Kokoros TTS")?;
+        Self::register_engine(Arc::new(kokoros));
         Ok(())
     }
 
-    pub async fn generate_kokoros_speech(
+    pub fn register_engine(engine: Arc<dyn SpeechEngine>) {
+        ENGINES.write().unwrap().insert(engine.id(), engine);
+    }
+
+    pub fn unregister_engine(engine_id: &str) {
+        ENGINES.write().unwrap().remove(engine_id);
+    }
+
+    pub fn get_engine(engine_id: &str) -> Option<Arc<dyn SpeechEngine>> {
+        ENGINES.read().unwrap().get(engine_id).cloned()
+    }
+
+    pub fn get_engine_for_voice(
+        voice_style: &str,
+    ) -> Result<Arc<dyn SpeechEngine>, Box<dyn Error + Send + Sync>> {
+        let engines = ENGINES.read().unwrap();
+        if engines.is_empty() {
+            return Err("No speech engine initialized".into());
+        }
+
+        engines
+            .values()
+            .find(|engine| engine.has_voice(voice_style))
+            .cloned()
+            .ok_or_else(|| format!("No speech engine provides voice '{}'", voice_style).into())
+    }
+
+    // Engines synthesize on the calling thread, so async callers get it done on a blocking
+    // thread instead of holding up a runtime worker
+    pub async fn generate_speech(
         text: &str,
         voice_style: &str,
         speed: f32,
     ) -> Result<SamplesBuffer<f32>, Box<dyn Error + Send + Sync>> {
-        let kokoros = KOKOROS_TTS.get().ok_or("Kokoros TTS not initialized")?;
+        let (text, voice_style) = (text.to_string(), voice_style.to_string());
+        tokio::task::spawn_blocking(move || {
+            Self::generate_speech_blocking(&text, &voice_style, speed)
+        })
+        .await?
+    }
 
-        kokoros.generate_speech(text, voice_style, speed).await
+    pub fn generate_speech_blocking(
+        text: &str,
+        voice_style: &str,
+        speed: f32,
+    ) -> Result<SamplesBuffer<f32>, Box<dyn Error + Send + Sync>> {
+        let engine = Self::get_engine_for_voice(voice_style)?;
+
+        engine.generate_speech(text, voice_style, speed)
     }
 
-    pub async fn save_kokoros_speech_to_file(
+    pub async fn generate_speech_with_engine(
+        engine_id: &str,
+        text: &str,
+        voice_style: &str,
+        speed: f32,
+    ) -> Result<SamplesBuffer<f32>, Box<dyn Error + Send + Sync>> {
+        let engine = Self::get_engine(engine_id)
+            .ok_or_else(|| format!("Speech engine '{}' not initialized", engine_id))?;
+
+        let (text, voice_style) = (text.to_string(), voice_style.to_string());
+        tokio::task::spawn_blocking(move || engine.generate_speech(&text, &voice_style, speed))
+            .await?
+    }
+
+    pub async fn save_speech_to_file(
         text: &str,
         voice_style: &str,
         speed: f32,
         output_path: &str,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let kokoros = KOKOROS_TTS.get().ok_or("Kokoros TTS not initialized")?;
+        let engine = Self::get_engine_for_voice(voice_style)?;
+
+        let (text, voice_style, output_path) = (
+            text.to_string(),
+            voice_style.to_string(),
+            output_path.to_string(),
+        );
+        tokio::task::spawn_blocking(move || {
+            engine.save_speech_to_file(&text, &voice_style, speed, &output_path)
+        })
+        .await?
+    }
 
-        kokoros
-            .save_speech_to_file(text, voice_style, speed, output_path)
-            .await
+    pub fn get_voices() -> Vec<String> {
+        ENGINES
+            .read()
+            .unwrap()
+            .values()
+            .flat_map(|engine| engine.available_voices())
+            .collect()
     }
 
     pub fn get_kokoros_voices() -> Vec<String> {
@@ -104,11 +224,42 @@ impl VoiceManager {
             .collect()
     }
 
+    // Presets are listed in the language and grade of their main style
+    pub fn get_preset_voice_rows() -> Vec<Voice> {
+        VoicePresets::load()
+            .into_iter()
+            .map(|preset| {
+                let main_style = preset.main_style();
+                let (language_code, language_name, region, flag) =
+                    Self::get_language_info_from_voice_style(main_style);
+                Voice {
+                    name: format!("{} {} - Preset", flag, preset.name),
+                    key: preset.key(),
+                    language: Language {
+                        code: language_code,
+                        name_english: language_name,
+                        region,
+                    },
+                    quality: Self::get_voice_quality_grade(main_style),
+                    traits: "🎨".to_string(),
+                    is_default: Some(false),
+                }
+            })
+            .collect()
+    }
+
+    // Built-in voices followed by the presets
+    pub fn get_voice_rows() -> Vec<Voice> {
+        let mut voices = Self::get_kokoros_voice_rows();
+        voices.extend(Self::get_preset_voice_rows());
+        voices
+    }
+
     pub async fn list_all_available_voices_with_kokoros(
     ) -> Result<BTreeMap<String, Voice>, Box<dyn Error>> {
         let mut all_voices = BTreeMap::new();
 
-        let kokoros_voices = Self::get_kokoros_voice_rows();
+        let kokoros_voices = Self::get_voice_rows();
         for voice in kokoros_voices {
             all_voices.insert(voice.key.clone(), voice);
         }
@@ -116,74 +267,33 @@ impl VoiceManager {
         Ok(all_voices)
     }
 
+    // Locale the voice speaks, like en-GB or ja
+    pub fn get_voice_language(voice_style: &str) -> String {
+        Self::get_language_info_from_voice_style(voice_style).0
+    }
+
+    // 'f' or 'm' as the second letter of the key tells, presets have the gender of their
+    // main style. None for keys of another shape
+    pub fn get_voice_gender(voice_style: &str) -> Option<char> {
+        if let Some(preset) = VoicePresets::find(voice_style) {
+            return Self::get_voice_gender(preset.main_style());
+        }
+        voice_style
+            .chars()
+            .nth(1)
+            .filter(|gender| matches!(gender, 'f' | 'm'))
+    }
+
+    // Voices of languages the catalog doesn't know are read as American English
     fn get_language_info_from_voice_style(voice_style: &str) -> (String, String, String, String) {
-        let prefix = voice_style.get(0..2).unwrap_or("");
-        match prefix {
-            // American English
-            "af" | "am" => (
-                "en-US".to_string(),
-                "English".to_string(),
-                "United States".to_string(),
-                "🇺🇸".to_string(),
-            ),
-            // British English
-            "bf" | "bm" => (
-                "en-GB".to_string(),
-                "English".to_string(),
-                "United Kingdom".to_string(),
-                "🇬🇧".to_string(),
-            ),
-            // Japanese
-            "jf" | "jm" => (
-                "ja".to_string(),
-                "Japanese".to_string(),
-                "Japan".to_string(),
-                "🇯🇵".to_string(),
-            ),
-            // Mandarin Chinese
-            "zf" | "zm" => (
-                "zh-CN".to_string(),
-                "Chinese".to_string(),
-                "China".to_string(),
-                "🇨🇳".to_string(),
-            ),
-            // Spanish
-            "ef" | "em" => (
-                "es".to_string(),
-                "Spanish".to_string(),
-                "Spain".to_string(),
-                "🇪🇸".to_string(),
+        match VoiceCatalog::get().language(voice_style) {
+            Some(language) => (
+                language.code.clone(),
+                language.name.clone(),
+                language.region.clone(),
+                language.flag.clone(),
             ),
-            // French
-            "ff" | "fm" => (
-                "fr".to_string(),
-                "French".to_string(),
-                "France".to_string(),
-                "🇫🇷".to_string(),
-            ),
-            // Hindi
-            "hf" | "hm" => (
-                "hi".to_string(),
-                "Hindi".to_string(),
-                "India".to_string(),
-                "🇮🇳".to_string(),
-            ),
-            // Italian
-            "if" | "im" => (
-                "it".to_string(),
-                "Italian".to_string(),
-                "Italy".to_string(),
-                "🇮🇹".to_string(),
-            ),
-            // Brazilian Portuguese
-            "pf" | "pm" => (
-                "pt-BR".to_string(),
-                "Portuguese".to_string(),
-                "Brazil".to_string(),
-                "🇧🇷".to_string(),
-            ),
-            // Default to American English for unknown prefixes
-            _ => (
+            None => (
                 "en-US".to_string(),
                 "English".to_string(),
                 "United States".to_string(),
@@ -199,18 +309,9 @@ impl VoiceManager {
             _ => "",
         };
 
-        let special_traits = match voice_style {
-            // American English special traits
-            "af_heart" => "❤️",            // Heart
-            "af_bella" => "🔥",            // Fire
-            "af_nicole" => "🎧",           // Headphones
-            "af_sky" | "am_santa" => "🤏", // Short training
-
-            // Japanese special traits
-            "jf_nezumi" | "jm_kumo" => "🤏", // Short training
-
-            _ => "",
-        };
+        let special_traits = VoiceCatalog::get()
+            .voice(voice_style)
+            .map_or("", |voice| voice.traits.as_str());
 
         if special_traits.is_empty() {
             base_gender_trait.to_string()
@@ -238,82 +339,64 @@ impl VoiceManager {
         format!("{} {} - {}", flag, formatted_name, country)
     }
 
+    // Voices without a grade in the catalog count as average
     fn get_voice_quality_grade(voice_style: &str) -> String {
-        match voice_style {
-            // American English
-            "af_heart" => "A".to_string(),
-            "af_alloy" => "C".to_string(),
-            "af_aoede" => "C+".to_string(),
-            "af_bella" => "A-".to_string(),
-            "af_jessica" => "D".to_string(),
-            "af_kore" => "C+".to_string(),
-            "af_nicole" => "B-".to_string(),
-            "af_nova" => "C".to_string(),
-            "af_river" => "D".to_string(),
-            "af_sarah" => "C+".to_string(),
-            "af_sky" => "C-".to_string(),
-            "am_adam" => "F+".to_string(),
-            "am_echo" => "D".to_string(),
-            "am_eric" => "D".to_string(),
-            "am_fenrir" => "C+".to_string(),
-            "am_liam" => "D".to_string(),
-            "am_michael" => "C+".to_string(),
-            "am_onyx" => "D".to_string(),
-            "am_puck" => "C+".to_string(),
-            "am_santa" => "D-".to_string(),
-
-            // British English
-            "bf_alice" => "D".to_string(),
-            "bf_emma" => "B-".to_string(),
-            "bf_isabella" => "C".to_string(),
-            "bf_lily" => "D".to_string(),
-            "bm_daniel" => "D".to_string(),
-            "bm_fable" => "C".to_string(),
-            "bm_george" => "C".to_string(),
-            "bm_lewis" => "D+".to_string(),
-
-            // Japanese
-            "jf_alpha" => "C+".to_string(),
-            "jf_gongitsune" => "C".to_string(),
-            "jf_nezumi" => "C-".to_string(),
-            "jf_tebukuro" => "C".to_string(),
-            "jm_kumo" => "C-".to_string(),
-
-            // Mandarin Chinese
-            "zf_xiaobei" => "D".to_string(),
-            "zf_xiaoni" => "D".to_string(),
-            "zf_xiaoxiao" => "D".to_string(),
-            "zf_xiaoyi" => "D".to_string(),
-            "zm_yunjian" => "D".to_string(),
-            "zm_yunxi" => "D".to_string(),
-            "zm_yunxia" => "D".to_string(),
-            "zm_yunyang" => "D".to_string(),
-
-            // Spanish (no specific grades in table, using reasonable defaults)
-            "ef_dora" => "C".to_string(),
-            "em_alex" => "C".to_string(),
-            "em_santa" => "C".to_string(),
-
-            // French
-            "ff_siwis" => "B-".to_string(),
-
-            // Hindi
-            "hf_alpha" => "C".to_string(),
-            "hf_beta" => "C".to_string(),
-            "hm_omega" => "C".to_string(),
-            "hm_psi" => "C".to_string(),
-
-            // Italian
-            "if_sara" => "C".to_string(),
-            "im_nicola" => "C".to_string(),
-
-            // Brazilian Portuguese (no specific grades in table, using reasonable defaults)
-            "pf_dora" => "C".to_string(),
-            "pm_alex" => "C".to_string(),
-            "pm_santa" => "C".to_string(),
-
-            // Default fallback
-            _ => "C".to_string(),
-        }
+        VoiceCatalog::get()
+            .voice(voice_style)
+            .and_then(|voice| voice.grade.clone())
+            .unwrap_or_else(|| "C".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voice(key: &str) -> Voice {
+        VoiceManager::get_kokoros_voice_rows()
+            .into_iter()
+            .find(|voice| voice.key == key)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_grades_are_ordered() {
+        let grades = ["F", "D-", "D", "D+", "C-", "C", "C+", "B-", "A-", "A", "A+"];
+        let ranks = grades.map(|grade| grade_rank(grade).unwrap());
+        assert!(ranks.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(grade_rank("c+"), grade_rank("C+"));
+        assert_eq!(grade_rank("E"), None);
+        assert_eq!(grade_rank("B++"), None);
+    }
+
+    #[test]
+    fn test_voice_filter() {
+        let british = VoiceFilter {
+            language: Some("en-GB".to_string()),
+            ..Default::default()
+        };
+        assert!(british.matches(&voice("bf_emma")));
+        assert!(!british.matches(&voice("af_heart")));
+
+        let english_women = VoiceFilter {
+            language: Some("en".to_string()),
+            gender: Some('f'),
+            min_grade: Some("B-".to_string()),
+        };
+        assert!(english_women.matches(&voice("af_heart")));
+        assert!(english_women.matches(&voice("bf_emma")));
+        assert!(!english_women.matches(&voice("af_sky")));
+        assert!(!english_women.matches(&voice("am_michael")));
+        assert!(!english_women.matches(&voice("ff_siwis")));
+        assert_eq!(VoiceManager::get_voice_gender("am_adam"), Some('m'));
+        assert_eq!(VoiceManager::get_voice_gender("mock_voice"), None);
+
+        assert_eq!(
+            VoiceManager::get_kokoros_voice_rows().len(),
+            VoiceManager::get_kokoros_voice_rows()
+                .iter()
+                .filter(|v| VoiceFilter::default().matches(v))
+                .count()
+        );
     }
 }
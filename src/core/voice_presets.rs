@@ -0,0 +1,301 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::{
+    core::kokoros_manager::KokorosTTS, paths::voice_config, utils::file_handler::FileHandler,
+};
+
+const PRESET_PREFIX: &str = "preset_";
+const MAX_STYLES: usize = 10;
+
+// Presets by the path and modification time of their file, which is read again only once it
+// changed, by this process or by another one like the CLI
+type PresetsCache = Option<(String, Option<SystemTime>, Vec<VoicePreset>)>;
+static PRESETS: Mutex<PresetsCache> = Mutex::new(None);
+
+// Voice mixed from Kokoros styles by the user, read like a built-in voice under its key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VoicePreset {
+    pub name: String,
+    pub styles: Vec<PresetStyle>,
+    // Relative to the speed asked for, so 1.2 reads a bit faster at every speed
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+}
+
+// Weights are relative to the other styles of the preset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PresetStyle {
+    pub voice: String,
+    pub weight: f32,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+impl VoicePreset {
+    // Like preset_warm_emma for "Warm Emma"
+    pub fn key(&self) -> String {
+        let name = self.name.to_lowercase();
+        let words = name
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>();
+        format!("{}{}", PRESET_PREFIX, words.join("_"))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.key() == PRESET_PREFIX {
+            return Err("Preset name needs letters or digits".to_string());
+        }
+        if self.styles.is_empty() {
+            return Err("Preset needs at least one voice style".to_string());
+        }
+        // Each style gets a share in tenths
+        if self.styles.len() > MAX_STYLES {
+            return Err(format!("Presets mix at most {} voice styles", MAX_STYLES));
+        }
+        let styles = KokorosTTS::get_available_voices();
+        for style in &self.styles {
+            if !styles.contains(&style.voice) {
+                return Err(format!(
+                    "Unknown voice style '{}', presets mix the built-in Kokoros voices",
+                    style.voice
+                ));
+            }
+            if !style.weight.is_finite() || style.weight <= 0.0 {
+                return Err(format!("Weight of '{}' must be above 0", style.voice));
+            }
+        }
+        if !(0.5..=2.0).contains(&self.speed) {
+            return Err("Speed must be between 0.5 and 2.0".to_string());
+        }
+        Ok(())
+    }
+
+    // Style with the largest weight, the preset speaks its language
+    pub fn main_style(&self) -> &str {
+        self.styles
+            .iter()
+            .max_by(|a, b| a.weight.total_cmp(&b.weight))
+            .map_or("", |style| style.voice.as_str())
+    }
+
+    // Kokoros mixes styles written like af_heart.6+bf_emma.4, by shares in tenths that have
+    // to add up to a whole. The tenths left after rounding down go to the largest
+    // remainders, styles left without any are dropped
+    pub fn kokoros_style(&self) -> String {
+        let total = self.styles.iter().map(|style| style.weight).sum::<f32>();
+        let exact = self
+            .styles
+            .iter()
+            .map(|style| style.weight / total * 10.0)
+            .collect::<Vec<_>>();
+        let mut tenths = exact
+            .iter()
+            .map(|share| share.floor() as u32)
+            .collect::<Vec<_>>();
+        let left = 10u32.saturating_sub(tenths.iter().sum());
+        let mut by_remainder = (0..exact.len()).collect::<Vec<_>>();
+        by_remainder.sort_by(|a, b| exact[*b].fract().total_cmp(&exact[*a].fract()));
+        for idx in by_remainder.into_iter().take(left as usize) {
+            tenths[idx] += 1;
+        }
+
+        let mixed = self
+            .styles
+            .iter()
+            .zip(tenths)
+            .filter(|(_, tenths)| *tenths > 0)
+            .collect::<Vec<_>>();
+        if let [(style, _)] = mixed[..] {
+            return style.voice.clone();
+        }
+        mixed
+            .iter()
+            .map(|(style, tenths)| format!("{}.{}", style.voice, tenths))
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+}
+
+pub struct VoicePresets {}
+
+impl VoicePresets {
+    // Presets saved on disk, none when the file is missing or unreadable
+    pub fn load() -> Vec<VoicePreset> {
+        load_cached(&voice_config::get_presets_path())
+    }
+
+    pub fn find(key: &str) -> Option<VoicePreset> {
+        if !key.starts_with(PRESET_PREFIX) {
+            return None;
+        }
+        Self::load().into_iter().find(|preset| preset.key() == key)
+    }
+
+    // Replaces a preset with the same key
+    pub fn save(preset: VoicePreset) -> Result<(), Box<dyn Error>> {
+        preset.validate()?;
+        let saved = save_to(&voice_config::get_presets_path(), preset);
+        // The modification time may not change within the same second
+        PRESETS.lock().unwrap().take();
+        saved
+    }
+
+    // Whether a preset with the key was there to remove
+    pub fn remove(key: &str) -> Result<bool, Box<dyn Error>> {
+        let removed = remove_from(&voice_config::get_presets_path(), key);
+        PRESETS.lock().unwrap().take();
+        removed
+    }
+}
+
+fn load_cached(path: &str) -> Vec<VoicePreset> {
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let mut cache = PRESETS.lock().unwrap();
+    match cache.as_ref() {
+        Some((cached_path, cached, presets)) if cached_path == path && *cached == modified => {
+            presets.clone()
+        }
+        _ => {
+            let presets = load_from(path);
+            *cache = Some((path.to_string(), modified, presets.clone()));
+            presets
+        }
+    }
+}
+
+fn load_from(path: &str) -> Vec<VoicePreset> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    serde_json::from_str(&content).unwrap_or_else(|e| {
+        eprintln!("Failed to read voice presets from {}: {}", path, e);
+        Vec::new()
+    })
+}
+
+fn save_to(path: &str, preset: VoicePreset) -> Result<(), Box<dyn Error>> {
+    let mut presets = load_from(path);
+    match presets.iter_mut().find(|saved| saved.key() == preset.key()) {
+        Some(saved) => *saved = preset,
+        None => presets.push(preset),
+    }
+    write_presets(path, &presets)
+}
+
+fn remove_from(path: &str, key: &str) -> Result<bool, Box<dyn Error>> {
+    let mut presets = load_from(path);
+    let count = presets.len();
+    presets.retain(|preset| preset.key() != key);
+    if presets.len() == count {
+        return Ok(false);
+    }
+    write_presets(path, &presets)?;
+    Ok(true)
+}
+
+fn write_presets(path: &str, presets: &[VoicePreset]) -> Result<(), Box<dyn Error>> {
+    FileHandler::ensure_all_paths_exists(path)?;
+    fs::write(path, serde_json::to_string_pretty(presets)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(name: &str, styles: &[(&str, f32)]) -> VoicePreset {
+        VoicePreset {
+            name: name.to_string(),
+            styles: styles
+                .iter()
+                .map(|(voice, weight)| PresetStyle {
+                    voice: voice.to_string(),
+                    weight: *weight,
+                })
+                .collect(),
+            speed: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_kokoros_style() {
+        let warm = preset("Warm Emma!", &[("af_heart", 3.0), ("bf_emma", 2.0)]);
+        assert_eq!(warm.key(), "preset_warm_emma");
+        assert_eq!(warm.kokoros_style(), "af_heart.6+bf_emma.4");
+        assert_eq!(warm.main_style(), "af_heart");
+        assert!(warm.validate().is_ok());
+        assert_eq!(
+            preset("Single", &[("ff_siwis", 1.0)]).kokoros_style(),
+            "ff_siwis"
+        );
+        // Shares always add up to ten tenths
+        let even = preset(
+            "Even",
+            &[("af_heart", 1.0), ("bf_emma", 1.0), ("am_adam", 1.0)],
+        );
+        assert_eq!(even.kokoros_style(), "af_heart.4+bf_emma.3+am_adam.3");
+        let faint = preset("Faint", &[("af_heart", 99.0), ("bf_emma", 1.0)]);
+        assert_eq!(faint.kokoros_style(), "af_heart");
+        let crowded = preset("Crowded", &[("af_heart", 1.0); 11]);
+        assert!(crowded.validate().is_err());
+
+        assert!(preset("Unknown", &[("xx_nobody", 1.0)]).validate().is_err());
+        assert!(preset("Weightless", &[("af_heart", 0.0)])
+            .validate()
+            .is_err());
+        assert!(preset(" !", &[("af_heart", 1.0)]).validate().is_err());
+    }
+
+    #[test]
+    fn test_presets_are_saved_and_removed() {
+        let path = std::env::temp_dir()
+            .join(format!("fox-reader-presets-{}.json", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let _ = fs::remove_file(&path);
+
+        save_to(&path, preset("Mix", &[("af_heart", 1.0)])).unwrap();
+        save_to(&path, preset("Other", &[("bf_emma", 1.0)])).unwrap();
+        // Saving under the same name replaces the preset
+        save_to(&path, preset("mix", &[("am_adam", 1.0)])).unwrap();
+        let presets = load_from(&path);
+        assert_eq!(presets.len(), 2);
+        assert_eq!(presets[0].styles[0].voice, "am_adam");
+
+        assert!(remove_from(&path, "preset_other").unwrap());
+        assert!(!remove_from(&path, "preset_other").unwrap());
+        assert_eq!(load_from(&path).len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_presets_are_read_again_once_changed() {
+        let path = std::env::temp_dir()
+            .join(format!(
+                "fox-reader-cached-presets-{}.json",
+                std::process::id()
+            ))
+            .to_string_lossy()
+            .to_string();
+        let _ = fs::remove_file(&path);
+        assert!(load_cached(&path).is_empty());
+
+        save_to(&path, preset("Mix", &[("af_heart", 1.0)])).unwrap();
+        let presets = load_cached(&path);
+        assert_eq!(presets.len(), 1);
+        assert_eq!(presets[0].key(), "preset_mix");
+        assert_eq!(load_cached(&path), presets);
+
+        fs::remove_file(&path).unwrap();
+        assert!(load_cached(&path).is_empty());
+    }
+}
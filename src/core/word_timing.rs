@@ -0,0 +1,159 @@
+use std::ops::Range;
+
+// Samples quieter than this are treated as the silence engines leave around speech
+const SILENCE_THRESHOLD: f32 = 0.01;
+// Extra weight, in characters, for the pause a speaker makes after punctuation
+const CLAUSE_PAUSE: usize = 2;
+const SENTENCE_PAUSE: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordTiming {
+    // Characters of the block text taken by the word
+    pub char_range: Range<usize>,
+    // Interleaved sample of the block audio at which the word starts
+    pub sample_offset: usize,
+}
+
+// Engines don't report timestamps, so words are spread over the voiced part of the audio
+// proportionally to their length, with some extra time for pauses after punctuation
+pub fn align_words(text: &str, samples: &[f32], channels: u16) -> Vec<WordTiming> {
+    let words = split_words(text);
+    if words.is_empty() {
+        return Vec::new();
+    }
+
+    let channels = channels.max(1) as usize;
+    let voiced = |sample: &f32| sample.abs() > SILENCE_THRESHOLD;
+    let (first_frame, end_frame) = match (
+        samples.iter().position(voiced),
+        samples.iter().rposition(voiced),
+    ) {
+        (Some(first), Some(last)) => (first / channels, last / channels + 1),
+        _ => (0, samples.len() / channels),
+    };
+
+    let weights = words
+        .iter()
+        .map(|(_, word)| word_weight(word))
+        .collect::<Vec<_>>();
+    let total_weight = weights.iter().sum::<usize>();
+    let voiced_frames = end_frame - first_frame;
+
+    let mut elapsed_weight = 0;
+    words
+        .into_iter()
+        .zip(weights)
+        .map(|((char_range, _), weight)| {
+            let frame = first_frame + voiced_frames * elapsed_weight / total_weight;
+            elapsed_weight += weight;
+            WordTiming {
+                char_range,
+                sample_offset: frame * channels,
+            }
+        })
+        .collect()
+}
+
+// Runs of non-whitespace characters with their character range in `text`
+fn split_words(text: &str) -> Vec<(Range<usize>, &str)> {
+    let mut words = Vec::new();
+    let mut word_start = None;
+    let mut char_idx = 0;
+
+    for (byte_idx, c) in text.char_indices() {
+        match (word_start, c.is_whitespace()) {
+            (None, false) => word_start = Some((char_idx, byte_idx)),
+            (Some((start_char, start_byte)), true) => {
+                words.push((start_char..char_idx, &text[start_byte..byte_idx]));
+                word_start = None;
+            }
+            _ => {}
+        }
+        char_idx += 1;
+    }
+
+    if let Some((start_char, start_byte)) = word_start {
+        words.push((start_char..char_idx, &text[start_byte..]));
+    }
+
+    words
+}
+
+// Characters of the word and the space after it, plus the pause that follows it
+fn word_weight(word: &str) -> usize {
+    let pause = match word.chars().last() {
+        Some('.' | '!' | '?') => SENTENCE_PAUSE,
+        Some(',' | ';' | ':') => CLAUSE_PAUSE,
+        _ => 0,
+    };
+    word.chars().count() + 1 + pause
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn speech(leading_silence: usize, voiced: usize, trailing_silence: usize) -> Vec<f32> {
+        [
+            vec![0.0; leading_silence],
+            vec![0.5; voiced],
+            vec![0.0; trailing_silence],
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_split_words_uses_char_ranges() {
+        let words = split_words("  Zażółć gęślą\njaźń ");
+        let ranges = words.into_iter().map(|(r, _)| r).collect::<Vec<_>>();
+
+        assert_eq!(ranges, vec![2..8, 9..14, 15..19]);
+    }
+
+    #[test]
+    fn test_words_spread_over_voiced_audio() {
+        let timings = align_words("one two", &speech(100, 600, 100), 1);
+
+        assert_eq!(
+            timings,
+            vec![
+                WordTiming {
+                    char_range: 0..3,
+                    sample_offset: 100
+                },
+                WordTiming {
+                    char_range: 4..7,
+                    sample_offset: 400
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_punctuation_delays_next_word() {
+        let plain = align_words("one two", &speech(0, 1000, 0), 1);
+        let paused = align_words("one. two", &speech(0, 1000, 0), 1);
+
+        assert!(paused[1].sample_offset > plain[1].sample_offset);
+    }
+
+    #[test]
+    fn test_offsets_are_aligned_to_frames() {
+        let timings = align_words("a bb ccc", &speech(10, 990, 0), 2);
+
+        assert_eq!(timings.len(), 3);
+        assert!(timings.iter().all(|t| t.sample_offset % 2 == 0));
+        assert!(timings
+            .windows(2)
+            .all(|pair| pair[0].sample_offset < pair[1].sample_offset));
+    }
+
+    #[test]
+    fn test_silent_audio_and_empty_text() {
+        let timings = align_words("one two", &[0.0; 80], 1);
+        assert_eq!(timings[0].sample_offset, 0);
+        assert_eq!(timings[1].sample_offset, 40);
+
+        assert!(align_words("  ", &speech(0, 100, 0), 1).is_empty());
+    }
+}
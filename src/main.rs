@@ -4,10 +4,11 @@ use gtk::{gio, glib};
 use gtk::{prelude::*, CssProvider};
 use settings::Settings;
 use std::sync::LazyLock;
-use utils::schema_handler::SchemaHandler;
+use utils::{audio_player::AudioOutput, schema_handler::SchemaHandler};
 
 mod cli;
 mod core;
+mod output;
 mod paths;
 mod settings;
 mod ui;
@@ -18,33 +19,21 @@ const APP_ID: &str = "com.github.kopecmaciej.fox-reader";
 pub static SETTINGS: LazyLock<Settings> = LazyLock::new(Settings::default);
 
 fn main() -> glib::ExitCode {
-    let args: Vec<String> = std::env::args().collect();
-    let is_cli_mode = args.iter().any(|arg| arg == "--cli");
-
-    if args.len() > 1
-        && (args.contains(&"--help".to_string()) || args.contains(&"-h".to_string()))
-        && !is_cli_mode
-    {
-        print_general_help();
+    let matches = cli::command().get_matches_from(cli::legacy_args(std::env::args()));
+
+    if matches.get_flag("keybindings") {
+        print_keybindings();
         return glib::ExitCode::SUCCESS;
     }
 
-    if is_cli_mode {
-        match runtime().block_on(cli::run_cli()) {
-            Ok(true) => return glib::ExitCode::SUCCESS,
+    if matches.subcommand().is_some() {
+        return match runtime().block_on(cli::run(&matches)) {
+            Ok(()) => glib::ExitCode::SUCCESS,
             Err(e) => {
                 eprintln!("{}", e);
-                return glib::ExitCode::FAILURE;
+                glib::ExitCode::FAILURE
             }
-            _ => {
-                return glib::ExitCode::FAILURE;
-            }
-        }
-    }
-
-    if args.iter().any(|arg| arg == "--keybindings" || arg == "-k") {
-        print_keybindings();
-        return glib::ExitCode::SUCCESS;
+        };
     }
 
     if let Err(e) = runtime().block_on(SchemaHandler::install_from_url()) {
@@ -52,6 +41,8 @@ fn main() -> glib::ExitCode {
         return glib::ExitCode::FAILURE;
     }
 
+    AudioOutput::set_default(SETTINGS.get_audio_output());
+
     gio::resources_register_include!("fox-reader.gresource")
         .expect("Failed to register resources.");
 
@@ -79,25 +70,6 @@ fn load_css() {
     );
 }
 
-fn print_general_help() {
-    println!("Fox Reader - Text-to-Speech Application");
-    println!("=======================================\n");
-    println!("A modern text-to-speech application with both GUI and CLI modes.\n");
-
-    println!("Usage:");
-    println!("  fox-reader                    Start GUI mode (default)");
-    println!("  fox-reader --cli <options>    Run in CLI mode");
-    println!("  fox-reader -k --keybindings    Show keyboard shortcuts");
-    println!("  fox-reader -h --help           Show this help");
-
-    println!("\nCLI Examples:");
-    println!("  fox-reader --cli --text \"Hello world\"");
-    println!("  fox-reader --cli --list-voices");
-    println!("  fox-reader --cli --help");
-
-    println!("\nFor detailed CLI options, run: fox-reader --cli --help");
-}
-
 fn print_keybindings() {
     use ui::keybindings::{format_key_combination, KeyBindingManager};
 
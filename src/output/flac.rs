@@ -0,0 +1,531 @@
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+
+use super::{replace_header, vorbis_comment_data, AudioEncoder, AudioMetadata};
+use crate::core::{audiobook::ChapterMarker, speech_engine::EngineResult};
+
+// Frames per FLAC frame, the usual choice of reference encoders for 16 bit audio
+const BLOCK_SIZE: usize = 4096;
+const BITS_PER_SAMPLE: u32 = 16;
+const MAX_FIXED_ORDER: usize = 4;
+// Highest values the streamable subset allows, so any player can decode the files
+const MAX_PARTITION_ORDER: u32 = 8;
+const MAX_RICE_PARAMETER: u32 = 14;
+
+const STREAMINFO: u8 = 0;
+const VORBIS_COMMENT: u8 = 4;
+const STREAMINFO_LEN: usize = 34;
+// "fLaC" followed by the STREAMINFO block header
+const STREAMINFO_OFFSET: u64 = 8;
+
+// Lossless 16 bit FLAC with fixed linear predictors and Rice coded residuals, which
+// roughly halves the size of speech compared to WAV
+pub struct FlacEncoder<W: Write> {
+    output: BufWriter<W>,
+    metadata: AudioMetadata,
+    // Bytes of metadata blocks before the first frame
+    header_len: u64,
+    channels: usize,
+    sample_rate: u32,
+    // Interleaved samples not making up a full block yet
+    pending: Vec<i32>,
+    samples_written: u64,
+    frame_number: u64,
+    min_frame_size: u32,
+    max_frame_size: u32,
+}
+
+impl<W: Read + Write + Seek> FlacEncoder<W> {
+    pub fn create(
+        output: W,
+        channels: u16,
+        sample_rate: u32,
+        metadata: &AudioMetadata,
+    ) -> EngineResult<Self> {
+        if !(1..=8).contains(&channels) {
+            return Err(format!("FLAC supports 1 to 8 channels, got {}", channels).into());
+        }
+
+        // STREAMINFO is filled in by `finish` once the totals are known
+        let header = metadata_blocks(&[0; STREAMINFO_LEN], &vorbis_comment_data(metadata, &[]));
+        let mut output = BufWriter::new(output);
+        output.write_all(&header)?;
+
+        Ok(Self {
+            output,
+            metadata: metadata.clone(),
+            header_len: header.len() as u64,
+            channels: channels as usize,
+            sample_rate,
+            pending: Vec::with_capacity(BLOCK_SIZE * channels as usize),
+            samples_written: 0,
+            frame_number: 0,
+            min_frame_size: u32::MAX,
+            max_frame_size: 0,
+        })
+    }
+
+    fn write_frame(&mut self, samples: &[i32]) -> EngineResult<()> {
+        let frame = encode_frame(samples, self.channels, self.sample_rate, self.frame_number);
+        self.output.write_all(&frame)?;
+
+        self.frame_number += 1;
+        self.min_frame_size = self.min_frame_size.min(frame.len() as u32);
+        self.max_frame_size = self.max_frame_size.max(frame.len() as u32);
+        Ok(())
+    }
+
+    fn streaminfo(&self) -> Vec<u8> {
+        let mut bits = BitWriter::default();
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(BLOCK_SIZE as u64, 16);
+        bits.write(self.min_frame_size.min(self.max_frame_size) as u64, 24);
+        bits.write(self.max_frame_size as u64, 24);
+        bits.write(self.sample_rate as u64, 20);
+        bits.write(self.channels as u64 - 1, 3);
+        bits.write(BITS_PER_SAMPLE as u64 - 1, 5);
+        bits.write(self.samples_written / self.channels as u64, 36);
+        // An empty MD5 signature means it wasn't computed
+        for _ in 0..4 {
+            bits.write(0, 32);
+        }
+        bits.into_bytes()
+    }
+}
+
+impl<W: Read + Write + Seek> AudioEncoder<W> for FlacEncoder<W> {
+    fn write_samples(&mut self, samples: &[f32]) -> EngineResult<()> {
+        let block_len = BLOCK_SIZE * self.channels;
+        for sample in samples {
+            self.pending
+                .push((sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i32);
+            if self.pending.len() == block_len {
+                let block = std::mem::take(&mut self.pending);
+                self.write_frame(&block)?;
+                self.pending = block;
+                self.pending.clear();
+            }
+        }
+
+        self.samples_written += samples.len() as u64;
+        Ok(())
+    }
+
+    fn frames_written(&self) -> u64 {
+        self.samples_written / self.channels as u64
+    }
+
+    fn finish(mut self: Box<Self>, markers: &[ChapterMarker]) -> EngineResult<W> {
+        // A trailing partial frame is dropped, engines never produce one
+        let block = std::mem::take(&mut self.pending);
+        let frames = block.len() / self.channels;
+        if frames > 0 {
+            self.write_frame(&block[..frames * self.channels])?;
+        }
+        self.samples_written -= (block.len() % self.channels) as u64;
+
+        let streaminfo = self.streaminfo();
+        let mut output = self.output.into_inner().map_err(|e| e.into_error())?;
+        if markers.is_empty() {
+            output.seek(SeekFrom::Start(STREAMINFO_OFFSET))?;
+            output.write_all(&streaminfo)?;
+            output.flush()?;
+            return Ok(output);
+        }
+
+        // Chapters make the comments grow, so the frames move back
+        let comments = vorbis_comment_data(&self.metadata, markers);
+        let header = metadata_blocks(&streaminfo, &comments);
+        replace_header(&mut output, self.header_len, &header)?;
+        Ok(output)
+    }
+}
+
+fn metadata_blocks(streaminfo: &[u8], comments: &[u8]) -> Vec<u8> {
+    let mut header = b"fLaC".to_vec();
+    for (is_last, block_type, data) in [
+        (false, STREAMINFO, streaminfo),
+        (true, VORBIS_COMMENT, comments),
+    ] {
+        let block_header =
+            ((is_last as u32) << 31) | ((block_type as u32) << 24) | data.len() as u32;
+        header.extend_from_slice(&block_header.to_be_bytes());
+        header.extend_from_slice(data);
+    }
+    header
+}
+
+fn encode_frame(samples: &[i32], channels: usize, sample_rate: u32, frame_number: u64) -> Vec<u8> {
+    let block_size = samples.len() / channels;
+    let (rate_code, rate_bits, rate_len) = sample_rate_code(sample_rate);
+
+    let mut bits = BitWriter::default();
+    // Sync code, reserved bit and fixed block size strategy
+    bits.write(0b11_1111_1111_1110, 14);
+    bits.write(0, 2);
+    // Block size stored as 16 bits after the frame number
+    bits.write(0b0111, 4);
+    bits.write(rate_code, 4);
+    // Channels coded independently
+    bits.write(channels as u64 - 1, 4);
+    // 16 bits per sample and a reserved bit
+    bits.write(0b100, 3);
+    bits.write(0, 1);
+    bits.write_utf8(frame_number);
+    bits.write(block_size as u64 - 1, 16);
+    bits.write(rate_bits, rate_len);
+    let header_crc = crc8(bits.bytes());
+    bits.write(header_crc as u64, 8);
+
+    for channel in 0..channels {
+        let channel_samples = samples
+            .iter()
+            .skip(channel)
+            .step_by(channels)
+            .map(|s| *s as i64)
+            .collect::<Vec<_>>();
+        encode_subframe(&mut bits, &channel_samples);
+    }
+
+    bits.align();
+    let mut frame = bits.into_bytes();
+    let frame_crc = crc16(&frame);
+    frame.extend_from_slice(&frame_crc.to_be_bytes());
+    frame
+}
+
+// Frame header code for the rate and the bits following the header when the rate
+// isn't one of the common ones
+fn sample_rate_code(sample_rate: u32) -> (u64, u64, u32) {
+    let rate = sample_rate as u64;
+    match sample_rate {
+        88200 => (0b0001, 0, 0),
+        176400 => (0b0010, 0, 0),
+        192000 => (0b0011, 0, 0),
+        8000 => (0b0100, 0, 0),
+        16000 => (0b0101, 0, 0),
+        22050 => (0b0110, 0, 0),
+        24000 => (0b0111, 0, 0),
+        32000 => (0b1000, 0, 0),
+        44100 => (0b1001, 0, 0),
+        48000 => (0b1010, 0, 0),
+        96000 => (0b1011, 0, 0),
+        _ if rate.is_multiple_of(1000) && rate / 1000 <= 0xFF => (0b1100, rate / 1000, 8),
+        _ if rate <= 0xFFFF => (0b1101, rate, 16),
+        _ if rate.is_multiple_of(10) && rate / 10 <= 0xFFFF => (0b1110, rate / 10, 16),
+        _ => (0b0000, 0, 0),
+    }
+}
+
+struct ResidualCoding {
+    partition_order: u32,
+    parameters: Vec<u32>,
+    bits: u64,
+}
+
+fn encode_subframe(bits: &mut BitWriter, samples: &[i64]) {
+    if samples.iter().all(|s| *s == samples[0]) {
+        bits.write(0b0000_0000, 8);
+        bits.write_signed(samples[0], BITS_PER_SAMPLE);
+        return;
+    }
+
+    let verbatim_bits = samples.len() as u64 * BITS_PER_SAMPLE as u64;
+    let best = (0..=MAX_FIXED_ORDER.min(samples.len() - 1))
+        .map(|order| {
+            let residuals = fixed_residuals(samples, order);
+            let coding = choose_residual_coding(&residuals, samples.len(), order);
+            let bits = order as u64 * BITS_PER_SAMPLE as u64 + coding.bits;
+            (order, residuals, coding, bits)
+        })
+        .min_by_key(|(_, _, _, bits)| *bits);
+
+    match best {
+        Some((order, residuals, coding, bits_needed)) if bits_needed < verbatim_bits => {
+            bits.write(0b0001_0000 | (order as u64) << 1, 8);
+            for sample in &samples[..order] {
+                bits.write_signed(*sample, BITS_PER_SAMPLE);
+            }
+            write_residuals(bits, &residuals, samples.len(), order, &coding);
+        }
+        _ => {
+            bits.write(0b0000_0010, 8);
+            for sample in samples {
+                bits.write_signed(*sample, BITS_PER_SAMPLE);
+            }
+        }
+    }
+}
+
+// Difference between each sample and its prediction from the `order` ones before it
+fn fixed_residuals(samples: &[i64], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| {
+            let s = |back: usize| samples[i - back];
+            match order {
+                0 => s(0),
+                1 => s(0) - s(1),
+                2 => s(0) - 2 * s(1) + s(2),
+                3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+            }
+        })
+        .collect()
+}
+
+fn fold(residual: i64) -> u64 {
+    ((residual << 1) ^ (residual >> 63)) as u64
+}
+
+// Picks the partition order and per partition Rice parameters giving the smallest
+// estimated size. Partitions are sums of the finest ones, so each order costs little
+fn choose_residual_coding(residuals: &[i64], block_size: usize, order: usize) -> ResidualCoding {
+    let max_order = (0..=MAX_PARTITION_ORDER)
+        .take_while(|p| block_size.is_multiple_of(1 << p) && (block_size >> p) > order)
+        .last()
+        .unwrap_or(0);
+
+    let finest_len = block_size >> max_order;
+    let mut sums = vec![0u64; 1 << max_order];
+    let mut counts = vec![0u64; 1 << max_order];
+    for (i, residual) in residuals.iter().enumerate() {
+        let partition = (i + order) / finest_len;
+        sums[partition] += fold(*residual);
+        counts[partition] += 1;
+    }
+
+    let mut best: Option<ResidualCoding> = None;
+    for partition_order in (0..=max_order).rev() {
+        let parameters_and_bits = sums
+            .iter()
+            .zip(&counts)
+            .map(|(sum, count)| best_rice_parameter(*sum, *count))
+            .collect::<Vec<_>>();
+        let bits = 6 + parameters_and_bits
+            .iter()
+            .map(|(_, bits)| 4 + bits)
+            .sum::<u64>();
+
+        if best.as_ref().is_none_or(|b| bits < b.bits) {
+            best = Some(ResidualCoding {
+                partition_order,
+                parameters: parameters_and_bits.iter().map(|(p, _)| *p).collect(),
+                bits,
+            });
+        }
+
+        sums = sums.chunks(2).map(|pair| pair.iter().sum()).collect();
+        counts = counts.chunks(2).map(|pair| pair.iter().sum()).collect();
+    }
+
+    best.unwrap()
+}
+
+// Estimated from the sum of folded residuals, as each costs `parameter + 1` bits
+// plus its value shifted right by the parameter
+fn best_rice_parameter(sum: u64, count: u64) -> (u32, u64) {
+    (0..=MAX_RICE_PARAMETER)
+        .map(|parameter| {
+            (
+                parameter,
+                count * (parameter as u64 + 1) + (sum >> parameter),
+            )
+        })
+        .min_by_key(|(_, bits)| *bits)
+        .unwrap()
+}
+
+fn write_residuals(
+    bits: &mut BitWriter,
+    residuals: &[i64],
+    block_size: usize,
+    order: usize,
+    coding: &ResidualCoding,
+) {
+    // Rice coding with 4 bit parameters
+    bits.write(0b00, 2);
+    bits.write(coding.partition_order as u64, 4);
+
+    let partition_len = block_size >> coding.partition_order;
+    let mut start = 0;
+    for (partition, parameter) in coding.parameters.iter().enumerate() {
+        let end = (partition + 1) * partition_len - order;
+        bits.write(*parameter as u64, 4);
+        for residual in &residuals[start..end] {
+            let value = fold(*residual);
+            bits.write_unary(value >> parameter);
+            bits.write(value & ((1 << parameter) - 1), *parameter);
+        }
+        start = end;
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    buffered: u32,
+}
+
+impl BitWriter {
+    // Writes the lowest `len` bits of `value`, most significant first, up to 56 at once
+    fn write(&mut self, value: u64, len: u32) {
+        if len == 0 {
+            return;
+        }
+        self.buffer = (self.buffer << len) | (value & ((1 << len) - 1));
+        self.buffered += len;
+        while self.buffered >= 8 {
+            self.buffered -= 8;
+            self.bytes.push((self.buffer >> self.buffered) as u8);
+        }
+        self.buffer &= (1 << self.buffered) - 1;
+    }
+
+    fn write_signed(&mut self, value: i64, len: u32) {
+        self.write(value as u64, len);
+    }
+
+    fn write_unary(&mut self, zeros: u64) {
+        let mut zeros = zeros;
+        while zeros > 32 {
+            self.write(0, 32);
+            zeros -= 32;
+        }
+        self.write(1, zeros as u32 + 1);
+    }
+
+    // Frame numbers are coded like UTF-8 characters, extended to 36 bits
+    fn write_utf8(&mut self, value: u64) {
+        if value < 0x80 {
+            self.write(value, 8);
+            return;
+        }
+
+        let len = match value {
+            0x80..0x800 => 2,
+            0x800..0x10000 => 3,
+            0x10000..0x200000 => 4,
+            0x200000..0x4000000 => 5,
+            0x4000000..0x80000000 => 6,
+            _ => 7,
+        };
+        let first = ((0xFF00u64 >> len) & 0xFF) | (value >> (6 * (len - 1)));
+        self.write(first & 0xFF, 8);
+        for i in (0..len - 1).rev() {
+            self.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+        }
+    }
+
+    fn align(&mut self) {
+        if self.buffered > 0 {
+            self.write(0, 8 - self.buffered);
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc_check_values() {
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        assert_eq!(crc16(b"123456789"), 0xFEE8);
+    }
+
+    #[test]
+    fn test_samples_and_chapters_survive_round_trip() {
+        let metadata = AudioMetadata {
+            title: "Book".to_string(),
+            author: String::new(),
+            voice: "af_heart".to_string(),
+        };
+        // A tone, silence and noise, so every kind of subframe shows up, with a
+        // partial last block
+        let frames = BLOCK_SIZE * 3 + 1000;
+        let mut seed = 1u32;
+        let left = (0..frames)
+            .map(|i| match i / BLOCK_SIZE {
+                0 => (i as f32 * 0.05).sin() * 0.5,
+                1 => 0.0,
+                _ => {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    (seed >> 16) as f32 / 32768.0 - 1.0
+                }
+            })
+            .collect::<Vec<_>>();
+        let samples = left
+            .iter()
+            .flat_map(|s| [*s, -*s * 0.5])
+            .collect::<Vec<_>>();
+
+        let output = std::io::Cursor::new(Vec::new());
+        let mut encoder = Box::new(FlacEncoder::create(output, 2, 24000, &metadata).unwrap());
+        for chunk in samples.chunks(3000) {
+            encoder.write_samples(chunk).unwrap();
+        }
+        assert_eq!(encoder.frames_written(), frames as u64);
+        let markers = [ChapterMarker {
+            title: "Noise".to_string(),
+            frame: BLOCK_SIZE as u32 * 2,
+            sample_rate: 24000,
+        }];
+        let bytes = encoder.finish(&markers).unwrap().into_inner();
+
+        // Read back by an independent decoder, which checks the frame CRCs as well
+        let mut reader = claxon::FlacReader::new(std::io::Cursor::new(&bytes)).unwrap();
+        let streaminfo = reader.streaminfo();
+        assert_eq!(streaminfo.sample_rate, 24000);
+        assert_eq!(streaminfo.channels, 2);
+        assert_eq!(streaminfo.bits_per_sample, 16);
+        assert_eq!(streaminfo.samples, Some(frames as u64));
+        assert_eq!(
+            reader.tags().collect::<Vec<_>>(),
+            vec![
+                ("TITLE", "Book"),
+                ("PERFORMER", "af_heart"),
+                ("CHAPTER001", "00:00:00.341"),
+                ("CHAPTER001NAME", "Noise")
+            ]
+        );
+
+        let decoded = reader.samples().collect::<Result<Vec<_>, _>>().unwrap();
+        let quantize = |s: f32| (s.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i32;
+        let expected = samples.iter().map(|s| quantize(*s)).collect::<Vec<_>>();
+        assert_eq!(decoded, expected);
+        assert!(bytes.len() < samples.len() * 2);
+    }
+}
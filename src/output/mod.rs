@@ -0,0 +1,350 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::core::{audiobook::ChapterMarker, speech_engine::EngineResult};
+
+mod flac;
+mod ogg_opus;
+pub mod subtitles;
+mod wav;
+
+// Written as the encoder/software tag of every exported file
+pub const VENDOR: &str = concat!("Fox Reader ", env!("CARGO_PKG_VERSION"));
+const MOVE_BUFFER_LEN: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Wav,
+    Flac,
+    OggOpus,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [Self::Wav, Self::Flac, Self::OggOpus];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Wav => "wav",
+            ExportFormat::Flac => "flac",
+            ExportFormat::OggOpus => "opus",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Wav => "WAV (uncompressed)",
+            ExportFormat::Flac => "FLAC (lossless)",
+            ExportFormat::OggOpus => "Ogg Opus (smallest)",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.to_lowercase().parse().ok()
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wav" => Ok(ExportFormat::Wav),
+            "flac" => Ok(ExportFormat::Flac),
+            "opus" | "ogg" => Ok(ExportFormat::OggOpus),
+            _ => Err(format!(
+                "Invalid format '{}', expected one of: wav, flac, opus",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioMetadata {
+    pub title: String,
+    pub author: String,
+    pub voice: String,
+}
+
+impl AudioMetadata {
+    // Vorbis comments shared by FLAC and Ogg, the voice goes in as the performer.
+    // Chapters follow the CHAPTERxxx convention most audiobook players read
+    fn vorbis_comments(&self, markers: &[ChapterMarker]) -> Vec<String> {
+        let mut comments = [
+            ("TITLE", &self.title),
+            ("ARTIST", &self.author),
+            ("PERFORMER", &self.voice),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>();
+
+        for (i, marker) in markers.iter().enumerate() {
+            let start = marker.start();
+            comments.push(format!(
+                "CHAPTER{:03}={:02}:{:02}:{:02}.{:03}",
+                i + 1,
+                start.as_secs() / 3600,
+                start.as_secs() / 60 % 60,
+                start.as_secs() % 60,
+                start.subsec_millis()
+            ));
+            comments.push(format!("CHAPTER{:03}NAME={}", i + 1, marker.title));
+        }
+        comments
+    }
+}
+
+// Streams interleaved samples in -1.0..1.0 into an output of some format
+pub trait AudioEncoder<W> {
+    fn write_samples(&mut self, samples: &[f32]) -> EngineResult<()>;
+
+    // Frames written so far, where a chapter starting now gets its marker
+    fn frames_written(&self) -> u64;
+
+    // Hands the output back once the tags and chapters are in
+    fn finish(self: Box<Self>, markers: &[ChapterMarker]) -> EngineResult<W>;
+}
+
+// Encodes into files as well as into memory. The output is read back when chapters make
+// room for themselves in the header
+pub fn create_encoder<W: Read + Write + Seek + 'static>(
+    format: ExportFormat,
+    output: W,
+    channels: u16,
+    sample_rate: u32,
+    metadata: &AudioMetadata,
+) -> EngineResult<Box<dyn AudioEncoder<W>>> {
+    Ok(match format {
+        ExportFormat::Wav => Box::new(wav::WavEncoder::create(
+            output,
+            channels,
+            sample_rate,
+            metadata,
+        )?),
+        ExportFormat::Flac => Box::new(flac::FlacEncoder::create(
+            output,
+            channels,
+            sample_rate,
+            metadata,
+        )?),
+        ExportFormat::OggOpus => Box::new(ogg_opus::OggOpusEncoder::create(
+            output,
+            channels,
+            sample_rate,
+            metadata,
+        )?),
+    })
+}
+
+// A file for `create_encoder`, which may read it back
+pub fn create_file(path: &Path) -> io::Result<File> {
+    File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+// Comment header body shared by FLAC and Ogg. Unlike the rest of FLAC it's little endian
+fn vorbis_comment_data(metadata: &AudioMetadata, markers: &[ChapterMarker]) -> Vec<u8> {
+    let comments = metadata.vorbis_comments(markers);
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+    data.extend_from_slice(VENDOR.as_bytes());
+    data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        data.extend_from_slice(comment.as_bytes());
+    }
+    data
+}
+
+// Swaps the first `old_len` bytes of the output for the longer `header`. Tags go before
+// the audio but chapters are only known after it, so the audio is moved back in place,
+// from its end so nothing gets overwritten before it's read
+fn replace_header<W: Read + Write + Seek>(
+    output: &mut W,
+    old_len: u64,
+    header: &[u8],
+) -> EngineResult<()> {
+    let shift = (header.len() as u64)
+        .checked_sub(old_len)
+        .ok_or("Header can't get shorter than the one written")?;
+    let mut buffer = vec![0; MOVE_BUFFER_LEN];
+    let mut end = output.seek(SeekFrom::End(0))?;
+    while end > old_len {
+        let start = end.saturating_sub(buffer.len() as u64).max(old_len);
+        let chunk = &mut buffer[..(end - start) as usize];
+        output.seek(SeekFrom::Start(start))?;
+        output.read_exact(chunk)?;
+        output.seek(SeekFrom::Start(start + shift))?;
+        output.write_all(chunk)?;
+        end = start;
+    }
+    output.seek(SeekFrom::Start(0))?;
+    output.write_all(header)?;
+    output.flush()?;
+    Ok(())
+}
+
+// Writes the chapters as a CUE sheet next to the audio file, for players that don't
+// read chapters from the file itself
+pub fn write_cue_sheet(
+    audio_path: &Path,
+    metadata: &AudioMetadata,
+    markers: &[ChapterMarker],
+) -> EngineResult<PathBuf> {
+    let cue_path = audio_path.with_extension("cue");
+    let file_name = audio_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    fs::write(&cue_path, cue_sheet(&file_name, metadata, markers))?;
+    Ok(cue_path)
+}
+
+fn cue_sheet(file_name: &str, metadata: &AudioMetadata, markers: &[ChapterMarker]) -> String {
+    // Quotes can't be escaped in CUE sheets
+    let quoted = |value: &str| format!("\"{}\"", value.replace('"', "'"));
+
+    let mut sheet = String::new();
+    if !metadata.author.is_empty() {
+        sheet.push_str(&format!("PERFORMER {}\n", quoted(&metadata.author)));
+    }
+    if !metadata.title.is_empty() {
+        sheet.push_str(&format!("TITLE {}\n", quoted(&metadata.title)));
+    }
+    sheet.push_str(&format!("FILE {} WAVE\n", quoted(file_name)));
+
+    for (i, marker) in markers.iter().enumerate() {
+        sheet.push_str(&format!("  TRACK {:02} AUDIO\n", i + 1));
+        sheet.push_str(&format!("    TITLE {}\n", quoted(&marker.title)));
+        sheet.push_str(&format!("    INDEX 01 {}\n", cue_time(marker.start())));
+    }
+    sheet
+}
+
+// Minutes, seconds and frames, of which CUE sheets count 75 per second
+fn cue_time(duration: Duration) -> String {
+    let frames = duration.as_millis() as u64 * 75 / 1000;
+    format!(
+        "{:02}:{:02}:{:02}",
+        frames / 75 / 60,
+        frames / 75 % 60,
+        frames % 75
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_name_and_path() {
+        assert_eq!("flac".parse::<ExportFormat>(), Ok(ExportFormat::Flac));
+        assert_eq!("ogg".parse::<ExportFormat>(), Ok(ExportFormat::OggOpus));
+        assert!("mp4".parse::<ExportFormat>().is_err());
+
+        assert_eq!(
+            ExportFormat::from_path(Path::new("/tmp/book.OPUS")),
+            Some(ExportFormat::OggOpus)
+        );
+        assert_eq!(ExportFormat::from_path(Path::new("/tmp/book")), None);
+        for format in ExportFormat::ALL {
+            assert_eq!(format.extension().parse::<ExportFormat>(), Ok(format));
+        }
+    }
+
+    #[test]
+    fn test_empty_tags_are_skipped() {
+        let metadata = AudioMetadata {
+            title: "Book".to_string(),
+            author: String::new(),
+            voice: "af_heart".to_string(),
+        };
+
+        assert_eq!(
+            metadata.vorbis_comments(&[]),
+            vec!["TITLE=Book", "PERFORMER=af_heart"]
+        );
+    }
+
+    #[test]
+    fn test_header_grows_in_place() {
+        let audio = (0..MOVE_BUFFER_LEN * 2 + 10)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        let mut output = io::Cursor::new([b"head".as_slice(), &audio].concat());
+
+        replace_header(&mut output, 4, b"longer head").unwrap();
+        assert_eq!(output.get_ref()[..11], *b"longer head");
+        assert_eq!(output.get_ref()[11..], audio);
+        assert!(replace_header(&mut output, 11, b"head").is_err());
+    }
+
+    fn markers() -> Vec<ChapterMarker> {
+        vec![
+            ChapterMarker {
+                title: "Introduction".to_string(),
+                frame: 0,
+                sample_rate: 24000,
+            },
+            ChapterMarker {
+                title: "The \"End\"".to_string(),
+                frame: 24000 * 3725 + 12000,
+                sample_rate: 24000,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_chapters_as_vorbis_comments() {
+        let comments = AudioMetadata::default().vorbis_comments(&markers());
+
+        assert_eq!(
+            comments,
+            vec![
+                "CHAPTER001=00:00:00.000",
+                "CHAPTER001NAME=Introduction",
+                "CHAPTER002=01:02:05.500",
+                "CHAPTER002NAME=The \"End\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cue_sheet_lists_chapters() {
+        let metadata = AudioMetadata {
+            title: "Book".to_string(),
+            ..Default::default()
+        };
+
+        let sheet = cue_sheet("book.opus", &metadata, &markers());
+
+        assert_eq!(
+            sheet,
+            "TITLE \"Book\"\n\
+             FILE \"book.opus\" WAVE\n  \
+             TRACK 01 AUDIO\n    TITLE \"Introduction\"\n    INDEX 01 00:00:00\n  \
+             TRACK 02 AUDIO\n    TITLE \"The 'End'\"\n    INDEX 01 62:05:37\n"
+        );
+    }
+}
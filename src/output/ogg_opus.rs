@@ -0,0 +1,435 @@
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use opus::{Application, Bitrate, Channels};
+use std::{
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{replace_header, vorbis_comment_data, AudioEncoder, AudioMetadata};
+use crate::core::{audiobook::ChapterMarker, speech_engine::EngineResult};
+
+// Granule positions and the pre-skip are always counted at 48kHz
+const GRANULE_RATE: u32 = 48000;
+const OPUS_RATES: [u32; 5] = [8000, 12000, 16000, 24000, 48000];
+const FRAME_DURATION_MS: u32 = 20;
+// Plenty for speech, which is all that gets exported
+const BITRATE_PER_CHANNEL: i32 = 32000;
+// Largest packet libopus recommends reserving room for
+const MAX_PACKET_SIZE: usize = 4000;
+
+pub struct OggOpusEncoder<W: Write> {
+    writer: PacketWriter<BufWriter<W>>,
+    encoder: opus::Encoder,
+    serial: u32,
+    metadata: AudioMetadata,
+    head: Vec<u8>,
+    // Bytes of the header pages before the first audio page
+    header_len: u64,
+    channels: usize,
+    // Rate fed to libopus, input at other rates is resampled to 48kHz
+    encoder_rate: u32,
+    resampler: Option<LinearResampler>,
+    // Interleaved samples not making up a full Opus frame yet
+    pending: Vec<f32>,
+    // Held back so the last packet can end the stream
+    last_packet: Option<Vec<u8>>,
+    pre_skip: u64,
+    packets_written: u64,
+    input_frames: u64,
+    input_rate: u32,
+}
+
+impl<W: Read + Write + Seek> OggOpusEncoder<W> {
+    pub fn create(
+        output: W,
+        channels: u16,
+        sample_rate: u32,
+        metadata: &AudioMetadata,
+    ) -> EngineResult<Self> {
+        let opus_channels = match channels {
+            1 => Channels::Mono,
+            2 => Channels::Stereo,
+            _ => return Err(format!("Opus supports 1 or 2 channels, got {}", channels).into()),
+        };
+
+        let (encoder_rate, resampler) = if OPUS_RATES.contains(&sample_rate) {
+            (sample_rate, None)
+        } else {
+            let resampler = LinearResampler::new(sample_rate, GRANULE_RATE, channels as usize);
+            (GRANULE_RATE, Some(resampler))
+        };
+
+        let mut encoder = opus::Encoder::new(encoder_rate, opus_channels, Application::Audio)?;
+        encoder.set_bitrate(Bitrate::Bits(BITRATE_PER_CHANNEL * channels as i32))?;
+        let pre_skip = encoder.get_lookahead()? as u64 * (GRANULE_RATE / encoder_rate) as u64;
+
+        let serial = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        let head = opus_head(channels as u8, pre_skip as u16, sample_rate);
+        let mut writer = PacketWriter::new(BufWriter::new(output));
+        write_headers(&mut writer, serial, &head, opus_tags(metadata, &[]))?;
+        let header_len = writer.inner_mut().stream_position()?;
+
+        Ok(Self {
+            writer,
+            encoder,
+            serial,
+            metadata: metadata.clone(),
+            head,
+            header_len,
+            channels: channels as usize,
+            encoder_rate,
+            resampler,
+            pending: Vec::new(),
+            last_packet: None,
+            pre_skip,
+            packets_written: 0,
+            input_frames: 0,
+            input_rate: sample_rate,
+        })
+    }
+
+    fn frame_len(&self) -> usize {
+        (self.encoder_rate * FRAME_DURATION_MS / 1000) as usize * self.channels
+    }
+
+    fn encode_pending_frames(&mut self) -> EngineResult<()> {
+        let frame_len = self.frame_len();
+        let mut packet = vec![0; MAX_PACKET_SIZE];
+
+        let mut start = 0;
+        while self.pending.len() - start >= frame_len {
+            let frame = &self.pending[start..start + frame_len];
+            let len = self.encoder.encode_float(frame, &mut packet)?;
+            if let Some(previous) = self.last_packet.replace(packet[..len].to_vec()) {
+                self.write_packet(previous, PacketWriteEndInfo::NormalPacket, None)?;
+            }
+            start += frame_len;
+        }
+
+        self.pending.drain(..start);
+        Ok(())
+    }
+
+    fn write_packet(
+        &mut self,
+        packet: Vec<u8>,
+        end_info: PacketWriteEndInfo,
+        granule: Option<u64>,
+    ) -> EngineResult<()> {
+        self.packets_written += 1;
+        let granule_frame = (GRANULE_RATE * FRAME_DURATION_MS / 1000) as u64;
+        let granule = granule.unwrap_or(self.packets_written * granule_frame);
+        self.writer
+            .write_packet(packet.into(), self.serial, end_info, granule)?;
+        Ok(())
+    }
+}
+
+impl<W: Read + Write + Seek> AudioEncoder<W> for OggOpusEncoder<W> {
+    fn write_samples(&mut self, samples: &[f32]) -> EngineResult<()> {
+        match self.resampler.as_mut() {
+            Some(resampler) => resampler.process(samples, &mut self.pending),
+            None => self.pending.extend_from_slice(samples),
+        }
+        self.input_frames += (samples.len() / self.channels) as u64;
+        self.encode_pending_frames()
+    }
+
+    fn frames_written(&self) -> u64 {
+        self.input_frames
+    }
+
+    fn finish(mut self: Box<Self>, markers: &[ChapterMarker]) -> EngineResult<W> {
+        // The encoder lags behind by its lookahead, so it's flushed with silence and
+        // the end trimmed through the final granule position
+        let lookahead = self.pre_skip as usize / (GRANULE_RATE / self.encoder_rate) as usize;
+        let frame_len = self.frame_len();
+        let padded_len =
+            (self.pending.len() + lookahead * self.channels).div_ceil(frame_len) * frame_len;
+        self.pending.resize(padded_len, 0.0);
+        self.encode_pending_frames()?;
+
+        let duration = self.input_frames * GRANULE_RATE as u64 / self.input_rate as u64;
+        let final_granule = self.pre_skip + duration;
+        let packet = self.last_packet.take().unwrap_or_default();
+        self.write_packet(packet, PacketWriteEndInfo::EndStream, Some(final_granule))?;
+
+        let mut output = self
+            .writer
+            .into_inner()
+            .into_inner()
+            .map_err(|e| e.into_error())?;
+        if markers.is_empty() {
+            output.flush()?;
+            return Ok(output);
+        }
+
+        // Audio pages are moved back as they are, comments spanning more than a single
+        // page shift their sequence numbers
+        let mut header = PacketWriter::new(Vec::new());
+        let tags = opus_tags(&self.metadata, markers);
+        write_headers(&mut header, self.serial, &self.head, tags)?;
+        let header = header.into_inner();
+        replace_header(&mut output, self.header_len, &header)?;
+        let header_pages = pages(&header).count() as u32;
+        if header_pages > 2 {
+            renumber_pages(&mut output, header.len() as u64, header_pages - 2)?;
+        }
+        Ok(output)
+    }
+}
+
+// Both headers go on pages of their own, as the spec requires
+fn write_headers<W: Write>(
+    writer: &mut PacketWriter<W>,
+    serial: u32,
+    head: &[u8],
+    tags: Vec<u8>,
+) -> EngineResult<()> {
+    writer.write_packet(head.into(), serial, PacketWriteEndInfo::EndPage, 0)?;
+    writer.write_packet(tags.into(), serial, PacketWriteEndInfo::EndPage, 0)?;
+    Ok(())
+}
+
+// Ogg pages of a stream as they follow each other, by their header
+fn pages(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = bytes;
+    std::iter::from_fn(move || {
+        let segments = *rest.get(26)? as usize;
+        let body_len = rest.get(27..27 + segments)?.iter().map(|len| *len as usize);
+        let (page, next) = rest.split_at((27 + segments + body_len.sum::<usize>()).min(rest.len()));
+        rest = next;
+        Some(page)
+    })
+}
+
+// Adds `shift` to the sequence numbers of the pages from `start` on, each with its
+// checksum updated
+fn renumber_pages<W: Read + Write + Seek>(
+    output: &mut W,
+    start: u64,
+    shift: u32,
+) -> EngineResult<()> {
+    let end = output.seek(SeekFrom::End(0))?;
+    let mut position = start;
+    while position < end {
+        let mut page = vec![0; 27];
+        output.seek(SeekFrom::Start(position))?;
+        output.read_exact(&mut page)?;
+        let segments = page[26] as usize;
+        page.resize(27 + segments, 0);
+        output.read_exact(&mut page[27..])?;
+        let body_len = page[27..].iter().map(|len| *len as usize).sum::<usize>();
+        page.resize(27 + segments + body_len, 0);
+        output.read_exact(&mut page[27 + segments..])?;
+
+        let sequence = u32::from_le_bytes(page[18..22].try_into().unwrap()) + shift;
+        page[18..22].copy_from_slice(&sequence.to_le_bytes());
+        page[22..26].fill(0);
+        let checksum = page_checksum(&page);
+        page[22..26].copy_from_slice(&checksum.to_le_bytes());
+        output.seek(SeekFrom::Start(position))?;
+        output.write_all(&page)?;
+        position += page.len() as u64;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+// CRC-32 with the 0x04c11db7 polynomial, unreflected and starting at 0, over the page
+// with its checksum field zeroed
+fn page_checksum(page: &[u8]) -> u32 {
+    page.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u32) << 24), |crc, _| {
+            match crc & 0x8000_0000 {
+                0 => crc << 1,
+                _ => (crc << 1) ^ 0x04c1_1db7,
+            }
+        })
+    })
+}
+
+// Identification header from RFC 7845, with channel mapping family 0 for mono/stereo
+fn opus_head(channels: u8, pre_skip: u16, input_rate: u32) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.push(1);
+    head.push(channels);
+    head.extend_from_slice(&pre_skip.to_le_bytes());
+    head.extend_from_slice(&input_rate.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes());
+    head.push(0);
+    head
+}
+
+fn opus_tags(metadata: &AudioMetadata, markers: &[ChapterMarker]) -> Vec<u8> {
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&vorbis_comment_data(metadata, markers));
+    tags
+}
+
+// Linear interpolation is enough for speech and keeps state across blocks, so there are
+// no clicks where they meet
+struct LinearResampler {
+    step: f64,
+    channels: usize,
+    // Position in the input, relative to the last frame of the previous call
+    position: f64,
+    previous: Vec<f32>,
+}
+
+impl LinearResampler {
+    fn new(from_rate: u32, to_rate: u32, channels: usize) -> Self {
+        Self {
+            step: from_rate as f64 / to_rate as f64,
+            channels,
+            position: 1.0,
+            previous: vec![0.0; channels],
+        }
+    }
+
+    fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        let frames = input.len() / self.channels;
+        let frame = |index: usize| match index {
+            0 => &self.previous[..],
+            _ => &input[(index - 1) * self.channels..index * self.channels],
+        };
+
+        while self.position < frames as f64 {
+            let index = self.position as usize;
+            let fraction = (self.position - index as f64) as f32;
+            let (current, next) = (frame(index), frame(index + 1));
+            out.extend(
+                current
+                    .iter()
+                    .zip(next)
+                    .map(|(a, b)| a + (b - a) * fraction),
+            );
+            self.position += self.step;
+        }
+
+        if frames > 0 {
+            self.position -= frames as f64;
+            self.previous = input[(frames - 1) * self.channels..].to_vec();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ogg::reading::PacketReader;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_headers_follow_spec_layout() {
+        let head = opus_head(1, 312, 22050);
+        assert_eq!(head.len(), 19);
+        assert_eq!(&head[..8], b"OpusHead");
+        assert_eq!(u16::from_le_bytes([head[10], head[11]]), 312);
+        assert_eq!(u32::from_le_bytes(head[12..16].try_into().unwrap()), 22050);
+
+        let metadata = AudioMetadata {
+            title: "Book".to_string(),
+            ..Default::default()
+        };
+        let tags = opus_tags(&metadata, &[]);
+        assert_eq!(&tags[..8], b"OpusTags");
+        assert!(tags.ends_with(b"\x01\0\0\0\x0a\0\0\0TITLE=Book"));
+    }
+
+    #[test]
+    fn test_page_checksum() {
+        // Check value of CRC-32/CKSUM, which inverts the result at the end
+        assert_eq!(!page_checksum(b"123456789"), 0x765e_7680);
+    }
+
+    #[test]
+    fn test_resampling_keeps_duration_across_blocks() {
+        let mut resampler = LinearResampler::new(22050, 48000, 1);
+        let mut out = Vec::new();
+        let input = (0..22050).map(|i| i as f32 / 22050.0).collect::<Vec<_>>();
+        for block in input.chunks(1000) {
+            resampler.process(block, &mut out);
+        }
+
+        // Only the interval after the last input frame is held back for the next block
+        assert!((out.len() as i64 - 48000).abs() <= 3);
+        // A ramp stays a ramp, including where blocks meet
+        assert!(out
+            .windows(2)
+            .all(|w| (w[1] - w[0] - 1.0 / 48000.0).abs() < 1e-5));
+    }
+
+    // Second of audio encoded with the chapters, the stream checked to end on time with
+    // its pages in sequence
+    fn encode_with_chapters(markers: &[ChapterMarker]) -> Vec<u8> {
+        let samples = (0..24000)
+            .map(|i| (i as f32 * 0.05).sin() * 0.5)
+            .collect::<Vec<_>>();
+
+        let output = Cursor::new(Vec::new());
+        let mut encoder =
+            Box::new(OggOpusEncoder::create(output, 1, 24000, &AudioMetadata::default()).unwrap());
+        for block in samples.chunks(7000) {
+            encoder.write_samples(block).unwrap();
+        }
+        assert_eq!(encoder.frames_written(), 24000);
+        let bytes = encoder.finish(markers).unwrap().into_inner();
+
+        // The first page holds only OpusHead, after the 27 byte header and 1 lacing value
+        assert_eq!(&bytes[28..36], b"OpusHead");
+        let pre_skip = u16::from_le_bytes([bytes[38], bytes[39]]) as u64;
+
+        // Pages after the rewritten comments still follow on in sequence
+        let sequence = pages(&bytes)
+            .map(|page| u32::from_le_bytes(page[18..22].try_into().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(sequence, (0..sequence.len() as u32).collect::<Vec<_>>());
+
+        let last_page = pages(&bytes).last().unwrap();
+        let header_type = last_page[5];
+        let granule = u64::from_le_bytes(last_page[6..14].try_into().unwrap());
+        assert_eq!(header_type & 0x04, 0x04);
+        assert_eq!(granule, pre_skip + 48000);
+        bytes
+    }
+
+    #[test]
+    fn test_stream_ends_at_input_duration_with_chapters() {
+        let markers = [ChapterMarker {
+            title: "Intro".to_string(),
+            frame: 0,
+            sample_rate: 24000,
+        }];
+        let bytes = encode_with_chapters(&markers);
+        assert!(bytes.windows(20).any(|w| w == b"CHAPTER001NAME=Intro"));
+    }
+
+    #[test]
+    fn test_comments_larger_than_a_page_keep_chapters() {
+        let markers = (0..600)
+            .map(|i| ChapterMarker {
+                title: format!("Chapter {} {}", i, "with a long title ".repeat(5)),
+                frame: i * 40,
+                sample_rate: 24000,
+            })
+            .collect::<Vec<_>>();
+        let tags = opus_tags(&AudioMetadata::default(), &markers);
+        // Largest packet fitting the 255 segments of a single page
+        assert!(tags.len() > 255 * 255 - 1);
+
+        let bytes = encode_with_chapters(&markers);
+        // The reader checks the checksum of every page
+        let mut reader = PacketReader::new(Cursor::new(bytes));
+        reader.read_packet_expected().unwrap();
+        assert_eq!(reader.read_packet_expected().unwrap().data, tags);
+        let mut audio_packets = 0;
+        while reader.read_packet().unwrap().is_some() {
+            audio_packets += 1;
+        }
+        assert!(audio_packets >= 50);
+    }
+}
@@ -0,0 +1,150 @@
+use std::{fs, path::Path, str::FromStr, time::Duration};
+
+use crate::core::speech_engine::EngineResult;
+
+// A reading block with the span of the exported audio it was spoken in
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubtitleCue {
+    pub text: String,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    Srt,
+    WebVtt,
+}
+
+impl SubtitleFormat {
+    pub const ALL: [SubtitleFormat; 2] = [Self::Srt, Self::WebVtt];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::WebVtt => "vtt",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "SRT",
+            SubtitleFormat::WebVtt => "WebVTT",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.to_lowercase().parse().ok()
+    }
+
+    pub fn render(&self, cues: &[SubtitleCue]) -> String {
+        let mut out = String::new();
+        if *self == SubtitleFormat::WebVtt {
+            out.push_str("WEBVTT\n\n");
+        }
+
+        for (i, cue) in cues.iter().enumerate() {
+            // A blank line ends a cue, so blocks spanning lines are joined into one
+            let text = cue.text.split_whitespace().collect::<Vec<_>>().join(" ");
+            let (start, end) = (self.timestamp(cue.start), self.timestamp(cue.end));
+            match self {
+                SubtitleFormat::Srt => {
+                    out.push_str(&format!("{}\n{} --> {}\n{}\n\n", i + 1, start, end, text));
+                }
+                SubtitleFormat::WebVtt => {
+                    let text = text
+                        .replace('&', "&amp;")
+                        .replace('<', "&lt;")
+                        .replace('>', "&gt;");
+                    out.push_str(&format!("{} --> {}\n{}\n\n", start, end, text));
+                }
+            }
+        }
+        out
+    }
+
+    // SRT separates milliseconds with a comma, WebVTT with a dot
+    fn timestamp(&self, duration: Duration) -> String {
+        let secs = duration.as_secs();
+        let separator = match self {
+            SubtitleFormat::Srt => ',',
+            SubtitleFormat::WebVtt => '.',
+        };
+        format!(
+            "{:02}:{:02}:{:02}{}{:03}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            separator,
+            duration.subsec_millis()
+        )
+    }
+}
+
+impl FromStr for SubtitleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "srt" => Ok(SubtitleFormat::Srt),
+            "vtt" | "webvtt" => Ok(SubtitleFormat::WebVtt),
+            _ => Err(format!(
+                "Invalid subtitle format '{}', expected one of: srt, vtt",
+                s
+            )),
+        }
+    }
+}
+
+pub fn write_subtitles(
+    path: &Path,
+    format: SubtitleFormat,
+    cues: &[SubtitleCue],
+) -> EngineResult<()> {
+    fs::write(path, format.render(cues))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cues() -> Vec<SubtitleCue> {
+        vec![
+            SubtitleCue {
+                text: "First block.".to_string(),
+                start: Duration::ZERO,
+                end: Duration::from_millis(1250),
+            },
+            SubtitleCue {
+                text: "Spans\n\nlines & <tags>.".to_string(),
+                start: Duration::from_millis(3_661_005),
+                end: Duration::from_millis(3_662_500),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_srt_cues() {
+        assert_eq!(
+            SubtitleFormat::Srt.render(&cues()),
+            "1\n00:00:00,000 --> 00:00:01,250\nFirst block.\n\n\
+             2\n01:01:01,005 --> 01:01:02,500\nSpans lines & <tags>.\n\n"
+        );
+    }
+
+    #[test]
+    fn test_webvtt_cues() {
+        assert_eq!(
+            SubtitleFormat::WebVtt.render(&cues()),
+            "WEBVTT\n\n\
+             00:00:00.000 --> 00:00:01.250\nFirst block.\n\n\
+             01:01:01.005 --> 01:01:02.500\nSpans lines &amp; &lt;tags&gt;.\n\n"
+        );
+        assert_eq!(
+            SubtitleFormat::from_path(Path::new("/tmp/book.VTT")),
+            Some(SubtitleFormat::WebVtt)
+        );
+        assert_eq!(SubtitleFormat::from_path(Path::new("/tmp/book.txt")), None);
+    }
+}
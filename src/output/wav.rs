@@ -0,0 +1,218 @@
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::{
+    cell::RefCell,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    rc::Rc,
+};
+
+use super::{AudioEncoder, AudioMetadata, VENDOR};
+use crate::core::{audiobook::ChapterMarker, speech_engine::EngineResult};
+
+pub struct WavEncoder<W: Write + Seek> {
+    writer: WavWriter<SharedOutput<BufWriter<W>>>,
+    output: Rc<RefCell<BufWriter<W>>>,
+    metadata: AudioMetadata,
+}
+
+impl<W: Write + Seek> WavEncoder<W> {
+    pub fn create(
+        output: W,
+        channels: u16,
+        sample_rate: u32,
+        metadata: &AudioMetadata,
+    ) -> EngineResult<Self> {
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+
+        let output = Rc::new(RefCell::new(BufWriter::new(output)));
+        Ok(Self {
+            writer: WavWriter::new(SharedOutput(output.clone()), spec)?,
+            output,
+            metadata: metadata.clone(),
+        })
+    }
+}
+
+impl<W: Write + Seek> AudioEncoder<W> for WavEncoder<W> {
+    fn write_samples(&mut self, samples: &[f32]) -> EngineResult<()> {
+        for sample in samples {
+            self.writer.write_sample(*sample)?;
+        }
+        Ok(())
+    }
+
+    fn frames_written(&self) -> u64 {
+        self.writer.duration() as u64
+    }
+
+    fn finish(self: Box<Self>, markers: &[ChapterMarker]) -> EngineResult<W> {
+        let Self {
+            writer,
+            output,
+            metadata,
+        } = *self;
+        writer.finalize()?;
+        let mut output = Rc::into_inner(output)
+            .ok_or("WAV output is still in use")?
+            .into_inner();
+
+        let mut chunks = Vec::new();
+        if !markers.is_empty() {
+            append_cue_chunks(&mut chunks, markers);
+        }
+        append_info_chunk(&mut chunks, &metadata);
+        append_to_riff(&mut output, &chunks)?;
+        Ok(output.into_inner().map_err(|e| e.into_error())?)
+    }
+}
+
+// Hound keeps its writer until it's finalized, after which the output is taken back
+// to append the chunks it doesn't write
+struct SharedOutput<W>(Rc<RefCell<W>>);
+
+impl<W: Write> Write for SharedOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+impl<W: Seek> Seek for SharedOutput<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.borrow_mut().seek(pos)
+    }
+}
+
+// `cue ` points with their `labl` labels, the way most editors and players store
+// markers in WAV files
+fn append_cue_chunks(out: &mut Vec<u8>, markers: &[ChapterMarker]) {
+    let mut cue = Vec::new();
+    cue.extend_from_slice(&(markers.len() as u32).to_le_bytes());
+    for (id, marker) in markers.iter().enumerate() {
+        cue.extend_from_slice(&(id as u32 + 1).to_le_bytes());
+        cue.extend_from_slice(&marker.frame.to_le_bytes());
+        cue.extend_from_slice(b"data");
+        cue.extend_from_slice(&0u32.to_le_bytes());
+        cue.extend_from_slice(&0u32.to_le_bytes());
+        cue.extend_from_slice(&marker.frame.to_le_bytes());
+    }
+
+    let mut labels = b"adtl".to_vec();
+    for (id, marker) in markers.iter().enumerate() {
+        let mut label = (id as u32 + 1).to_le_bytes().to_vec();
+        label.extend_from_slice(marker.title.as_bytes());
+        label.push(0);
+        append_chunk(&mut labels, b"labl", &label);
+    }
+
+    append_chunk(out, b"cue ", &cue);
+    append_chunk(out, b"LIST", &labels);
+}
+
+fn append_info_chunk(out: &mut Vec<u8>, metadata: &AudioMetadata) {
+    let comment = if metadata.voice.is_empty() {
+        String::new()
+    } else {
+        format!("Voice: {}", metadata.voice)
+    };
+
+    let mut info = b"INFO".to_vec();
+    for (id, value) in [
+        (b"INAM", metadata.title.as_str()),
+        (b"IART", metadata.author.as_str()),
+        (b"ICMT", comment.as_str()),
+        (b"ISFT", VENDOR),
+    ] {
+        if !value.is_empty() {
+            append_chunk(&mut info, id, &[value.as_bytes(), &[0]].concat());
+        }
+    }
+
+    append_chunk(out, b"LIST", &info);
+}
+
+// RIFF chunks are padded to an even length
+fn append_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+// Chunks go after the audio data, so the RIFF size in the header is fixed up
+fn append_to_riff<W: Write + Seek>(output: &mut W, chunks: &[u8]) -> EngineResult<()> {
+    let riff_size = output.seek(SeekFrom::End(0))? + chunks.len() as u64 - 8;
+    output.write_all(chunks)?;
+    output.seek(SeekFrom::Start(4))?;
+    output.write_all(&(riff_size as u32).to_le_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Data of the chunks with the given id, including ones nested in lists
+    fn find_chunks(bytes: &[u8], id: &[u8; 4]) -> Vec<Vec<u8>> {
+        bytes
+            .windows(4)
+            .enumerate()
+            .filter(|(_, window)| window == id)
+            .map(|(pos, _)| {
+                let size = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap());
+                bytes[pos + 8..pos + 8 + size as usize].to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_markers_and_tags_are_appended() {
+        let metadata = AudioMetadata {
+            title: "Book".to_string(),
+            author: "Author".to_string(),
+            voice: "af_heart".to_string(),
+        };
+        let markers = vec![
+            ChapterMarker {
+                title: "Page 1".to_string(),
+                frame: 0,
+                sample_rate: 8000,
+            },
+            ChapterMarker {
+                title: "Page 2".to_string(),
+                frame: 50,
+                sample_rate: 8000,
+            },
+        ];
+
+        let output = io::Cursor::new(Vec::new());
+        let mut encoder = Box::new(WavEncoder::create(output, 1, 8000, &metadata).unwrap());
+        encoder.write_samples(&[0.25; 100]).unwrap();
+        assert_eq!(encoder.frames_written(), 100);
+        let bytes = encoder.finish(&markers).unwrap().into_inner();
+
+        let reader = hound::WavReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.duration(), 100);
+
+        let labels = find_chunks(&bytes, b"labl");
+        assert_eq!(labels[1], b"\x02\0\0\0Page 2\0".to_vec());
+        assert_eq!(find_chunks(&bytes, b"INAM")[0], b"Book\0".to_vec());
+        assert_eq!(
+            find_chunks(&bytes, b"ICMT")[0],
+            b"Voice: af_heart\0".to_vec()
+        );
+        assert_eq!(
+            u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize,
+            bytes.len() - 8
+        );
+    }
+}
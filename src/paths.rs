@@ -9,8 +9,11 @@ const SCHEMA_URL: &str = "https://raw.githubusercontent.com/kopecmaciej/fox-read
 
 const DISPATCHER_CONFIG_PATH: &str = "$HOME/.config/speech-dispatcher";
 const DISPATCHER_CONFIG_FILE: &str = "speechd.conf";
+const DISPATCHER_CONFIG_BACKUP_FILE: &str = "speechd.conf.fox-reader-backup";
+const DISPATCHER_SYSTEM_CONFIG_PATH: &str = "/etc/speech-dispatcher/speechd.conf";
 const DISPATCHER_MODULE_FILE: &str = "modules/fox-reader.conf";
 const DISPATCHER_SCRIPT_FILE: &str = "fox-reader.sh";
+pub const DISPATCHER_MODULE_BINARY: &str = "sd_fox-reader";
 
 pub fn resolve_home(path: &str) -> String {
     let home = home_dir().expect("Failed to get home directory");
@@ -93,13 +96,44 @@ pub mod dispatcher_config {
         build_path(DISPATCHER_CONFIG_PATH, DISPATCHER_CONFIG_FILE)
     }
 
+    // Copy of the user's config from before Fox Reader first changed it
+    pub fn get_config_backup_path() -> String {
+        build_path(DISPATCHER_CONFIG_PATH, DISPATCHER_CONFIG_BACKUP_FILE)
+    }
+
+    // A user config replaces this one, so new user configs start as a copy of it
+    pub fn get_system_config_path() -> String {
+        DISPATCHER_SYSTEM_CONFIG_PATH.to_string()
+    }
+
     pub fn get_module_config_path() -> String {
         build_path(DISPATCHER_CONFIG_PATH, DISPATCHER_MODULE_FILE)
     }
 
+    // Only written by older versions, which ran the module through sd_generic
     pub fn get_script_path() -> String {
         build_path(DISPATCHER_CONFIG_PATH, DISPATCHER_SCRIPT_FILE)
     }
+
+    // Link to the fox-reader binary, which runs as the module when started by this name
+    pub fn get_module_binary_path() -> String {
+        build_path(FOX_READER_BASE_PATH, DISPATCHER_MODULE_BINARY)
+    }
+}
+
+pub mod daemon_config {
+    use super::*;
+
+    // Sockets belong in the runtime directory, which is cleaned up on logout. Without one
+    // the socket goes in a directory of its own, which the daemon keeps private
+    pub fn get_socket_path() -> String {
+        match std::env::var("XDG_RUNTIME_DIR") {
+            Ok(runtime_dir) if !runtime_dir.is_empty() => {
+                build_path(&runtime_dir, "fox-reader.sock")
+            }
+            _ => build_path(FOX_READER_BASE_PATH, "run/fox-reader.sock"),
+        }
+    }
 }
 
 pub mod voice_config {
@@ -112,6 +146,15 @@ pub mod voice_config {
     pub fn get_kokoros_voices_path() -> String {
         build_path(FOX_READER_BASE_PATH, "kokoros/voices-v1.0.bin")
     }
+
+    // Voice catalog of the user, extending the bundled one
+    pub fn get_catalog_override_path() -> String {
+        build_path(FOX_READER_BASE_PATH, "voices.json")
+    }
+
+    pub fn get_presets_path() -> String {
+        build_path(FOX_READER_BASE_PATH, "voice-presets.json")
+    }
 }
 
 pub mod schema_config {
@@ -4,7 +4,9 @@ use gtk::{gdk::RGBA, gio, glib, pango::FontDescription};
 use serde::{Deserialize, Serialize};
 use std::{fmt::Display, ops::Deref};
 
-use crate::{paths::whisper_config::get_whisper_models_names, APP_ID};
+use crate::{
+    paths::whisper_config::get_whisper_models_names, utils::audio_player::AudioOutput, APP_ID,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProviderConfig {
@@ -349,6 +351,80 @@ impl Settings {
             .expect("Failed to set default voice");
     }
 
+    pub fn get_lookahead_blocks(&self) -> u32 {
+        self.uint("lookahead-blocks")
+    }
+
+    pub fn get_volume(&self) -> f64 {
+        self.double("volume")
+    }
+
+    pub fn set_volume(&self, volume: f64) {
+        self.set_double("volume", volume)
+            .expect("Failed to set volume");
+    }
+
+    pub fn get_pitch(&self) -> f64 {
+        self.double("pitch")
+    }
+
+    pub fn set_pitch(&self, pitch: f64) {
+        self.set_double("pitch", pitch)
+            .expect("Failed to set pitch");
+    }
+
+    pub fn get_normalize_audio(&self) -> bool {
+        self.boolean("normalize-audio")
+    }
+
+    pub fn set_normalize_audio(&self, normalize: bool) {
+        self.set_boolean("normalize-audio", normalize)
+            .expect("Failed to set audio normalization");
+    }
+
+    pub fn get_detect_language(&self) -> bool {
+        self.boolean("detect-language")
+    }
+
+    pub fn set_detect_language(&self, detect: bool) {
+        self.set_boolean("detect-language", detect)
+            .expect("Failed to set language detection");
+    }
+
+    pub fn get_audio_output(&self) -> AudioOutput {
+        self.string("audio-output").parse().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            AudioOutput::Device
+        })
+    }
+
+    pub fn connect_pitch_changed<F: Fn(&gio::Settings, &str) + 'static>(
+        &self,
+        f: F,
+    ) -> glib::SignalHandlerId {
+        self.connect_changed(Some("pitch"), move |s, key| {
+            f(s, key);
+        })
+    }
+
+    pub fn connect_normalize_audio_changed<F: Fn(&gio::Settings, &str) + 'static>(
+        &self,
+        f: F,
+    ) -> glib::SignalHandlerId {
+        self.connect_changed(Some("normalize-audio"), move |s, key| {
+            f(s, key);
+        })
+    }
+
+    pub fn connect_detect_language_changed<F: Fn(&gio::Settings, &str) + 'static>(
+        &self,
+        f: F,
+    ) -> glib::SignalHandlerId {
+        self.connect_changed(Some("detect-language"), move |s, key| {
+            f(s, key);
+        })
+    }
+
     pub fn connect_default_voice_changed<F: Fn(&gio::Settings, &str) + 'static>(
         &self,
         f: F,
@@ -6,12 +6,15 @@ use gtk::{
 };
 use std::{
     cell::RefCell,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
 
 use crate::{
-    core::{llm_manager::LLMManager, runtime::spawn_tokio, voice_manager::VoiceManager},
+    core::{llm_manager::LLMManager, runtime::spawn_tokio, sentence_player::play_sentences},
     paths::whisper_config::get_model_path,
     settings::Settings,
     ui::dialogs::show_error_dialog,
@@ -68,6 +71,8 @@ mod imp {
         pub shared_audio_buffer: RefCell<Option<Arc<Mutex<Vec<f32>>>>>,
         pub llm_manager: Arc<LLMManager>,
         pub audio_player: Arc<audio_player::AudioPlayer>,
+        // Tells the reply being spoken to stop synthesizing
+        pub speech_stopped: Arc<AtomicBool>,
     }
 
     #[glib::object_subclass]
@@ -239,6 +244,7 @@ impl AiChat {
     fn stop_speaking(&self) {
         let imp = self.imp();
 
+        imp.speech_stopped.store(true, Ordering::SeqCst);
         imp.audio_player.stop();
         imp.status_label.set_text("Ready");
     }
@@ -456,75 +462,17 @@ impl AiChat {
             return;
         };
 
-        let speed = 1.0;
-        let mut current_audio = None;
-
-        if let Some(first_sentence) = sentences.first() {
-            let voice_clone = voice.clone();
-            let sentence_clone = first_sentence.clone();
-
-            match spawn_tokio(async move {
-                VoiceManager::generate_kokoros_speech(&sentence_clone, &voice_clone, speed).await
-            })
-            .await
-            {
-                Ok(audio) => current_audio = Some(audio),
-                Err(e) => {
-                    show_error_dialog(&format!("Error generating TTS: {}", e), self);
-                    return;
-                }
-            }
-        }
-
-        for i in 0..sentences.len() {
-            {
-                let mut state = imp.state.borrow_mut();
-                if *state == State::Stopped {
-                    *state = State::Idle;
-                    break;
-                }
-            }
-
-            let audio_to_play = if let Some(audio) = current_audio.take() {
-                audio
-            } else {
-                continue;
-            };
-
-            let next_index = i + 1;
-            if next_index < sentences.len() {
-                let next_sentence = sentences[next_index].clone();
-                let voice_clone = voice.clone();
-
-                let audio_player = self.imp().audio_player.clone();
-                let play_future =
-                    spawn_tokio(async move { audio_player.play_audio(audio_to_play) });
-
-                let generate_future = spawn_tokio(async move {
-                    VoiceManager::generate_kokoros_speech(&next_sentence, &voice_clone, speed).await
-                });
-
-                let (play_result, generate_result) = tokio::join!(play_future, generate_future);
-
-                if let Err(e) = play_result {
-                    show_error_dialog(&format!("Error playing audio: {}", e), self);
-                }
-
-                match generate_result {
-                    Ok(next_audio) => current_audio = Some(next_audio),
-                    Err(e) => {
-                        show_error_dialog(&format!("Error generating TTS: {}", e), self);
-                        break;
-                    }
-                }
-            } else {
-                let audio_player = self.imp().audio_player.clone();
-                if let Err(e) =
-                    spawn_tokio(async move { audio_player.play_audio(audio_to_play) }).await
-                {
-                    show_error_dialog(&format!("Error playing audio: {}", e), self);
-                }
-            }
+        imp.speech_stopped.store(false, Ordering::SeqCst);
+        let stopped = imp.speech_stopped.clone();
+        let player = imp.audio_player.clone();
+        let spoken = spawn_tokio(async move {
+            let is_stopped = move || stopped.load(Ordering::SeqCst);
+            let sentences = tokio_stream::iter(sentences);
+            play_sentences(sentences, &voice, 1.0, player, is_stopped).await
+        })
+        .await;
+        if let Err(e) = spoken {
+            show_error_dialog(&format!("Error speaking the response: {}", e), self);
         }
 
         {
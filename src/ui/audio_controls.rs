@@ -1,17 +1,30 @@
 use std::cell::RefCell;
 
-use crate::core::{runtime::runtime, tts::Tts};
-use crate::settings::Settings;
+use crate::core::{
+    audiobook::{AudiobookExporter, Chapter},
+    runtime::runtime,
+    tts::Tts,
+};
+use crate::output::AudioMetadata;
+use crate::utils::audio_effects::AudioEffects;
+use crate::{settings::Settings, SETTINGS};
 use gtk::{
     glib::{self, clone},
     prelude::*,
     subclass::prelude::*,
 };
 
-use super::{dialogs, helpers::voice_selector, voice_events::event_emiter, voice_row::VoiceRow};
+use super::{
+    dialogs,
+    export_dialog::{self, ExportDialog},
+    helpers::voice_selector,
+    voice_events::event_emiter,
+    voice_row::VoiceRow,
+};
 
 type PlayHandler = RefCell<Option<Box<dyn Fn(u32)>>>;
 type StopHandler = RefCell<Option<Box<dyn Fn()>>>;
+type ExportHandler = RefCell<Option<Box<dyn Fn() -> (Vec<Chapter>, AudioMetadata)>>>;
 
 mod imp {
 
@@ -35,9 +48,14 @@ mod imp {
         pub prev_button: TemplateChild<gtk::Button>,
         #[template_child]
         pub speed_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub volume_button: TemplateChild<gtk::ScaleButton>,
+        #[template_child]
+        pub export_button: TemplateChild<gtk::Button>,
         pub tts: Arc<Tts>,
         pub play_handler: PlayHandler,
         pub stop_handler: StopHandler,
+        pub export_handler: ExportHandler,
     }
 
     #[glib::object_subclass]
@@ -122,6 +140,11 @@ mod imp {
                 dialogs::show_error_dialog(&e.to_string(), button)
             }
         }
+
+        #[template_callback]
+        fn on_export_button_clicked(&self, _button: &gtk::Button) {
+            self.obj().export_audiobook();
+        }
     }
 
     impl ObjectImpl for AudioControls {}
@@ -136,6 +159,24 @@ glib::wrapper! {
 
 impl AudioControls {
     pub fn init(&self) {
+        let imp = self.imp();
+        let settings = Settings::default();
+        imp.tts
+            .set_lookahead(settings.get_lookahead_blocks() as usize);
+        imp.tts.set_volume(settings.get_volume() as f32);
+        imp.tts.set_pitch(settings.get_pitch() as f32);
+        imp.tts.set_normalize(settings.get_normalize_audio());
+        imp.tts
+            .set_language_detection(settings.get_detect_language());
+
+        imp.volume_button.set_icons(&[
+            "audio-volume-muted-symbolic",
+            "audio-volume-high-symbolic",
+            "audio-volume-low-symbolic",
+            "audio-volume-medium-symbolic",
+        ]);
+        imp.volume_button.set_value(settings.get_volume());
+
         self.setup_signals();
     }
 
@@ -173,6 +214,18 @@ impl AudioControls {
         );
     }
 
+    // Play button is dimmed while the block to read is still being synthesized
+    pub fn set_buffering(&self, buffering: bool) {
+        let button = &self.imp().play_button;
+        if buffering {
+            button.add_css_class("buffering");
+            button.set_tooltip_text(Some("Buffering…"));
+        } else {
+            button.remove_css_class("buffering");
+            button.set_tooltip_text(Some("Play"));
+        }
+    }
+
     pub fn start_audio(&self, id: u32) {
         let imp = self.imp();
         let button = &imp.play_button;
@@ -200,6 +253,80 @@ impl AudioControls {
         self.imp().stop_handler.replace(Some(Box::new(handler)));
     }
 
+    pub fn set_export_handler<F>(&self, handler: F)
+    where
+        F: Fn() -> (Vec<Chapter>, AudioMetadata) + 'static,
+    {
+        self.imp().export_handler.replace(Some(Box::new(handler)));
+    }
+
+    // Exports the whole document given by the export handler with the current voice,
+    // speed and audio settings
+    pub fn export_audiobook(&self) {
+        let imp = self.imp();
+        let (chapters, metadata) = match imp.export_handler.borrow().as_ref() {
+            Some(handler) => handler(),
+            None => {
+                dialogs::show_error_dialog("No export handler configured", self);
+                return;
+            }
+        };
+
+        if chapters.iter().all(|c| c.blocks.is_empty()) {
+            dialogs::show_error_dialog("There is no text to export", self);
+            return;
+        }
+
+        let Some(voice) = self.get_selected_voice_key() else {
+            dialogs::show_error_dialog("Select a voice to export with", self);
+            return;
+        };
+
+        let effects = AudioEffects {
+            pitch: SETTINGS.get_pitch() as f32,
+            normalize: SETTINGS.get_normalize_audio(),
+        };
+        let exporter = AudiobookExporter::new(
+            &voice,
+            (self.get_speed() / 100.0) as f32,
+            imp.volume_button.value() as f32,
+            effects,
+        );
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to=this)]
+            self,
+            async move {
+                let Some(options) = export_dialog::choose_export_options(&this, metadata).await
+                else {
+                    return;
+                };
+
+                let format = options.format;
+                let name = match options.metadata.title.trim() {
+                    "" => "audiobook".to_string(),
+                    title => title.replace('/', "-"),
+                };
+                let parent = this.root().and_downcast::<gtk::Window>();
+                let file =
+                    dialogs::save_audio_dialog(&format!("{}.{}", name, format.extension()), format)
+                        .save_future(parent.as_ref())
+                        .await;
+                let Some(path) = file.ok().and_then(|file| file.path()) else {
+                    return;
+                };
+
+                let export_dialog = ExportDialog::new();
+                if let Err(e) = export_dialog
+                    .export_and_show(&this, exporter, chapters, path, options)
+                    .await
+                {
+                    dialogs::show_error_dialog(&e.to_string(), &this);
+                }
+            }
+        ));
+    }
+
     pub fn populate_voice_selector(&self, voices: &[VoiceRow]) {
         voice_selector::populate_voice_selector(&self.imp().voice_selector, voices);
 
@@ -210,6 +337,38 @@ impl AudioControls {
         let imp = self.imp();
         let tts = imp.tts.clone();
 
+        imp.volume_button.connect_value_changed(clone!(
+            #[weak]
+            tts,
+            move |_, volume| {
+                tts.set_volume(volume as f32);
+                SETTINGS.set_volume(volume);
+            }
+        ));
+
+        let settings = &SETTINGS;
+        settings.connect_pitch_changed(clone!(
+            #[weak]
+            tts,
+            move |settings, key| {
+                tts.set_pitch(settings.double(key) as f32);
+            }
+        ));
+        settings.connect_normalize_audio_changed(clone!(
+            #[weak]
+            tts,
+            move |settings, key| {
+                tts.set_normalize(settings.boolean(key));
+            }
+        ));
+        settings.connect_detect_language_changed(clone!(
+            #[weak]
+            tts,
+            move |settings, key| {
+                tts.set_language_detection(settings.boolean(key));
+            }
+        ));
+
         let debounce_duration = std::time::Duration::from_millis(300);
         let timeout_handle = RefCell::new(None::<glib::SourceId>);
         imp.speed_spin.connect_value_changed(clone!(
@@ -1,5 +1,7 @@
 use adw::{prelude::*, AlertDialog};
 
+use crate::output::ExportFormat;
+
 pub fn show_error_dialog(err_msg: &str, widget: &impl IsA<gtk::Widget>) {
     let dialog = AlertDialog::builder()
         .heading("Error")
@@ -29,3 +31,18 @@ pub fn file_dialog() -> gtk::FileDialog {
     file_chooser.set_default_filter(Some(&filter));
     file_chooser
 }
+
+pub fn save_audio_dialog(initial_name: &str, format: ExportFormat) -> gtk::FileDialog {
+    let file_chooser = gtk::FileDialog::builder()
+        .title("Export Audiobook")
+        .accept_label("Export")
+        .initial_name(initial_name)
+        .modal(true)
+        .build();
+
+    let filter = gtk::FileFilter::new();
+    filter.add_suffix(format.extension());
+    filter.set_name(Some(format.label()));
+    file_chooser.set_default_filter(Some(&filter));
+    file_chooser
+}
@@ -0,0 +1,242 @@
+use adw::prelude::*;
+use adw::AlertDialog;
+use gtk::{
+    self,
+    glib::{self, clone},
+    prelude::IsA,
+};
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::core::audiobook::{AudiobookExporter, Chapter, ExportTimeline};
+use crate::core::runtime::runtime;
+use crate::core::speech_engine::EngineResult;
+use crate::output::{
+    self,
+    subtitles::{self, SubtitleFormat},
+    AudioMetadata, ExportFormat,
+};
+use crate::utils::progress_tracker::ProgressTracker;
+
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub metadata: AudioMetadata,
+    pub write_cue_sheet: bool,
+    // Captions are saved next to the audio, named after it
+    pub subtitles: Option<SubtitleFormat>,
+}
+
+pub struct ExportDialog {
+    dialog: AlertDialog,
+    progress_bar: gtk::ProgressBar,
+    status_label: gtk::Label,
+}
+
+impl Default for ExportDialog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExportDialog {
+    pub fn new() -> Self {
+        let dialog = AlertDialog::builder()
+            .heading("Exporting Audiobook")
+            .body("Synthesizing the whole document, this can take a while...")
+            .build();
+
+        dialog.add_response("cancel", "Cancel");
+        dialog.set_response_appearance("cancel", adw::ResponseAppearance::Destructive);
+
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 12);
+        vbox.set_margin_top(12);
+        vbox.set_margin_bottom(12);
+        vbox.set_margin_start(12);
+        vbox.set_margin_end(12);
+
+        let status_label = gtk::Label::new(Some("Initializing..."));
+        status_label.set_halign(gtk::Align::Start);
+        vbox.append(&status_label);
+
+        let progress_bar = gtk::ProgressBar::new();
+        progress_bar.set_show_text(true);
+        progress_bar.set_text(Some("0%"));
+        vbox.append(&progress_bar);
+
+        dialog.set_extra_child(Some(&vbox));
+
+        Self {
+            dialog,
+            progress_bar,
+            status_label,
+        }
+    }
+
+    // Cancelling through the dialog isn't an error, the partial file is just removed
+    pub async fn export_and_show(
+        &self,
+        parent: &impl IsA<gtk::Widget>,
+        exporter: AudiobookExporter,
+        chapters: Vec<Chapter>,
+        output_path: PathBuf,
+        options: ExportOptions,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.dialog.present(Some(parent));
+
+        let blocks = chapters.iter().map(|c| c.blocks.len()).sum::<usize>();
+        self.status_label.set_text(&format!(
+            "Exporting {} blocks in {} chapters...",
+            blocks,
+            chapters.len()
+        ));
+
+        let progress_tracker = ProgressTracker::default();
+        let progress_callback = progress_tracker.get_progress_callback();
+        let (on_complete, on_cancel) = progress_tracker.track_with_progress_bar(&self.progress_bar);
+
+        let exporter = Arc::new(exporter);
+        self.dialog.connect_response(
+            None,
+            clone!(
+                #[strong]
+                exporter,
+                move |_, response| {
+                    if response == "cancel" {
+                        exporter.cancel();
+                    }
+                }
+            ),
+        );
+
+        let export_task = runtime().spawn_blocking(clone!(
+            #[strong]
+            exporter,
+            #[strong]
+            output_path,
+            move || -> EngineResult<ExportTimeline> {
+                let timeline = exporter.export(
+                    &chapters,
+                    &output_path,
+                    options.format,
+                    &options.metadata,
+                    Some(progress_callback),
+                )?;
+                if options.write_cue_sheet {
+                    output::write_cue_sheet(&output_path, &options.metadata, &timeline.markers)?;
+                }
+                if let Some(format) = options.subtitles {
+                    let subtitles_path = output_path.with_extension(format.extension());
+                    subtitles::write_subtitles(&subtitles_path, format, &timeline.cues)?;
+                }
+                Ok(timeline)
+            }
+        ));
+
+        match export_task.await {
+            Ok(Ok(timeline)) => {
+                on_complete();
+                self.status_label.set_text(&format!(
+                    "Saved {} chapters to {}",
+                    timeline.markers.len(),
+                    output_path.display()
+                ));
+
+                let dialog_clone = self.dialog.clone();
+                glib::timeout_add_seconds_local(2, move || {
+                    dialog_clone.close();
+                    glib::ControlFlow::Break
+                });
+
+                Ok(())
+            }
+            Ok(Err(_)) if exporter.is_cancelled() => {
+                on_cancel();
+                Ok(())
+            }
+            Ok(Err(e)) => {
+                on_cancel();
+                self.dialog.close();
+                Err(format!("Failed to export audiobook: {}", e).into())
+            }
+            Err(e) => {
+                on_cancel();
+                self.dialog.close();
+                Err(format!("Export task failed: {}", e).into())
+            }
+        }
+    }
+}
+
+// Asks for the format and tags of the audiobook, prefilled with the document's tags.
+// Returns None when the user backs out
+pub async fn choose_export_options(
+    parent: &impl IsA<gtk::Widget>,
+    metadata: AudioMetadata,
+) -> Option<ExportOptions> {
+    let dialog = AlertDialog::builder()
+        .heading("Export Audiobook")
+        .body("Choose the audio format, tags and files saved along with the audiobook")
+        .build();
+
+    dialog.add_response("cancel", "Cancel");
+    dialog.add_response("export", "Export");
+    dialog.set_response_appearance("export", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("export"));
+    dialog.set_close_response("cancel");
+
+    let labels = ExportFormat::ALL.map(|format| format.label());
+    let format_row = adw::ComboRow::builder()
+        .title("Format")
+        .model(&gtk::StringList::new(&labels))
+        .build();
+    let title_row = adw::EntryRow::builder().title("Title").build();
+    title_row.set_text(&metadata.title);
+    let author_row = adw::EntryRow::builder().title("Author").build();
+    author_row.set_text(&metadata.author);
+    let subtitle_labels = ["None"]
+        .into_iter()
+        .chain(SubtitleFormat::ALL.map(|format| format.label()))
+        .collect::<Vec<_>>();
+    let subtitles_row = adw::ComboRow::builder()
+        .title("Subtitles")
+        .model(&gtk::StringList::new(&subtitle_labels))
+        .build();
+    let cue_row = adw::SwitchRow::builder()
+        .title("CUE Sheet")
+        .subtitle("Also save the chapters to a .cue file next to the audiobook")
+        .build();
+
+    let list = gtk::ListBox::new();
+    list.add_css_class("boxed-list");
+    list.set_selection_mode(gtk::SelectionMode::None);
+    list.append(&format_row);
+    list.append(&title_row);
+    list.append(&author_row);
+    list.append(&subtitles_row);
+    list.append(&cue_row);
+    dialog.set_extra_child(Some(&list));
+
+    if dialog.choose_future(Some(parent)).await != "export" {
+        return None;
+    }
+
+    let format = ExportFormat::ALL
+        .get(format_row.selected() as usize)
+        .copied()
+        .unwrap_or_default();
+    let metadata = AudioMetadata {
+        title: title_row.text().trim().to_string(),
+        author: author_row.text().trim().to_string(),
+        ..metadata
+    };
+    Some(ExportOptions {
+        format,
+        metadata,
+        write_cue_sheet: cue_row.is_active(),
+        // The first entry is "None"
+        subtitles: (subtitles_row.selected() as usize)
+            .checked_sub(1)
+            .and_then(|i| SubtitleFormat::ALL.get(i).copied()),
+    })
+}
@@ -3,6 +3,7 @@ mod ai_chat;
 mod ai_chat_row;
 mod audio_controls;
 mod dialogs;
+mod export_dialog;
 mod helpers;
 mod kokoros_download_dialog;
 mod pdf_reader;
@@ -7,12 +7,18 @@ use gtk::{
     gdk_pixbuf::{Colorspace, Pixbuf},
     glib::clone,
 };
-use pdfium_render::prelude::{PdfPage, PdfPoints, PdfRenderConfig};
+use pdfium_render::prelude::{PdfDocumentMetadataTagType, PdfPage, PdfPoints, PdfRenderConfig};
 use std::{cell::RefCell, collections::BTreeMap, fmt::Debug, rc::Rc};
+use tokio::sync::broadcast::error::RecvError;
 
 use crate::{
     core::{runtime::runtime, tts::TTSEvent},
-    utils::{debouncer::Debouncer, pdf_highlighter::PdfReadingBlock},
+    output::AudioMetadata,
+    utils::{
+        debouncer::Debouncer,
+        pdf_highlighter::{PdfHighlighter, PdfReadingBlock},
+        pdfium::PageRange,
+    },
     SETTINGS,
 };
 
@@ -544,6 +550,23 @@ impl PdfReader {
                     }
                 }
                 cr.fill().expect("Failed to fill base highlights");
+
+                // Currently spoken word, drawn once more over its block
+                let word_rectangles = imp
+                    .pdf_highlighter
+                    .borrow()
+                    .get_highlighted_word_rectangles();
+                cr.set_source_rgba(red.into(), green.into(), blue.into(), 0.6);
+                for rect in word_rectangles.iter() {
+                    cr.rectangle(
+                        rect.left().value as f64 * scale_factor,
+                        page_size.top().value as f64 * scale_factor
+                            - rect.top().value as f64 * scale_factor,
+                        rect.width().value as f64 * scale_factor,
+                        rect.height().value as f64 * scale_factor,
+                    );
+                }
+                cr.fill().expect("Failed to fill word highlights");
             }
         ));
 
@@ -618,6 +641,37 @@ impl PdfReader {
             }
         ));
 
+        imp.audio_controls.set_export_handler(clone!(
+            #[weak]
+            imp,
+            #[upgrade_or_default]
+            move || {
+                let pdf_wrapper = imp.pdf_wrapper.borrow();
+                let Some(document) = pdf_wrapper.get_document() else {
+                    return Default::default();
+                };
+
+                let metadata = AudioMetadata {
+                    title: pdf_wrapper
+                        .get_metadata_tag(PdfDocumentMetadataTagType::Title)
+                        .unwrap_or_default(),
+                    author: pdf_wrapper
+                        .get_metadata_tag(PdfDocumentMetadataTagType::Author)
+                        .unwrap_or_default(),
+                    ..Default::default()
+                };
+                let outline = pdf_wrapper.get_outline();
+                (
+                    PdfHighlighter::generate_document_chapters(
+                        document,
+                        &outline,
+                        PageRange::default(),
+                    ),
+                    metadata,
+                )
+            }
+        ));
+
         imp.audio_controls.set_read_handler(clone!(
             #[weak]
             imp,
@@ -660,13 +714,35 @@ impl PdfReader {
             async move {
                 let mut subscriber = imp.audio_controls.imp().tts.sender.subscribe();
 
-                while let Ok(event) = subscriber.recv().await {
+                loop {
+                    // Word events come in bursts, the ones missed are skipped
+                    let event = match subscriber.recv().await {
+                        Ok(event) => event,
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    };
                     match event {
                         TTSEvent::Progress { block_id } => {
+                            imp.audio_controls.set_buffering(false);
+                            imp.pdf_highlighter.borrow_mut().highlight(block_id);
+                            imp.highlight_area.borrow().queue_draw();
+                        }
+                        TTSEvent::Buffering { block_id } => {
+                            imp.audio_controls.set_buffering(true);
                             imp.pdf_highlighter.borrow_mut().highlight(block_id);
                             imp.highlight_area.borrow().queue_draw();
                         }
+                        TTSEvent::WordProgress {
+                            block_id,
+                            char_range,
+                        } => {
+                            imp.pdf_highlighter
+                                .borrow_mut()
+                                .highlight_word(block_id, char_range);
+                            imp.highlight_area.borrow().queue_draw();
+                        }
                         TTSEvent::Error(e) => {
+                            imp.audio_controls.set_buffering(false);
                             dialogs::show_error_dialog(&e, &this);
                             imp.pdf_highlighter.borrow_mut().clear_highlight();
                             this.refresh_view();
@@ -677,6 +753,7 @@ impl PdfReader {
                             imp.highlight_area.borrow().queue_draw();
                         }
                         TTSEvent::Stop => {
+                            imp.audio_controls.set_buffering(false);
                             imp.pdf_highlighter.borrow_mut().clear_highlight();
                             imp.highlight_area.borrow().queue_draw();
                             break;
@@ -705,6 +782,7 @@ impl PdfReader {
                     }
 
                     imp.pdf_highlighter.borrow_mut().clear_highlight();
+                    imp.audio_controls.set_buffering(false);
                     this.refresh_view();
                     this.imp()
                         .audio_controls
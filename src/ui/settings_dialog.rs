@@ -29,6 +29,14 @@ mod imp {
         #[template_child]
         pub highlight_color_button: TemplateChild<gtk::ColorDialogButton>,
 
+        // Playback settings
+        #[template_child]
+        pub pitch_scale: TemplateChild<gtk::Scale>,
+        #[template_child]
+        pub normalize_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub detect_language_switch: TemplateChild<gtk::Switch>,
+
         // LLM Settings
         #[template_child]
         pub provider_list: TemplateChild<adw::ComboRow>,
@@ -93,6 +101,12 @@ impl Default for SettingsDialog {
         let rgba = settings.get_highlight_rgba();
         imp.highlight_color_button.set_rgba(&rgba);
 
+        imp.pitch_scale.set_value(settings.get_pitch());
+        imp.normalize_switch
+            .set_active(settings.get_normalize_audio());
+        imp.detect_language_switch
+            .set_active(settings.get_detect_language());
+
         *imp.whisper_downloaded_models.borrow_mut() = get_downloaded_models();
 
         obj.setup_provider_list();
@@ -119,6 +133,18 @@ impl SettingsDialog {
             settings.set_highlight_color(&rgba);
         });
 
+        imp.pitch_scale.connect_value_changed(|scale| {
+            settings.set_pitch(scale.value());
+        });
+
+        imp.normalize_switch.connect_active_notify(|switch| {
+            settings.set_normalize_audio(switch.is_active());
+        });
+
+        imp.detect_language_switch.connect_active_notify(|switch| {
+            settings.set_detect_language(switch.is_active());
+        });
+
         imp.provider_list.connect_selected_notify(clone!(
             #[weak(rename_to=this)]
             self,
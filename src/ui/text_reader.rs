@@ -4,8 +4,14 @@ use gtk::{
     prelude::*,
 };
 use std::cell::RefCell;
+use tokio::sync::broadcast::error::RecvError;
 
-use crate::{core::tts::TTSEvent, utils::text_highlighter::TextHighlighter, SETTINGS};
+use crate::{
+    core::{audiobook::Chapter, tts::TTSEvent},
+    output::AudioMetadata,
+    utils::text_highlighter::TextHighlighter,
+    SETTINGS,
+};
 
 use super::dialogs;
 
@@ -118,6 +124,16 @@ impl TextReader {
             }
         ));
 
+        imp.audio_controls.set_export_handler(clone!(
+            #[weak]
+            imp,
+            #[upgrade_or_default]
+            move || {
+                let text = imp.text_highlighter.borrow().get_text();
+                (Chapter::from_text(&text, "Text"), AudioMetadata::default())
+            }
+        ));
+
         imp.audio_controls.set_read_handler(clone!(
             #[weak(rename_to=this)]
             self,
@@ -138,14 +154,33 @@ impl TextReader {
                     #[weak]
                     imp,
                     async move {
-                        while let Ok(event) =
-                            imp.audio_controls.imp().tts.sender.subscribe().recv().await
-                        {
+                        let mut subscriber = imp.audio_controls.imp().tts.sender.subscribe();
+                        loop {
+                            // Word events come in bursts, the ones missed are skipped
+                            let event = match subscriber.recv().await {
+                                Ok(event) => event,
+                                Err(RecvError::Lagged(_)) => continue,
+                                Err(RecvError::Closed) => break,
+                            };
                             match event {
                                 TTSEvent::Progress { block_id } => {
+                                    imp.audio_controls.set_buffering(false);
                                     imp.text_highlighter.borrow().highlight(block_id);
                                 }
+                                TTSEvent::Buffering { block_id } => {
+                                    imp.audio_controls.set_buffering(true);
+                                    imp.text_highlighter.borrow().highlight(block_id);
+                                }
+                                TTSEvent::WordProgress {
+                                    block_id,
+                                    char_range,
+                                } => {
+                                    imp.text_highlighter
+                                        .borrow()
+                                        .highlight_word(block_id, char_range);
+                                }
                                 TTSEvent::Error(e) => {
+                                    imp.audio_controls.set_buffering(false);
                                     dialogs::show_error_dialog(&e, &this);
                                     imp.text_highlighter.borrow().clear();
                                     imp.text_input.set_editable(true);
@@ -155,6 +190,7 @@ impl TextReader {
                                     imp.text_highlighter.borrow().clear();
                                 }
                                 TTSEvent::Stop => {
+                                    imp.audio_controls.set_buffering(false);
                                     break;
                                 }
                             }
@@ -185,6 +221,7 @@ impl TextReader {
                             }
                             imp.text_highlighter.borrow().clear();
                             imp.text_input.set_editable(true);
+                            imp.audio_controls.set_buffering(false);
                             this.imp()
                                 .audio_controls
                                 .imp()
@@ -136,8 +136,7 @@ impl VoiceRow {
                         println!("Playing sample for voice: {}", this.key());
 
                         match spawn_tokio(async move {
-                            VoiceManager::generate_kokoros_speech(sample_text, &voice_style, 1.0)
-                                .await
+                            VoiceManager::generate_speech(sample_text, &voice_style, 1.0).await
                         })
                         .await
                         {
@@ -0,0 +1,238 @@
+use rodio::{buffer::SamplesBuffer, Source};
+use std::f32::consts::PI;
+
+pub const MIN_VOLUME: f32 = 0.0;
+pub const MAX_VOLUME: f32 = 2.0;
+// In semitones
+pub const MAX_PITCH_SHIFT: f32 = 12.0;
+
+// Loudness normalized blocks are brought to, quiet enough to leave headroom for peaks
+const TARGET_RMS: f32 = 0.1;
+const MAX_PEAK: f32 = 0.99;
+// Keeps near silent blocks from being boosted into noise
+const MAX_NORMALIZATION_GAIN: f32 = 4.0;
+
+// Pitch shifting windows, in frames
+const FRAME_LEN: usize = 1024;
+const SYNTHESIS_HOP: usize = FRAME_LEN / 4;
+
+// Processing applied to every buffer before it's queued, so changes take effect from the
+// next block on. Volume isn't part of it as the player applies it live
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AudioEffects {
+    pub pitch: f32,
+    pub normalize: bool,
+}
+
+impl AudioEffects {
+    pub fn is_neutral(&self) -> bool {
+        self.pitch == 0.0 && !self.normalize
+    }
+
+    pub fn apply(&self, audio: SamplesBuffer<f32>) -> SamplesBuffer<f32> {
+        if self.is_neutral() {
+            return audio;
+        }
+
+        let channels = audio.channels();
+        let sample_rate = audio.sample_rate();
+        let mut samples = audio.collect::<Vec<_>>();
+
+        if self.pitch != 0.0 {
+            samples = pitch_shift(&samples, channels as usize, self.pitch);
+        }
+        if self.normalize {
+            normalize(&mut samples);
+        }
+
+        SamplesBuffer::new(channels, sample_rate, samples)
+    }
+}
+
+pub fn clamp_volume(volume: f32) -> f32 {
+    volume.clamp(MIN_VOLUME, MAX_VOLUME)
+}
+
+pub fn clamp_pitch(pitch: f32) -> f32 {
+    pitch.clamp(-MAX_PITCH_SHIFT, MAX_PITCH_SHIFT)
+}
+
+fn normalize(samples: &mut [f32]) {
+    if samples.is_empty() {
+        return;
+    }
+
+    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    if rms == 0.0 || peak == 0.0 {
+        return;
+    }
+
+    let gain = (TARGET_RMS / rms)
+        .min(MAX_PEAK / peak)
+        .min(MAX_NORMALIZATION_GAIN);
+    samples.iter_mut().for_each(|s| *s *= gain);
+}
+
+// Stretches the audio in time by the pitch ratio and resamples it back to its original
+// length, so the pitch changes while durations, and with them word timings, stay the same
+fn pitch_shift(samples: &[f32], channels: usize, semitones: f32) -> Vec<f32> {
+    let channels = channels.max(1);
+    let ratio = 2f32.powf(clamp_pitch(semitones) / 12.0);
+    let frames = samples.len() / channels;
+
+    let shifted = (0..channels)
+        .map(|channel| {
+            let channel_samples = samples
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .copied()
+                .collect::<Vec<_>>();
+            resample(&stretch(&channel_samples, ratio), frames)
+        })
+        .collect::<Vec<_>>();
+
+    let mut output = Vec::with_capacity(samples.len());
+    for frame in 0..frames {
+        output.extend(shifted.iter().map(|channel| channel[frame]));
+    }
+    output
+}
+
+// Overlap-add with Hann windows, read with a hop shorter or longer than the written one
+fn stretch(samples: &[f32], ratio: f32) -> Vec<f32> {
+    if samples.len() < FRAME_LEN {
+        return samples.to_vec();
+    }
+
+    let out_len = (samples.len() as f32 * ratio) as usize;
+    let analysis_hop = SYNTHESIS_HOP as f32 / ratio;
+    let window = (0..FRAME_LEN)
+        .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FRAME_LEN as f32).cos())
+        .collect::<Vec<_>>();
+
+    let mut output = vec![0.0; out_len + FRAME_LEN];
+    let mut weights = vec![0.0; out_len + FRAME_LEN];
+
+    let mut frame = 0;
+    while frame * SYNTHESIS_HOP < out_len {
+        let in_start = ((frame as f32 * analysis_hop) as usize).min(samples.len() - FRAME_LEN);
+        let out_start = frame * SYNTHESIS_HOP;
+
+        for (i, weight) in window.iter().enumerate() {
+            output[out_start + i] += samples[in_start + i] * weight;
+            weights[out_start + i] += weight;
+        }
+        frame += 1;
+    }
+
+    output.truncate(out_len);
+    output
+        .iter_mut()
+        .zip(weights)
+        .filter(|(_, weight)| *weight > 1e-3)
+        .for_each(|(sample, weight)| *sample /= weight);
+    output
+}
+
+// Linear interpolation to exactly `len` samples
+fn resample(samples: &[f32], len: usize) -> Vec<f32> {
+    if samples.len() < 2 || len < 2 {
+        return samples
+            .iter()
+            .copied()
+            .chain(std::iter::repeat(0.0))
+            .take(len)
+            .collect();
+    }
+
+    let step = (samples.len() - 1) as f32 / (len - 1) as f32;
+    (0..len)
+        .map(|i| {
+            let position = i as f32 * step;
+            let index = (position as usize).min(samples.len() - 2);
+            let fraction = position - index as f32;
+            samples[index] * (1.0 - fraction) + samples[index + 1] * fraction
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 8000;
+
+    fn sine(frequency: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * PI * frequency * i as f32 / SAMPLE_RATE as f32).sin() * 0.5)
+            .collect()
+    }
+
+    // Counts upward zero crossings in the middle of the signal, away from the edges
+    fn frequency_of(samples: &[f32]) -> f32 {
+        let middle = &samples[samples.len() / 4..samples.len() * 3 / 4];
+        let crossings = middle
+            .windows(2)
+            .filter(|pair| pair[0] <= 0.0 && pair[1] > 0.0)
+            .count();
+        crossings as f32 * SAMPLE_RATE as f32 / middle.len() as f32
+    }
+
+    #[test]
+    fn test_neutral_effects_keep_audio() {
+        let samples = sine(440.0, 800);
+        let audio = SamplesBuffer::new(1, SAMPLE_RATE, samples.clone());
+
+        let processed = AudioEffects::default().apply(audio).collect::<Vec<_>>();
+
+        assert_eq!(processed, samples);
+    }
+
+    #[test]
+    fn test_pitch_shift_keeps_duration() {
+        let samples = sine(440.0, 16000);
+
+        for semitones in [-12.0, -3.0, 5.0, 12.0] {
+            let shifted = pitch_shift(&samples, 1, semitones);
+            assert_eq!(shifted.len(), samples.len());
+        }
+
+        let stereo = samples.iter().flat_map(|s| [*s, *s]).collect::<Vec<_>>();
+        assert_eq!(pitch_shift(&stereo, 2, 7.0).len(), stereo.len());
+    }
+
+    #[test]
+    fn test_octave_up_doubles_frequency() {
+        let shifted = pitch_shift(&sine(300.0, 16000), 1, 12.0);
+
+        let frequency = frequency_of(&shifted);
+        assert!((frequency - 600.0).abs() < 30.0, "{}", frequency);
+    }
+
+    #[test]
+    fn test_normalize_reaches_target_loudness() {
+        let mut quiet = sine(440.0, 8000)
+            .into_iter()
+            .map(|s| s * 0.1)
+            .collect::<Vec<_>>();
+        normalize(&mut quiet);
+
+        let rms = (quiet.iter().map(|s| s * s).sum::<f32>() / quiet.len() as f32).sqrt();
+        assert!((rms - TARGET_RMS).abs() < 0.01, "{}", rms);
+
+        let mut silence = vec![0.0; 100];
+        normalize(&mut silence);
+        assert!(silence.iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn test_normalize_never_clips() {
+        let mut loud = vec![0.0; 1000];
+        loud[10] = 0.8;
+        normalize(&mut loud);
+
+        assert!(loud.iter().all(|s| s.abs() <= MAX_PEAK + 1e-6));
+    }
+}
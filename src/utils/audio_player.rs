@@ -1,118 +1,918 @@
+use hound::{SampleFormat, WavSpec, WavWriter};
 use rodio::buffer::SamplesBuffer;
-use rodio::{OutputStream, Sink};
+use rodio::queue::SourcesQueueOutput;
+use rodio::{OutputStream, Sink, Source};
+use std::collections::VecDeque;
 use std::error::Error;
-use std::io::Cursor;
-use std::sync::Arc;
-use std::sync::Mutex;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Cursor};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
 
-pub enum State {
-    Idle,
-    Paused,
-    Playing,
+use super::audio_effects::{clamp_pitch, clamp_volume, AudioEffects};
+
+const OUTPUT_TICK: Duration = Duration::from_millis(10);
+
+static DEFAULT_OUTPUT: RwLock<AudioOutput> = RwLock::new(AudioOutput::Device);
+
+type SharedWavWriter = Arc<Mutex<Option<WavWriter<BufWriter<File>>>>>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Pacing {
+    // Samples are consumed at the speed they would be played, so timing behaves like a device
+    #[default]
+    RealTime,
+    // Samples are consumed as fast as they are produced
+    Instant,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AudioOutput {
+    Device,
+    // Discards samples, used for tests and headless runs with no sound card
+    Null(Pacing),
+    // Appends everything played by the player to a single WAV file
+    Wav(PathBuf),
+}
+
+impl AudioOutput {
+    // Output used by players created with `Default`, set once on startup from settings or CLI
+    pub fn set_default(output: AudioOutput) {
+        *DEFAULT_OUTPUT.write().unwrap() = output;
+    }
+}
+
+impl Default for AudioOutput {
+    fn default() -> Self {
+        DEFAULT_OUTPUT.read().unwrap().clone()
+    }
+}
+
+impl FromStr for AudioOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "device" => Ok(AudioOutput::Device),
+            "null" => Ok(AudioOutput::Null(Pacing::RealTime)),
+            "null-instant" => Ok(AudioOutput::Null(Pacing::Instant)),
+            _ => match s.strip_prefix("wav:") {
+                Some(path) if !path.is_empty() => Ok(AudioOutput::Wav(PathBuf::from(path))),
+                _ => Err(format!(
+                    "Invalid audio output '{}', expected one of: device, null, null-instant, wav:<path>",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for AudioOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AudioOutput::Device => write!(f, "device"),
+            AudioOutput::Null(Pacing::RealTime) => write!(f, "null"),
+            AudioOutput::Null(Pacing::Instant) => write!(f, "null-instant"),
+            AudioOutput::Wav(path) => write!(f, "wav:{}", path.display()),
+        }
+    }
+}
+
+// Block being played and the last of its marks playback went past
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudiblePosition {
+    pub block_id: u32,
+    pub mark: Option<usize>,
+}
+
+struct QueuedBlock {
+    sequence: u64,
+    block_id: u32,
+    started: bool,
+}
+
+// Blocks appended to the player that have not finished playing yet, in playback order.
+// Every append gets its own sequence number so sounds cleared from the sink can't
+// be confused with the ones queued after them
+#[derive(Default)]
+struct BlockQueue {
+    blocks: Mutex<VecDeque<QueuedBlock>>,
+    next_sequence: AtomicU64,
+    audible: watch::Sender<Option<AudiblePosition>>,
+}
+
+impl BlockQueue {
+    fn push(&self, block_id: u32) -> u64 {
+        let sequence = self.next_sequence.fetch_add(1, Ordering::SeqCst);
+        self.blocks.lock().unwrap().push_back(QueuedBlock {
+            sequence,
+            block_id,
+            started: false,
+        });
+        sequence
+    }
+
+    // Returns false for blocks that got discarded before they could start
+    fn start(&self, sequence: u64) -> bool {
+        let mut blocks = self.blocks.lock().unwrap();
+        match blocks.iter_mut().find(|b| b.sequence == sequence) {
+            Some(block) => {
+                block.started = true;
+                drop(blocks);
+                self.reached(sequence, None);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn reached(&self, sequence: u64, mark: Option<usize>) {
+        let blocks = self.blocks.lock().unwrap();
+        if let Some(front) = blocks.front().filter(|b| b.sequence == sequence) {
+            let position = Some(AudiblePosition {
+                block_id: front.block_id,
+                mark,
+            });
+            self.audible.send_if_modified(|audible| {
+                let changed = *audible != position;
+                *audible = position;
+                changed
+            });
+        }
+    }
+
+    fn finished(&self, sequence: u64) {
+        let mut blocks = self.blocks.lock().unwrap();
+        if blocks.front().is_some_and(|b| b.sequence == sequence) {
+            blocks.pop_front();
+        }
+        // Next block starts right away when it's queued, so only a dry queue is reported
+        if blocks.is_empty() {
+            self.set_idle();
+        }
+    }
+
+    fn discard_upcoming(&self) -> Option<u32> {
+        let mut blocks = self.blocks.lock().unwrap();
+        let started = blocks.iter().take_while(|b| b.started).count();
+        let first_discarded = blocks.get(started).map(|b| b.block_id);
+        blocks.truncate(started);
+        if blocks.is_empty() {
+            self.set_idle();
+        }
+        first_discarded
+    }
+
+    fn clear(&self) {
+        self.blocks.lock().unwrap().clear();
+        self.set_idle();
+    }
+
+    fn set_idle(&self) {
+        self.audible
+            .send_if_modified(|audible| audible.take().is_some());
+    }
+}
+
+struct TrackedSource {
+    inner: SamplesBuffer<f32>,
+    sequence: u64,
+    queue: Arc<BlockQueue>,
+    // Sample offsets reported once playback reaches them, in ascending order
+    marks: Vec<usize>,
+    next_mark: usize,
+    position: usize,
+    started: bool,
+    finished: bool,
+}
+
+impl Iterator for TrackedSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if !self.started {
+            self.started = true;
+            if !self.queue.start(self.sequence) {
+                self.finished = true;
+            }
+        }
+        if self.finished {
+            return None;
+        }
+
+        let passed = self.marks[self.next_mark..]
+            .iter()
+            .take_while(|mark| **mark <= self.position)
+            .count();
+        if passed > 0 {
+            self.next_mark += passed;
+            self.queue.reached(self.sequence, Some(self.next_mark - 1));
+        }
+        self.position += 1;
+
+        let sample = self.inner.next();
+        if sample.is_none() && !self.finished {
+            self.finished = true;
+            self.queue.finished(self.sequence);
+        }
+        sample
+    }
+}
+
+impl Source for TrackedSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+// Writes samples to the WAV output as the sink pulls them, so the silence the sink
+// plays while idle never ends up in the file. Sink volume only applies to what it plays,
+// so it's applied to the recorded samples here
+struct RecordedSource<S> {
+    inner: S,
+    wav_writer: SharedWavWriter,
+    volume: Arc<AtomicU32>,
+}
+
+impl<S> Iterator for RecordedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        if let Some(writer) = self.wav_writer.lock().unwrap().as_mut() {
+            let volume = f32::from_bits(self.volume.load(Ordering::Relaxed));
+            if let Err(e) = writer.write_sample(sample * volume) {
+                eprintln!("Failed to write WAV output: {}", e);
+            }
+        }
+        Some(sample)
+    }
 }
 
+impl<S> Source for RecordedSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+struct OpenedOutput {
+    sink: Arc<Sink>,
+    // Device stream lives on its own thread as it can't be sent between threads,
+    // dropping this sender closes it
+    _stream_guard: Option<mpsc::Sender<()>>,
+}
+
+// Output is opened on first playback and kept for the whole life of the player,
+// so queued sounds play back to back without reopening the device in between
 pub struct AudioPlayer {
-    sink: Arc<Mutex<Option<Arc<Sink>>>>,
-    state: Arc<Mutex<State>>,
+    output: AudioOutput,
+    opened: Mutex<Option<OpenedOutput>>,
+    queue: Arc<BlockQueue>,
+    wav_writer: SharedWavWriter,
+    // f32 bits, shared with the recorded sources
+    volume: Arc<AtomicU32>,
+    effects: Mutex<AudioEffects>,
 }
 
 impl Default for AudioPlayer {
     fn default() -> Self {
-        AudioPlayer {
-            sink: Arc::new(Mutex::new(None)),
-            state: Arc::new(Mutex::new(State::Idle)),
-        }
+        Self::new(AudioOutput::default())
+    }
+}
+
+impl Drop for AudioPlayer {
+    fn drop(&mut self) {
+        Self::flush_wav_writer(&self.wav_writer);
     }
 }
 
 impl AudioPlayer {
-    pub fn play_mp3(&self, audio_data: Vec<u8>) -> Result<(), String> {
-        let cursor = Cursor::new(audio_data);
+    pub fn new(output: AudioOutput) -> Self {
+        AudioPlayer {
+            output,
+            opened: Mutex::new(None),
+            queue: Arc::new(BlockQueue::default()),
+            wav_writer: Arc::new(Mutex::new(None)),
+            volume: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            effects: Mutex::new(AudioEffects::default()),
+        }
+    }
 
-        let (_stream, stream_handle) = OutputStream::try_default()
-            .map_err(|e| format!("Failed to setup audio output: {}", e))?;
+    fn sink(&self, channels: u16, sample_rate: u32) -> Result<Arc<Sink>, String> {
+        if let AudioOutput::Wav(path) = &self.output {
+            self.open_wav_writer(path, channels, sample_rate)?;
+        }
 
-        let sink = Sink::try_new(&stream_handle)
-            .map_err(|e| format!("Failed to create audio sink: {}", e))?;
+        let mut opened = self.opened.lock().unwrap();
+        if let Some(opened) = opened.as_ref() {
+            return Ok(opened.sink.clone());
+        }
 
-        let sink = Arc::new(sink);
+        let output = self.open_output()?;
+        let sink = output.sink.clone();
+        sink.set_volume(self.volume());
+        *opened = Some(output);
 
-        *self.sink.lock().unwrap() = Some(Arc::clone(&sink));
+        Ok(sink)
+    }
+
+    fn opened_sink(&self) -> Option<Arc<Sink>> {
+        self.opened.lock().unwrap().as_ref().map(|o| o.sink.clone())
+    }
+
+    fn open_output(&self) -> Result<OpenedOutput, String> {
+        match &self.output {
+            AudioOutput::Device => {
+                let (sink_tx, sink_rx) = mpsc::channel();
+                let (stream_guard, stream_closed) = mpsc::channel::<()>();
+
+                std::thread::spawn(move || {
+                    let opened = OutputStream::try_default()
+                        .map_err(|e| format!("Failed to setup audio output: {}", e))
+                        .and_then(|(stream, stream_handle)| {
+                            Sink::try_new(&stream_handle)
+                                .map(|sink| (stream, sink))
+                                .map_err(|e| format!("Failed to create audio sink: {}", e))
+                        });
+
+                    match opened {
+                        Ok((_stream, sink)) => {
+                            let _ = sink_tx.send(Ok(sink));
+                            // Returns once the player drops the guard
+                            let _ = stream_closed.recv();
+                        }
+                        Err(e) => {
+                            let _ = sink_tx.send(Err(e));
+                        }
+                    }
+                });
+
+                let sink = sink_rx
+                    .recv()
+                    .map_err(|e| format!("Audio output thread exited: {}", e))??;
+
+                Ok(OpenedOutput {
+                    sink: Arc::new(sink),
+                    _stream_guard: Some(stream_guard),
+                })
+            }
+            AudioOutput::Null(pacing) => {
+                let (sink, queue) = Sink::new_idle();
+                let sink = Arc::new(sink);
+                Self::spawn_output_thread(queue, Arc::downgrade(&sink), *pacing, None);
+                Ok(OpenedOutput {
+                    sink,
+                    _stream_guard: None,
+                })
+            }
+            AudioOutput::Wav(_) => {
+                let (sink, queue) = Sink::new_idle();
+                let sink = Arc::new(sink);
+                Self::spawn_output_thread(
+                    queue,
+                    Arc::downgrade(&sink),
+                    Pacing::Instant,
+                    Some(self.wav_writer.clone()),
+                );
+                Ok(OpenedOutput {
+                    sink,
+                    _stream_guard: None,
+                })
+            }
+        }
+    }
+
+    // The file is created on first playback and then reused, so one player produces one file
+    fn open_wav_writer(
+        &self,
+        path: &PathBuf,
+        channels: u16,
+        sample_rate: u32,
+    ) -> Result<(), String> {
+        let mut wav_writer = self.wav_writer.lock().unwrap();
+
+        if let Some(writer) = wav_writer.as_ref() {
+            let spec = writer.spec();
+            if spec.channels != channels || spec.sample_rate != sample_rate {
+                return Err(format!(
+                    "Cannot append {} Hz audio to {} Hz WAV output",
+                    sample_rate, spec.sample_rate
+                ));
+            }
+            return Ok(());
+        }
+
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let writer = WavWriter::create(path, spec)
+            .map_err(|e| format!("Failed to create WAV output {}: {}", path.display(), e))?;
+        *wav_writer = Some(writer);
+
+        Ok(())
+    }
+
+    fn flush_wav_writer(wav_writer: &SharedWavWriter) {
+        if let Some(writer) = wav_writer.lock().unwrap().as_mut() {
+            if let Err(e) = writer.flush() {
+                eprintln!("Failed to flush WAV output: {}", e);
+            }
+        }
+    }
+
+    fn spawn_output_thread(
+        mut queue: SourcesQueueOutput<f32>,
+        sink: Weak<Sink>,
+        pacing: Pacing,
+        wav_writer: Option<SharedWavWriter>,
+    ) {
+        std::thread::spawn(move || {
+            let clock = Instant::now();
+            let mut played = Duration::ZERO;
+            let mut unflushed = false;
+
+            loop {
+                let (empty, paused) = sink
+                    .upgrade()
+                    .map(|sink| (sink.empty(), sink.is_paused()))
+                    .unwrap_or_default();
+
+                // Queue pads with silence while the sink is idle, no need to spin on it
+                if pacing == Pacing::Instant && empty {
+                    if let Some(wav_writer) = wav_writer.as_ref().filter(|_| unflushed) {
+                        Self::flush_wav_writer(wav_writer);
+                        unflushed = false;
+                    }
+                    std::thread::sleep(OUTPUT_TICK);
+                    played = clock.elapsed();
+                    continue;
+                }
+
+                // Queue is closed once the sink gets dropped
+                if queue.next().is_none() {
+                    if let Some(wav_writer) = &wav_writer {
+                        Self::flush_wav_writer(wav_writer);
+                    }
+                    return;
+                }
+                unflushed = true;
+
+                // Paused sounds still have to be pulled, otherwise they could never be cleared
+                if pacing == Pacing::RealTime || paused {
+                    played += Duration::from_secs_f64(
+                        1.0 / (queue.sample_rate() as f64 * queue.channels() as f64),
+                    );
+                    let elapsed = clock.elapsed();
+                    if played > elapsed + OUTPUT_TICK {
+                        std::thread::sleep(played - elapsed);
+                    }
+                } else {
+                    played = clock.elapsed();
+                }
+            }
+        });
+    }
+
+    fn append<S>(&self, sink: &Sink, source: S)
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        match &self.output {
+            AudioOutput::Wav(_) => sink.append(RecordedSource {
+                inner: source,
+                wav_writer: self.wav_writer.clone(),
+                volume: self.volume.clone(),
+            }),
+            _ => sink.append(source),
+        }
+    }
+
+    // Notifies about the block that is currently audible and the marks it passes,
+    // `None` once everything queued has played
+    pub fn subscribe_audible(&self) -> watch::Receiver<Option<AudiblePosition>> {
+        self.queue.audible.subscribe()
+    }
+
+    // Blocks enqueued that haven't finished playing yet, the audible one included. Every
+    // block finishing is followed by a change of the audible position
+    pub fn queued_blocks(&self) -> usize {
+        self.queue.blocks.lock().unwrap().len()
+    }
+
+    // Appends audio after everything already queued and returns right away, `marks` are
+    // sample offsets in `source_audio` whose index gets published as they are played
+    pub fn enqueue(
+        &self,
+        block_id: u32,
+        source_audio: SamplesBuffer<f32>,
+        marks: Vec<usize>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let source_audio = self.effects().apply(source_audio);
+        let sink = self.sink(source_audio.channels(), source_audio.sample_rate())?;
+        let sequence = self.queue.push(block_id);
+
+        self.append(
+            &sink,
+            TrackedSource {
+                inner: source_audio,
+                sequence,
+                queue: self.queue.clone(),
+                marks,
+                next_mark: 0,
+                position: 0,
+                started: false,
+                finished: false,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub fn play_mp3(&self, audio_data: Vec<u8>) -> Result<(), String> {
+        let cursor = Cursor::new(audio_data);
 
         let source =
             rodio::Decoder::new(cursor).map_err(|e| format!("Failed to decode audio: {}", e))?;
 
-        sink.append(source);
+        self.stop();
+        let sink = self.sink(source.channels(), source.sample_rate())?;
+
+        self.append(&sink, source.convert_samples());
         sink.sleep_until_end();
 
-        self.clean();
+        Self::flush_wav_writer(&self.wav_writer);
 
         Ok(())
     }
 
+    // Replaces anything queued and blocks until the audio has been played
     pub fn play_audio(
         &self,
         source_audio: SamplesBuffer<f32>,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
         self.stop();
 
-        let (_stream, stream_handle) = OutputStream::try_default()
-            .map_err(|e| format!("Failed to setup audio output: {}", e))?;
+        let source_audio = self.effects().apply(source_audio);
+        let sink = self.sink(source_audio.channels(), source_audio.sample_rate())?;
 
-        let sink = Sink::try_new(&stream_handle)
-            .map_err(|e| format!("Failed to create audio sink: {}", e))?;
+        self.append(&sink, source_audio);
+        sink.sleep_until_end();
 
-        let sink = Arc::new(sink);
+        Self::flush_wav_writer(&self.wav_writer);
 
-        *self.sink.lock().unwrap() = Some(Arc::clone(&sink));
+        Ok(())
+    }
 
-        *self.state.lock().unwrap() = State::Playing;
+    // Blocks until everything queued has been played
+    pub fn sleep_until_end(&self) {
+        if let Some(sink) = self.opened_sink() {
+            sink.sleep_until_end();
+        }
+        Self::flush_wav_writer(&self.wav_writer);
+    }
 
-        sink.append(source_audio);
-        sink.sleep_until_end();
+    // Applies right away, including to audio that is already queued
+    pub fn set_volume(&self, volume: f32) {
+        let volume = clamp_volume(volume);
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+        if let Some(sink) = self.opened_sink() {
+            sink.set_volume(volume);
+        }
+    }
 
-        self.clean();
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
 
-        Ok(())
+    // Pitch shift in semitones, applies from the next queued audio
+    pub fn set_pitch(&self, pitch: f32) {
+        self.effects.lock().unwrap().pitch = clamp_pitch(pitch);
+    }
+
+    // Evens out loudness between blocks, applies from the next queued audio
+    pub fn set_normalize(&self, normalize: bool) {
+        self.effects.lock().unwrap().normalize = normalize;
     }
 
-    fn clean(&self) {
-        *self.sink.lock().unwrap() = None;
-        *self.state.lock().unwrap() = State::Idle;
+    pub fn effects(&self) -> AudioEffects {
+        *self.effects.lock().unwrap()
     }
 
     pub fn pause(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
-        if let Some(sink) = &*self.sink.lock().unwrap() {
+        if let Some(sink) = self.opened_sink().filter(|sink| !sink.empty()) {
             if !sink.is_paused() {
                 sink.pause();
-                *self.state.lock().unwrap() = State::Paused;
             } else {
                 sink.play();
-                *self.state.lock().unwrap() = State::Playing;
             }
         }
         Ok(())
     }
 
     pub fn play(&self) {
-        if let Some(sink) = &*self.sink.lock().unwrap() {
+        if let Some(sink) = self.opened_sink() {
             sink.play();
-            *self.state.lock().unwrap() = State::Playing;
         }
     }
 
     pub fn is_playing(&self) -> bool {
-        matches!(*self.state.lock().unwrap(), State::Playing)
+        self.opened_sink()
+            .is_some_and(|sink| !sink.empty() && !sink.is_paused())
     }
 
     pub fn is_paused(&self) -> bool {
-        matches!(*self.state.lock().unwrap(), State::Paused)
+        self.opened_sink()
+            .is_some_and(|sink| !sink.empty() && sink.is_paused())
     }
 
+    // Drops queued blocks that haven't started playing yet and returns the first of them,
+    // the audible block plays on until its end
+    pub fn discard_upcoming(&self) -> Option<u32> {
+        self.queue.discard_upcoming()
+    }
+
+    // Drops everything queued, the output itself stays open
     pub fn stop(&self) {
-        if let Some(sink) = &*self.sink.lock().unwrap() {
-            sink.stop();
+        if let Some(sink) = self.opened_sink() {
+            sink.clear();
+            // Clearing pauses the sink, the next sound should play right away
+            sink.play();
         }
-        self.clean();
+        self.queue.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(sample_rate: u32, len: usize) -> SamplesBuffer<f32> {
+        let samples = (0..len)
+            .map(|i| (i % 100) as f32 / 100.0)
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(1, sample_rate, samples)
+    }
+
+    #[test]
+    fn test_parse_audio_output() {
+        assert_eq!("device".parse(), Ok(AudioOutput::Device));
+        assert_eq!("null".parse(), Ok(AudioOutput::Null(Pacing::RealTime)));
+        assert_eq!(
+            "null-instant".parse(),
+            Ok(AudioOutput::Null(Pacing::Instant))
+        );
+        assert_eq!(
+            "wav:/tmp/out.wav".parse(),
+            Ok(AudioOutput::Wav(PathBuf::from("/tmp/out.wav")))
+        );
+        assert!("wav:".parse::<AudioOutput>().is_err());
+        assert!("speakers".parse::<AudioOutput>().is_err());
+    }
+
+    #[test]
+    fn test_audio_output_roundtrip() {
+        for output in [
+            AudioOutput::Device,
+            AudioOutput::Null(Pacing::RealTime),
+            AudioOutput::Null(Pacing::Instant),
+            AudioOutput::Wav(PathBuf::from("/tmp/out.wav")),
+        ] {
+            assert_eq!(output.to_string().parse(), Ok(output));
+        }
+    }
+
+    #[test]
+    fn test_null_output_real_time() {
+        let player = AudioPlayer::new(AudioOutput::Null(Pacing::RealTime));
+
+        let start = Instant::now();
+        player.play_audio(tone(8000, 1600)).unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        assert!(!player.is_playing());
+    }
+
+    #[test]
+    fn test_null_output_instant() {
+        let player = AudioPlayer::new(AudioOutput::Null(Pacing::Instant));
+
+        let start = Instant::now();
+        player.play_audio(tone(8000, 8000 * 60)).unwrap();
+
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_wav_output_appends_every_playback() {
+        let path = std::env::temp_dir().join(format!("fox-reader-{}.wav", std::process::id()));
+        let player = AudioPlayer::new(AudioOutput::Wav(path.clone()));
+
+        player.play_audio(tone(8000, 1000)).unwrap();
+        player.play_audio(tone(8000, 500)).unwrap();
+
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().sample_rate, 8000);
+        assert_eq!(reader.spec().channels, 1);
+
+        let samples = reader
+            .into_samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let expected = tone(8000, 1000).chain(tone(8000, 500)).collect::<Vec<_>>();
+        assert_eq!(samples, expected);
+
+        assert!(player.play_audio(tone(16000, 100)).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_queued_blocks_play_back_to_back() {
+        let player = AudioPlayer::new(AudioOutput::Null(Pacing::RealTime));
+        let mut audible = player.subscribe_audible();
+
+        player.enqueue(0, tone(8000, 800), Vec::new()).unwrap();
+        player.enqueue(1, tone(8000, 800), Vec::new()).unwrap();
+
+        let mut changes = Vec::new();
+        while audible.changed().await.is_ok() {
+            let block = audible.borrow_and_update().map(|p| p.block_id);
+            changes.push(block);
+            if block.is_none() {
+                break;
+            }
+        }
+
+        assert_eq!(changes, vec![Some(0), Some(1), None]);
+    }
+
+    #[tokio::test]
+    async fn test_marks_published_as_played() {
+        let player = AudioPlayer::new(AudioOutput::Null(Pacing::RealTime));
+        let mut audible = player.subscribe_audible();
+
+        player.enqueue(3, tone(8000, 1600), vec![0, 800]).unwrap();
+
+        let mut marks = Vec::new();
+        while audible.changed().await.is_ok() {
+            match *audible.borrow_and_update() {
+                Some(position) => {
+                    assert_eq!(position.block_id, 3);
+                    marks.push(position.mark);
+                }
+                None => break,
+            }
+        }
+
+        assert_eq!(marks.last(), Some(&Some(1)));
+        assert!(marks.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_pause_and_stop_queue() {
+        let player = AudioPlayer::new(AudioOutput::Null(Pacing::RealTime));
+        assert!(!player.is_playing());
+
+        player
+            .enqueue(0, tone(8000, 8000 * 10), Vec::new())
+            .unwrap();
+        assert!(player.is_playing());
+
+        player.pause().unwrap();
+        assert!(player.is_paused());
+
+        player.stop();
+        assert!(!player.is_playing());
+        assert!(!player.is_paused());
+        assert_eq!(*player.subscribe_audible().borrow(), None);
+    }
+
+    #[tokio::test]
+    async fn test_discard_upcoming_keeps_audible_block() {
+        let player = AudioPlayer::new(AudioOutput::Null(Pacing::RealTime));
+        let mut audible = player.subscribe_audible();
+
+        player.enqueue(0, tone(8000, 800), Vec::new()).unwrap();
+        player.enqueue(1, tone(8000, 800), Vec::new()).unwrap();
+        player.enqueue(2, tone(8000, 800), Vec::new()).unwrap();
+
+        audible
+            .wait_for(|position| position.is_some())
+            .await
+            .unwrap();
+        assert_eq!(player.discard_upcoming(), Some(1));
+        assert_eq!(player.discard_upcoming(), None);
+
+        let mut changes = vec![audible.borrow_and_update().map(|p| p.block_id)];
+        while audible.changed().await.is_ok() {
+            let block = audible.borrow_and_update().map(|p| p.block_id);
+            changes.push(block);
+            if block.is_none() {
+                break;
+            }
+        }
+
+        assert_eq!(changes, vec![Some(0), None]);
+    }
+
+    #[test]
+    fn test_wav_output_records_effects() {
+        let path =
+            std::env::temp_dir().join(format!("fox-reader-effects-{}.wav", std::process::id()));
+        let player = AudioPlayer::new(AudioOutput::Wav(path.clone()));
+        player.set_volume(0.5);
+        player.set_normalize(true);
+
+        player.play_audio(tone(8000, 1000)).unwrap();
+        drop(player);
+
+        let samples = hound::WavReader::open(&path)
+            .unwrap()
+            .into_samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let effects = AudioEffects {
+            pitch: 0.0,
+            normalize: true,
+        };
+        let expected = effects
+            .apply(tone(8000, 1000))
+            .map(|s| s * 0.5)
+            .collect::<Vec<_>>();
+        assert_eq!(samples, expected);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_volume_and_pitch_are_clamped() {
+        let player = AudioPlayer::new(AudioOutput::Null(Pacing::Instant));
+
+        player.set_volume(5.0);
+        player.set_pitch(-30.0);
+
+        assert_eq!(player.volume(), 2.0);
+        assert_eq!(player.effects().pitch, -12.0);
+    }
+
+    #[tokio::test]
+    async fn test_wav_output_appends_queued_blocks() {
+        let path =
+            std::env::temp_dir().join(format!("fox-reader-queue-{}.wav", std::process::id()));
+        let player = AudioPlayer::new(AudioOutput::Wav(path.clone()));
+        let mut audible = player.subscribe_audible();
+
+        player.enqueue(0, tone(8000, 1000), Vec::new()).unwrap();
+        player.enqueue(1, tone(8000, 500), Vec::new()).unwrap();
+        while audible.changed().await.is_ok() && audible.borrow_and_update().is_some() {}
+        drop(player);
+
+        let samples = hound::WavReader::open(&path)
+            .unwrap()
+            .into_samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let expected = tone(8000, 1000).chain(tone(8000, 500)).collect::<Vec<_>>();
+        assert_eq!(samples, expected);
+
+        std::fs::remove_file(path).unwrap();
     }
 }
@@ -0,0 +1,103 @@
+use std::cmp::Reverse;
+use whatlang::{Detector, Lang};
+
+use crate::core::voice_manager::{grade_rank, VoiceFilter, VoiceManager};
+
+// Headings, names and short quotes don't tell their language reliably
+const MIN_DETECTION_CHARS: usize = 20;
+
+// Languages the voices speak, with the language part of their locales
+const VOICE_LANGUAGES: [(Lang, &str); 8] = [
+    (Lang::Eng, "en"),
+    (Lang::Jpn, "ja"),
+    (Lang::Cmn, "zh"),
+    (Lang::Spa, "es"),
+    (Lang::Fra, "fr"),
+    (Lang::Hin, "hi"),
+    (Lang::Ita, "it"),
+    (Lang::Por, "pt"),
+];
+
+// Language of the text, like fr or en, when the detection is reliable, as reading a block
+// with a voice of the wrong language is worse than reading it with an accent. Only languages
+// the voices speak are told apart
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    if text.chars().filter(|c| c.is_alphabetic()).count() < MIN_DETECTION_CHARS {
+        return None;
+    }
+    let detector = Detector::with_allowlist(VOICE_LANGUAGES.map(|(lang, _)| lang).to_vec());
+    let lang = detector
+        .detect(text)
+        .filter(|info| info.is_reliable())?
+        .lang();
+    VOICE_LANGUAGES
+        .iter()
+        .find(|(known, _)| *known == lang)
+        .map(|(_, language)| *language)
+}
+
+// The voice itself when it speaks the language of the text, otherwise the best graded voice
+// of that language, of the same gender when there is one
+pub fn voice_for_text(voice: &str, text: &str) -> String {
+    let voices = VoiceManager::get_voice_rows();
+    let (Some(language), Some(current)) = (
+        detect_language(text),
+        voices.iter().find(|row| row.key == voice),
+    ) else {
+        return voice.to_string();
+    };
+
+    let filter = VoiceFilter {
+        language: Some(language.to_string()),
+        ..Default::default()
+    };
+    if filter.matches(current) {
+        return voice.to_string();
+    }
+    let gender = VoiceManager::get_voice_gender(voice);
+    voices
+        .iter()
+        .filter(|row| filter.matches(row))
+        .min_by_key(|row| {
+            (
+                VoiceManager::get_voice_gender(&row.key) != gender,
+                Reverse(grade_rank(&row.quality)),
+            )
+        })
+        .map_or_else(|| voice.to_string(), |row| row.key.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRENCH: &str = "Le renard brun saute par-dessus le chien paresseux dans le jardin.";
+    const ENGLISH: &str = "It was the best of times, it was the worst of times.";
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(detect_language(FRENCH), Some("fr"));
+        assert_eq!(detect_language(ENGLISH), Some("en"));
+        assert_eq!(
+            detect_language("こんにちは、今日はとても良い天気ですね。散歩に行きましょう。"),
+            Some("ja")
+        );
+        assert_eq!(detect_language("Chapter 1"), None);
+    }
+
+    #[test]
+    fn test_voice_follows_text_language() {
+        assert_eq!(voice_for_text("af_heart", FRENCH), "ff_siwis");
+        assert_eq!(voice_for_text("bf_emma", ENGLISH), "bf_emma");
+        // A voice of the same gender is preferred
+        assert_eq!(
+            voice_for_text(
+                "am_adam",
+                "Oggi andiamo al mare con gli amici e mangiamo una pizza."
+            ),
+            "im_nicola"
+        );
+        // Voices of other engines are kept
+        assert_eq!(voice_for_text("mock_voice", FRENCH), "mock_voice");
+    }
+}
@@ -1,14 +1,17 @@
+pub mod audio_effects;
 pub mod audio_player;
 pub mod debouncer;
 pub mod espeak_handler;
 pub mod file_handler;
 pub mod highlighter;
 pub mod kokoros_downloader;
+pub mod language_detector;
 pub mod markdown;
 pub mod pdf_highlighter;
 pub mod pdfium;
 pub mod progress_tracker;
 pub mod schema_handler;
+pub mod ssml;
 pub mod text;
 pub mod text_highlighter;
 pub mod whisper_downloader;
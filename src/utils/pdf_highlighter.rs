@@ -1,10 +1,16 @@
 use pdfium_render::prelude::{
-    PdfPage, PdfPageObjectCommon, PdfPageObjectsCommon, PdfPageTextObject, PdfPoints,
+    PdfDocument, PdfPage, PdfPageObjectCommon, PdfPageObjectsCommon, PdfPageTextObject, PdfPoints,
     PdfQuadPoints, PdfRect, PdfSearchDirection, PdfSearchOptions,
 };
 
-use crate::utils::highlighter::ReadingBlock;
-use std::{collections::BTreeMap, error::Error};
+use crate::{
+    core::audiobook::Chapter,
+    utils::{
+        highlighter::ReadingBlock,
+        pdfium::{OutlineEntry, PageRange},
+    },
+};
+use std::{collections::BTreeMap, error::Error, ops::Range};
 
 #[derive(Debug, Clone)]
 pub struct PdfReadingBlock {
@@ -35,6 +41,7 @@ pub struct PdfHighlighter {
     pub blocks_generated_for: Option<u16>,
     pub current_blocks: Vec<PdfReadingBlock>,
     pub highlighted_block: Option<u32>,
+    pub highlighted_word: Option<Range<usize>>,
 }
 
 impl Default for PdfHighlighter {
@@ -52,6 +59,7 @@ impl PdfHighlighter {
             blocks_generated_for: None,
             current_blocks: Vec::new(),
             highlighted_block: None,
+            highlighted_word: None,
         }
     }
 
@@ -102,6 +110,38 @@ impl PdfHighlighter {
         Ok(())
     }
 
+    // Reading blocks of the pages in range for audiobook export, split into chapters at the
+    // outline entries or one chapter per page without an outline. Pages out of range are
+    // left empty, so a range starting mid chapter still gets that chapter's title
+    pub fn generate_document_chapters(
+        document: &PdfDocument,
+        outline: &[OutlineEntry],
+        pages: PageRange,
+    ) -> Vec<Chapter> {
+        let mut highlighter = Self::new();
+
+        let pages = document
+            .pages()
+            .iter()
+            .enumerate()
+            .map(|(page_idx, page)| {
+                if !pages.contains(page_idx as u16) {
+                    return Vec::new();
+                }
+                match highlighter.generate_reading_blocks(&page, page_idx as u16) {
+                    Ok(()) => highlighter
+                        .get_reading_blocks()
+                        .into_iter()
+                        .map(|block| block.text)
+                        .collect(),
+                    Err(_) => Vec::new(),
+                }
+            })
+            .collect();
+
+        Chapter::from_pages(pages, outline)
+    }
+
     // This is the main process function, it's job is to split the text into reading blocks
     // that later will be used to highlight the text and also being read by the tts engine
     fn process_text_into_blocks(
@@ -365,6 +405,14 @@ impl PdfHighlighter {
         self.highlighted_block = Some(block_id);
     }
 
+    // `char_range` is relative to the text of the block
+    pub fn highlight_word(&mut self, block_id: u32, char_range: Range<usize>) {
+        if self.highlighted_block != Some(block_id) {
+            self.highlight(block_id);
+        }
+        self.highlighted_word = Some(char_range);
+    }
+
     pub fn get_reading_blocks(&self) -> Vec<PdfReadingBlock> {
         self.current_blocks.to_vec()
     }
@@ -379,7 +427,47 @@ impl PdfHighlighter {
         None
     }
 
+    // Words have no bounds of their own, so the word is placed along the rectangles of its
+    // block proportionally to where it is in the block text
+    pub fn get_highlighted_word_rectangles(&self) -> Vec<PdfRect> {
+        let (Some(block), Some(char_range)) =
+            (self.get_highlighted_block(), self.highlighted_word.as_ref())
+        else {
+            return Vec::new();
+        };
+
+        let char_count = block.text.chars().count();
+        let total_width: f32 = block.rectangles.iter().map(|r| r.width().value).sum();
+        if char_count == 0 || total_width <= 0.0 {
+            return Vec::new();
+        }
+
+        let word_start = total_width * char_range.start.min(char_count) as f32 / char_count as f32;
+        let word_end = total_width * char_range.end.min(char_count) as f32 / char_count as f32;
+
+        let mut rectangles = Vec::new();
+        let mut rect_start = 0.0;
+        for rect in block.rectangles.iter() {
+            let rect_end = rect_start + rect.width().value;
+            let left = word_start.max(rect_start) - rect_start;
+            let right = word_end.min(rect_end) - rect_start;
+
+            if right > left {
+                rectangles.push(PdfRect::new(
+                    rect.bottom(),
+                    PdfPoints::new(rect.left().value + left),
+                    rect.top(),
+                    PdfPoints::new(rect.left().value + right),
+                ));
+            }
+            rect_start = rect_end;
+        }
+
+        rectangles
+    }
+
     pub fn clear_highlight(&mut self) {
         self.highlighted_block = None;
+        self.highlighted_word = None;
     }
 }
@@ -3,6 +3,7 @@ use std::{
     fmt::Debug,
     fs::{self, create_dir_all},
     path::Path,
+    str::FromStr,
 };
 
 use flate2::bufread::GzDecoder;
@@ -11,6 +12,61 @@ use tar::Archive;
 
 use crate::{core::runtime::runtime, paths::get_pdfium_path};
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineEntry {
+    pub title: String,
+    pub page: PdfPageIndex,
+}
+
+// Pages picked by a range of page numbers counted from 1, like "3-10", "3", "3-" or "-10".
+// Indexes are kept zero based, the default is the whole document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PageRange {
+    first: PdfPageIndex,
+    last: Option<PdfPageIndex>,
+}
+
+impl PageRange {
+    pub fn first(&self) -> PdfPageIndex {
+        self.first
+    }
+
+    pub fn contains(&self, page: PdfPageIndex) -> bool {
+        page >= self.first && self.last.is_none_or(|last| page <= last)
+    }
+}
+
+impl FromStr for PageRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid page range '{}', expected pages like 3-10", s);
+        let page_index = |number: &str| match number.trim() {
+            "" => Ok(None),
+            number => match number.parse::<PdfPageIndex>() {
+                Ok(page) if page > 0 => Ok(Some(page - 1)),
+                _ => Err(invalid()),
+            },
+        };
+
+        let (first, last) = match s.split_once('-') {
+            Some((first, last)) => (page_index(first)?, page_index(last)?),
+            None => {
+                let page = page_index(s)?.ok_or_else(invalid)?;
+                (Some(page), Some(page))
+            }
+        };
+        let range = PageRange {
+            first: first.unwrap_or_default(),
+            last,
+        };
+        if range.last.is_some_and(|last| last < range.first) {
+            return Err(invalid());
+        }
+        Ok(range)
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct PdfiumWrapper {
     pdfium: Option<Pdfium>,
@@ -88,7 +144,76 @@ impl PdfiumWrapper {
         self.document.as_ref()
     }
 
+    // Blank tags are treated as missing, many PDFs carry empty ones
+    pub fn get_metadata_tag(&self, tag: PdfDocumentMetadataTagType) -> Option<String> {
+        let value = self
+            .document
+            .as_ref()?
+            .metadata()
+            .get(tag)?
+            .value()
+            .trim()
+            .to_string();
+        (!value.is_empty()).then_some(value)
+    }
+
+    // Bookmarks ordered by the page they point at. Chapters can't start mid page, so
+    // only the first bookmark of a page is kept, which is the outermost one
+    pub fn get_outline(&self) -> Vec<OutlineEntry> {
+        let Some(document) = self.document.as_ref() else {
+            return Vec::new();
+        };
+
+        let mut outline = document
+            .bookmarks()
+            .iter()
+            .filter_map(|bookmark| {
+                let title = bookmark.title()?.trim().to_string();
+                let page = match bookmark.destination() {
+                    Some(destination) => destination.page_index().ok()?,
+                    None => bookmark
+                        .action()?
+                        .as_local_destination_action()?
+                        .destination()
+                        .ok()?
+                        .page_index()
+                        .ok()?,
+                };
+                (!title.is_empty()).then_some(OutlineEntry { title, page })
+            })
+            .collect::<Vec<_>>();
+
+        outline.sort_by_key(|entry| entry.page);
+        outline.dedup_by_key(|entry| entry.page);
+        outline
+    }
+
     pub fn remove_pdf(&mut self) {
         self.document = None;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_range_parsing() {
+        let range = "3-10".parse::<PageRange>().unwrap();
+        assert_eq!(range.first(), 2);
+        assert!(!range.contains(1));
+        assert!(range.contains(2) && range.contains(9));
+        assert!(!range.contains(10));
+
+        let single = "5".parse::<PageRange>().unwrap();
+        assert!(single.contains(4) && !single.contains(3) && !single.contains(5));
+
+        assert!("4-".parse::<PageRange>().unwrap().contains(1000));
+        assert_eq!("-10".parse::<PageRange>().unwrap().first(), 0);
+        assert_eq!("-".parse::<PageRange>(), Ok(PageRange::default()));
+
+        for invalid in ["", "0", "10-3", "a-b", "1-2-3"] {
+            assert!(invalid.parse::<PageRange>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use regex::Regex;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+// Declarations and comments like <?xml ...?> are matched to be dropped
+static TAG_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(/?)\s*([\w:-]+)([^>]*?)(/?)\s*>|<[?!][^>]*>").unwrap());
+static ATTRIBUTE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"([\w:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+
+// What a speech engine has to do for a piece of SSML, in reading order
+#[derive(Debug, Clone, PartialEq)]
+pub enum SsmlPart {
+    // Text to read, at `rate` times the requested speed
+    Text { text: String, rate: f32 },
+    Break(Duration),
+    // Position the sender wants to hear back about once everything before it is read
+    Mark(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SayAs {
+    Text,
+    // Every character is read on its own, like for spelled out words or digit strings
+    Characters,
+}
+
+// Reads <mark>, <break>, <prosody rate> and <say-as>, the text of other elements is kept
+// and their tags are dropped. Text without tags comes back as a single part
+pub fn parse_ssml(message: &str) -> Vec<SsmlPart> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    // Open <prosody> and <say-as> elements, closing tags pop their own kind
+    let mut rates = vec![1.0];
+    let mut say_as = vec![SayAs::Text];
+    let mut last_end = 0;
+
+    for tag in TAG_REGEX.captures_iter(message) {
+        let whole = tag.get(0).unwrap();
+        append_text(
+            &mut text,
+            &message[last_end..whole.start()],
+            say_as_of(&say_as),
+        );
+        last_end = whole.end();
+
+        let group = |i| tag.get(i).map_or("", |group| group.as_str());
+        let is_closing = !group(1).is_empty();
+        let is_empty_element = !group(4).is_empty();
+        let attributes = group(3);
+        let rate = *rates.last().unwrap();
+
+        match (group(2).to_lowercase().as_str(), is_closing) {
+            ("mark", false) => {
+                if let Some(name) = attribute(attributes, "name") {
+                    push_text(&mut parts, &mut text, rate);
+                    parts.push(SsmlPart::Mark(name));
+                }
+            }
+            ("break", false) => {
+                push_text(&mut parts, &mut text, rate);
+                let pause = attribute(attributes, "time")
+                    .and_then(|time| parse_time(&time))
+                    .unwrap_or_else(|| strength_pause(attribute(attributes, "strength")));
+                if !pause.is_zero() {
+                    parts.push(SsmlPart::Break(pause));
+                }
+            }
+            ("prosody", false) if !is_empty_element => {
+                push_text(&mut parts, &mut text, rate);
+                let relative = attribute(attributes, "rate")
+                    .and_then(|value| parse_rate(&value))
+                    .unwrap_or(1.0);
+                rates.push(rate * relative);
+            }
+            ("prosody", true) if rates.len() > 1 => {
+                push_text(&mut parts, &mut text, rate);
+                rates.pop();
+            }
+            ("say-as", false) if !is_empty_element => {
+                say_as.push(match attribute(attributes, "interpret-as").as_deref() {
+                    Some("characters" | "spell-out" | "letters" | "digits") => SayAs::Characters,
+                    _ => SayAs::Text,
+                });
+            }
+            ("say-as", true) if say_as.len() > 1 => {
+                say_as.pop();
+            }
+            // Tags like </s> and <p> separate words
+            _ => text.push(' '),
+        }
+    }
+    append_text(&mut text, &message[last_end..], say_as_of(&say_as));
+    push_text(&mut parts, &mut text, *rates.last().unwrap());
+    parts
+}
+
+fn say_as_of(say_as: &[SayAs]) -> SayAs {
+    *say_as.last().unwrap()
+}
+
+fn append_text(text: &mut String, raw: &str, say_as: SayAs) {
+    let decoded = decode_entities(raw);
+    match say_as {
+        SayAs::Text => text.push_str(&decoded),
+        SayAs::Characters => {
+            let characters = decoded
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .map(String::from)
+                .collect::<Vec<_>>();
+            text.push(' ');
+            text.push_str(&characters.join(" "));
+        }
+    }
+}
+
+fn push_text(parts: &mut Vec<SsmlPart>, text: &mut String, rate: f32) {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    text.clear();
+    if collapsed.is_empty() {
+        return;
+    }
+    // Text split by dropped tags only is read as one part
+    if let Some(SsmlPart::Text {
+        text: previous,
+        rate: previous_rate,
+    }) = parts.last_mut()
+    {
+        if *previous_rate == rate {
+            previous.push(' ');
+            previous.push_str(&collapsed);
+            return;
+        }
+    }
+    parts.push(SsmlPart::Text {
+        text: collapsed,
+        rate,
+    });
+}
+
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let attribute = ATTRIBUTE_REGEX
+        .captures_iter(attributes)
+        .find(|attribute| attribute[1].eq_ignore_ascii_case(name))?;
+    let value = attribute.get(2).or(attribute.get(3))?;
+    Some(decode_entities(value.as_str()))
+}
+
+// Named references and numeric ones like &#39; or &#x2019;, anything else is kept as written
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .and_then(|end| Some((entity_char(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn entity_char(name: &str) -> Option<char> {
+    let code = match name {
+        "lt" => return Some('<'),
+        "gt" => return Some('>'),
+        "quot" => return Some('"'),
+        "apos" => return Some('\''),
+        "amp" => return Some('&'),
+        _ => {
+            let number = name.strip_prefix('#')?;
+            match number.strip_prefix(['x', 'X']) {
+                Some(hex) if hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                    u32::from_str_radix(hex, 16).ok()?
+                }
+                None if number.chars().all(|c| c.is_ascii_digit()) => number.parse().ok()?,
+                _ => return None,
+            }
+        }
+    };
+    char::from_u32(code)
+}
+
+// Times like 500ms or 1.5s
+fn parse_time(time: &str) -> Option<Duration> {
+    let time = time.trim();
+    let seconds = match time.strip_suffix("ms") {
+        Some(millis) => millis.trim().parse::<f64>().ok()? / 1000.0,
+        None => time.strip_suffix('s')?.trim().parse::<f64>().ok()?,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+// Breaks without a time default to a medium pause, as after a comma
+fn strength_pause(strength: Option<String>) -> Duration {
+    let millis = match strength.as_deref() {
+        Some("none") => 0,
+        Some("x-weak") => 100,
+        Some("weak") => 200,
+        Some("strong") => 700,
+        Some("x-strong") => 1000,
+        _ => 400,
+    };
+    Duration::from_millis(millis)
+}
+
+// Named rates, percentages of the current rate like 150% or +20%, or plain factors like 1.2
+fn parse_rate(rate: &str) -> Option<f32> {
+    let rate = rate.trim();
+    let named = match rate {
+        "x-slow" => Some(0.5),
+        "slow" => Some(0.75),
+        "medium" | "default" => Some(1.0),
+        "fast" => Some(1.25),
+        "x-fast" => Some(1.5),
+        _ => None,
+    };
+    if named.is_some() {
+        return named;
+    }
+
+    let factor = match rate.strip_suffix('%') {
+        Some(percent) if percent.starts_with(['+', '-']) => {
+            1.0 + percent.parse::<f32>().ok()? / 100.0
+        }
+        Some(percent) => percent.parse::<f32>().ok()? / 100.0,
+        None => rate.parse::<f32>().ok()?,
+    };
+    Some(factor).filter(|factor| *factor > 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str, rate: f32) -> SsmlPart {
+        SsmlPart::Text {
+            text: text.to_string(),
+            rate,
+        }
+    }
+
+    #[test]
+    fn test_marks_and_plain_text() {
+        assert_eq!(
+            parse_ssml(
+                r#"<?xml version="1.0"?><speak><mark name="0"/>Fish &amp; chips<s>please.</s><mark name='1'/></speak>"#
+            ),
+            vec![
+                SsmlPart::Mark("0".to_string()),
+                text("Fish & chips please.", 1.0),
+                SsmlPart::Mark("1".to_string()),
+            ]
+        );
+        assert_eq!(parse_ssml("Plain text"), vec![text("Plain text", 1.0)]);
+    }
+
+    #[test]
+    fn test_entities() {
+        assert_eq!(
+            decode_entities("It&#39;s &#8217;quoted&#x2019; &#X41;&lt;&amp;lt;"),
+            "It's \u{2019}quoted\u{2019} A<&lt;"
+        );
+        // Unknown, broken and out of range references stay as they are
+        assert_eq!(
+            decode_entities("&nbsp; & &#; &#x; &#xD800; &#12a; &#1114112; AT&T"),
+            "&nbsp; & &#; &#x; &#xD800; &#12a; &#1114112; AT&T"
+        );
+        assert_eq!(
+            parse_ssml(
+                "<speak>Don&#x27;t <say-as interpret-as=\"characters\">&#65;B</say-as></speak>"
+            ),
+            vec![text("Don't A B", 1.0)]
+        );
+    }
+
+    #[test]
+    fn test_breaks() {
+        assert_eq!(
+            parse_ssml(
+                r#"One<break time="500ms"/>two<break time="1.5s"/>three<break/>four<break strength="none"/>five"#
+            ),
+            vec![
+                text("One", 1.0),
+                SsmlPart::Break(Duration::from_millis(500)),
+                text("two", 1.0),
+                SsmlPart::Break(Duration::from_millis(1500)),
+                text("three", 1.0),
+                SsmlPart::Break(Duration::from_millis(400)),
+                text("four five", 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prosody_rate() {
+        assert_eq!(
+            parse_ssml(
+                r#"Normal <prosody rate="150%">fast <prosody rate="slow">nested</prosody></prosody> normal <prosody rate="-50%">slow</prosody>"#
+            ),
+            vec![
+                text("Normal", 1.0),
+                text("fast", 1.5),
+                text("nested", 1.125),
+                text("normal", 1.0),
+                text("slow", 0.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_say_as() {
+        assert_eq!(
+            parse_ssml(
+                r#"Code <say-as interpret-as="characters">AB12</say-as>, on <say-as interpret-as="date">2024-01-02</say-as>"#
+            ),
+            vec![text("Code A B 1 2, on 2024-01-02", 1.0)]
+        );
+    }
+}
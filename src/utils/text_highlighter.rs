@@ -1,10 +1,11 @@
-use std::{borrow::Borrow, collections::BTreeMap};
+use std::{borrow::Borrow, collections::BTreeMap, ops::Range};
 
 use gtk::prelude::*;
 
 use super::highlighter::ReadingBlock;
 
 const HIGHLIGHTED_TAG: &str = "highlighted";
+const WORD_HIGHLIGHTED_TAG: &str = "word-highlighted";
 
 #[derive(Debug, Clone)]
 pub struct TextReadingBlock {
@@ -28,6 +29,7 @@ impl ReadingBlock for TextReadingBlock {
 pub struct TextHighlighter {
     buffer: gtk::TextBuffer,
     highlight_tag: gtk::TextTag,
+    word_highlight_tag: gtk::TextTag,
     min_block_len: i32,
     current_blocks: Vec<TextReadingBlock>,
 }
@@ -38,11 +40,18 @@ impl TextHighlighter {
             .create_tag(Some(HIGHLIGHTED_TAG), &[])
             .expect("Failed to create tag");
 
+        // Created after the block tag so it takes priority over it
+        let word_highlight_tag = buffer
+            .create_tag(Some(WORD_HIGHLIGHTED_TAG), &[])
+            .expect("Failed to create tag");
+
         highlight_tag.set_background_rgba(Some(&initial_color));
+        word_highlight_tag.set_background_rgba(Some(&Self::word_color(&initial_color)));
 
         Self {
             buffer,
             highlight_tag,
+            word_highlight_tag,
             min_block_len,
             current_blocks: Vec::new(),
         }
@@ -50,6 +59,18 @@ impl TextHighlighter {
 
     pub fn set_highlight_color(&mut self, rgba: gtk::gdk::RGBA) {
         self.highlight_tag.set_background_rgba(Some(&rgba));
+        self.word_highlight_tag
+            .set_background_rgba(Some(&Self::word_color(&rgba)));
+    }
+
+    // Same color as the block, just more opaque so the word stands out within it
+    fn word_color(rgba: &gtk::gdk::RGBA) -> gtk::gdk::RGBA {
+        gtk::gdk::RGBA::new(
+            rgba.red(),
+            rgba.green(),
+            rgba.blue(),
+            (rgba.alpha() * 2.0).min(1.0),
+        )
     }
 
     pub fn is_buffer_empty(&self) -> bool {
@@ -215,12 +236,36 @@ impl TextHighlighter {
         }
     }
 
+    // `char_range` is relative to the text of the block
+    pub fn highlight_word(&self, block_id: u32, char_range: Range<usize>) {
+        let blocks = self.get_reading_blocks();
+        if let Some(block) = blocks.iter().find(|b| b.id == block_id) {
+            self.clear_word();
+            let start = (block.start_offset + char_range.start as i32).min(block.end_offset);
+            let end = (block.start_offset + char_range.end as i32).min(block.end_offset);
+            self.buffer.apply_tag(
+                &self.word_highlight_tag,
+                &self.buffer.iter_at_offset(start),
+                &self.buffer.iter_at_offset(end),
+            );
+        }
+    }
+
     pub fn clear(&self) {
         self.buffer.remove_tag(
             &self.highlight_tag,
             &self.buffer.start_iter(),
             &self.buffer.end_iter(),
         );
+        self.clear_word();
+    }
+
+    fn clear_word(&self) {
+        self.buffer.remove_tag(
+            &self.word_highlight_tag,
+            &self.buffer.start_iter(),
+            &self.buffer.end_iter(),
+        );
     }
 }
 
@@ -262,6 +307,32 @@ mod tests {
         assert_eq!(middle.tags(), Vec::<gtk::TextTag>::new());
     }
 
+    #[gtk::test]
+    fn test_highlight_word() {
+        let mut highlighter = create_test_highlighter("First sentence. Second sentence.");
+        highlighter.update_reading_blocks(vec![TextReadingBlock {
+            id: 1,
+            text: "Second sentence.".to_string(),
+            start_offset: 16,
+            end_offset: 32,
+        }]);
+
+        highlighter.highlight(1);
+        highlighter.highlight_word(1, 7..16);
+
+        let word_tag = &highlighter.word_highlight_tag;
+        assert!(!highlighter.buffer.iter_at_offset(22).has_tag(word_tag));
+        assert!(highlighter.buffer.iter_at_offset(23).has_tag(word_tag));
+        assert!(highlighter.buffer.iter_at_offset(31).has_tag(word_tag));
+
+        highlighter.highlight_word(1, 0..6);
+        assert!(highlighter.buffer.iter_at_offset(16).has_tag(word_tag));
+        assert!(!highlighter.buffer.iter_at_offset(23).has_tag(word_tag));
+
+        highlighter.clear();
+        assert!(!highlighter.buffer.iter_at_offset(16).has_tag(word_tag));
+    }
+
     #[gtk::test]
     fn test_split_text_into_sentences() {
         let text = "First sentence. Second sentence! Third sentence? Fourth sentence.";